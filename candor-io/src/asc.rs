@@ -0,0 +1,344 @@
+//! Vector ASCII trace files (.asc)
+
+use crate::{dlc_length, replay::Replay, seconds_ns, SourceError};
+use candor::{Kind, Packet};
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// Replays of the channels of a trace, in order of appearance, with
+/// consecutive indices starting at `index`
+pub fn open(
+    name: &str,
+    index: usize,
+    default_baud: u32,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+) -> Result<Vec<Replay>, Box<dyn Error>> {
+    let trace = AscParser::new_from_file(name, index)?;
+    Ok(Replay::split(
+        name,
+        index,
+        default_baud,
+        trace.packets,
+        &trace.channels,
+        tx,
+        errors,
+    ))
+}
+
+/// Packets of an ASC trace, with times relative to the start of the
+/// measurement
+pub struct AscParser {
+    packets: Vec<Packet>,
+    channels: Vec<String>,
+    hex: bool,
+    relative: bool,
+}
+
+impl AscParser {
+    pub fn new_from_file(
+        filename: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(filename)?;
+        let lines = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        Self::new_from_lines(lines, index)
+    }
+
+    pub fn new_from_text(
+        text: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_from_lines(
+            text.lines().map(|s| s.to_string()).collect(),
+            index,
+        )
+    }
+
+    /// Parse trace lines, with the packets of each channel using the next
+    /// source index starting at `index`
+    pub fn new_from_lines(
+        lines: Vec<String>,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut trace = Self {
+            packets: Vec::with_capacity(lines.len()),
+            channels: vec![],
+            hex: true,
+            relative: false,
+        };
+        let start_time = Instant::now();
+        let mut time_ns: u64 = 0;
+
+        for (number, line) in lines.iter().enumerate() {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.is_empty() || cols[0].starts_with("//") {
+                continue;
+            }
+            let invalid = || format!("Invalid line {}: {line}", number + 1);
+
+            // header
+            if cols[0] == "base" {
+                trace.hex = match cols.get(1) {
+                    Some(&"hex") => true,
+                    Some(&"dec") => false,
+                    _ => return Err(invalid().into()),
+                };
+                if cols.get(2) == Some(&"timestamps") {
+                    trace.relative = match cols.get(3) {
+                        Some(&"absolute") => false,
+                        Some(&"relative") => true,
+                        _ => return Err(invalid().into()),
+                    };
+                }
+                continue;
+            }
+
+            // events start with their time in seconds
+            let Some(time) = seconds_ns(cols[0]) else {
+                continue;
+            };
+            time_ns = match trace.relative {
+                true => time_ns.checked_add(time).ok_or_else(invalid)?,
+                false => time,
+            };
+            let Some(mut packet) = trace.parse_event(&cols, invalid)? else {
+                continue;
+            };
+            packet.source += index;
+            let time = start_time.checked_add(Duration::from_nanos(time_ns));
+            packet.time = Some(time.ok_or_else(invalid)?);
+            trace.packets.push(packet);
+        }
+
+        Ok(trace)
+    }
+
+    /// Parse a CAN event line, with the source as channel position,
+    /// ignoring other events
+    fn parse_event(
+        &mut self,
+        cols: &[&str],
+        invalid: impl Fn() -> String,
+    ) -> Result<Option<Packet>, Box<dyn Error>> {
+        let fd = cols.get(1) == Some(&"CANFD");
+        let (channel, rest) = match fd {
+            true => (cols.get(2), cols.get(3..)),
+            false => (cols.get(1), cols.get(2..)),
+        };
+        let (Some(channel), Some(rest)) = (channel, rest) else {
+            return Ok(None);
+        };
+        if channel.parse::<u32>().is_err() || rest.is_empty() {
+            return Ok(None);
+        }
+
+        let mut packet = Packet::default();
+        let frame = match fd {
+            // direction precedes the ID
+            true => &rest[1..],
+            false => rest,
+        };
+        match frame.first() {
+            Some(&"ErrorFrame") => packet.kind = Kind::Error,
+            Some(id) => {
+                let Some(id) = self.parse_id(id) else {
+                    // status and statistics events
+                    return Ok(None);
+                };
+                (packet.id, packet.extended) = id;
+                let bytes = match fd {
+                    true => self.parse_fd(&frame[1..]),
+                    false => self.parse_classic(&frame[1..], &mut packet),
+                };
+                packet.bytes = bytes.ok_or_else(&invalid)?;
+            }
+            None => return Ok(None),
+        }
+
+        packet.source = match self.channels.iter().position(|c| c == channel) {
+            Some(position) => position,
+            None => {
+                self.channels.push(channel.to_string());
+                self.channels.len() - 1
+            }
+        };
+        Ok(Some(packet))
+    }
+
+    /// ID and whether extended, marked by a trailing `x`
+    fn parse_id(&self, text: &str) -> Option<(u32, bool)> {
+        let (text, extended) = match text.strip_suffix(['x', 'X']) {
+            Some(text) => (text, true),
+            None => (text, false),
+        };
+        let id = self.parse_number(text)?;
+        (id <= 0x1fff_ffff).then_some((id, extended))
+    }
+
+    fn parse_number(&self, text: &str) -> Option<u32> {
+        match self.hex {
+            true => u32::from_str_radix(text, 16).ok(),
+            false => text.parse().ok(),
+        }
+    }
+
+    fn parse_bytes(&self, cols: &[&str], count: usize) -> Option<Vec<u8>> {
+        let cols = cols.get(..count)?;
+        cols.iter()
+            .map(|c| self.parse_number(c).and_then(|b| u8::try_from(b).ok()))
+            .collect()
+    }
+
+    /// Payload of `Rx d 8 01 02 ...` or `Tx r 4`
+    fn parse_classic(
+        &self,
+        cols: &[&str],
+        packet: &mut Packet,
+    ) -> Option<Vec<u8>> {
+        let dlc = || match cols.get(2) {
+            Some(dlc) => self.parse_number(dlc).map(|d| d.min(8) as usize),
+            None => Some(0),
+        };
+        match cols.get(1) {
+            Some(&"d") => self.parse_bytes(cols.get(3..)?, dlc()?),
+            Some(&"r") => {
+                dlc()?;
+                packet.kind = Kind::Remote;
+                Some(vec![])
+            }
+            _ => None,
+        }
+    }
+
+    /// Payload of `[name] brs esi dlc length data ...`
+    fn parse_fd(&self, cols: &[&str]) -> Option<Vec<u8>> {
+        let flag = |c: &&str| matches!(*c, "0" | "1");
+        // the symbolic name is optional
+        let cols = match cols.first().is_some_and(flag) {
+            true => cols,
+            false => cols.get(1..)?,
+        };
+        if !cols.get(..2)?.iter().all(flag) {
+            return None;
+        }
+        let dlc = u32::from_str_radix(cols.get(2)?, 16).ok()?;
+        let length = cols.get(3)?.parse::<usize>().ok()?;
+        if dlc_length(dlc as usize)? != length {
+            return None;
+        }
+        self.parse_bytes(cols.get(4..)?, length)
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    /// Channel numbers, by source index from the first
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hex_absolute() {
+        let asc = r#"
+date Wed Jun 21 10:44:46.000 am 2023
+base hex  timestamps absolute
+internal events logged
+// version 9.0.0
+Begin Triggerblock Wed Jun 21 10:44:46.000 am 2023
+   0.000000 Start of measurement
+   0.012345 1  123             Rx   d 8 01 02 03 04 05 06 07 08  Length = 231910 BitCount = 120 ID = 291
+   0.013000 1  18FEF100x       Tx   d 3 AA BB CC
+   0.014000 1  ErrorFrame
+   0.015000 2  7FF             Rx   r
+   0.016000 2  100             Rx   r 4
+   0.017000 1  Statistic: D 0 R 0 XD 0 XR 0 E 0 O 0 B 0.00%
+   0.018000 CANFD   2 Rx        101  EngineData                       1 0 d 32 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f   0    0      1000      0 0 0 0 0
+   0.019000 CANFD   1 Tx   1ABCDEF0x                                   0 0 8  8 11 22 33 44 55 66 77 88   0    0      1000      0 0 0 0 0
+   0.020000 CANFD   1 Rx        ErrorFrame                              0 0 0  0   0    0      1000      0 0 0 0 0
+   1.500000 1  000             Rx   d 0
+End TriggerBlock
+"#;
+        let data = AscParser::new_from_text(asc, 1).unwrap();
+        assert_eq!(data.channels(), ["1", "2"]);
+        let packets = data.packets();
+        assert_eq!(packets.len(), 9);
+
+        assert_eq!(packets[0].source, 1);
+        assert_eq!(packets[0].id, 0x123);
+        assert!(!packets[0].extended);
+        assert_eq!(packets[0].bytes, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(packets[1].id, 0x18fef100);
+        assert!(packets[1].extended);
+        assert_eq!(packets[1].bytes, [0xaa, 0xbb, 0xcc]);
+        assert_eq!(packets[2].kind, Kind::Error);
+
+        assert_eq!(packets[3].source, 2);
+        assert_eq!(packets[3].kind, Kind::Remote);
+        assert_eq!(packets[4].kind, Kind::Remote);
+
+        assert_eq!(packets[5].source, 2);
+        assert_eq!(packets[5].id, 0x101);
+        assert_eq!(packets[5].bytes.len(), 32);
+        assert_eq!(packets[5].bytes[31], 0x1f);
+        assert!(packets[6].extended);
+        assert_eq!(packets[6].bytes[7], 0x88);
+        assert_eq!(packets[7].kind, Kind::Error);
+
+        assert!(packets[8].bytes.is_empty());
+        assert_eq!(
+            packets[8].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_nanos(1487655000)
+        );
+    }
+
+    #[test]
+    fn dec_relative() {
+        let asc = r#"
+date Mon Jan 08 08:00:00.000 am 2024
+base dec  timestamps relative
+no internal events logged
+Begin Triggerblock Mon Jan 08 08:00:00.000 am 2024
+   0.010000 1  291             Rx   d 4 1 2 254 255
+   0.010000 1  419361024x      Rx   d 2 16 32
+   0.005000 3  2047            Rx   d 1 0
+End TriggerBlock
+"#;
+        let data = AscParser::new_from_text(asc, 0).unwrap();
+        assert!(!data.hex);
+        assert!(data.relative);
+        assert_eq!(data.channels(), ["1", "3"]);
+        let packets = data.packets();
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].id, 0x123);
+        assert_eq!(packets[0].bytes, [1, 2, 254, 255]);
+        assert_eq!(packets[1].id, 0x18fef100);
+        assert!(packets[1].extended);
+        assert_eq!(packets[2].id, 0x7ff);
+        assert_eq!(packets[2].source, 1);
+        assert_eq!(
+            packets[2].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_millis(15)
+        );
+
+        let asc = "base dec timestamps absolute\n0.1 1 291 Rx d 4 1 2";
+        assert!(AscParser::new_from_text(asc, 0).is_err());
+        let asc = "base hex timestamps absolute\n0.1 1 123 Rx d 1 100";
+        assert!(AscParser::new_from_text(asc, 0).is_err());
+        let asc = "base hex timestamps relative\n\
+            18446744073 1 123 Rx d 1 00\n18446744073 1 123 Rx d 1 00";
+        assert!(AscParser::new_from_text(asc, 0).is_err());
+    }
+}
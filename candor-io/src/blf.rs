@@ -0,0 +1,406 @@
+//! Vector binary logging files (.blf)
+//!
+//! A file header is followed by objects, mostly log containers holding a
+//! stream of further objects, usually zlib compressed. Objects in the
+//! stream may span containers.
+
+use crate::{
+    dlc_length, read_u16, read_u32, read_u64, replay::Replay, SourceError,
+};
+use candor::{Kind, Packet};
+
+use std::{
+    error::Error,
+    fs,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+const FILE_SIGNATURE: &[u8] = b"LOGG";
+const OBJECT_SIGNATURE: &[u8] = b"LOBJ";
+/// Size of the object header common to all versions
+const BASE_HEADER_SIZE: usize = 16;
+
+// object types
+const CAN_MESSAGE: u32 = 1;
+const CAN_ERROR: u32 = 2;
+const LOG_CONTAINER: u32 = 10;
+const CAN_ERROR_EXT: u32 = 73;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+// log container compression methods
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+
+/// Object timestamp in nanoseconds, rather than 10 microseconds
+const TIME_ONE_NANS: u32 = 2;
+/// Extended ID flag of message IDs
+const EXTENDED_FLAG: u32 = 0x8000_0000;
+const REMOTE_FLAG: u8 = 0x80;
+/// Extended data length flag of CAN FD messages
+const EDL_FLAG: u8 = 0x01;
+const REMOTE_FLAG_64: u32 = 0x0010;
+
+/// Replays of the channels of a file, in order of appearance, with
+/// consecutive indices starting at `index`
+pub fn open(
+    name: &str,
+    index: usize,
+    default_baud: u32,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+) -> Result<Vec<Replay>, Box<dyn Error>> {
+    let log = BlfParser::new_from_file(name, index)?;
+    Ok(Replay::split(
+        name,
+        index,
+        default_baud,
+        log.packets,
+        &log.channels,
+        tx,
+        errors,
+    ))
+}
+
+fn read_u8(data: &[u8], pos: usize) -> Option<u8> {
+    data.get(pos).copied()
+}
+
+/// Packets of a BLF file, with times relative to the start of the
+/// measurement
+pub struct BlfParser {
+    packets: Vec<Packet>,
+    channels: Vec<String>,
+    index: usize,
+    start_time: Instant,
+}
+
+impl BlfParser {
+    pub fn new_from_file(
+        filename: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_from_bytes(&fs::read(filename)?, index)
+    }
+
+    /// Parse a file, with the packets of each channel using the next source
+    /// index starting at `index`
+    pub fn new_from_bytes(
+        data: &[u8],
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if data.get(..4) != Some(FILE_SIGNATURE) {
+            return Err("Not a BLF file".into());
+        }
+        let header_size = read_u32(data, 4).ok_or("Truncated file header")?;
+        let mut log = Self {
+            packets: vec![],
+            channels: vec![],
+            index,
+            start_time: Instant::now(),
+        };
+
+        // uncompressed stream of the objects in log containers
+        let mut stream: Vec<u8> = vec![];
+        let mut pos = header_size as usize;
+        while let Some(object) = Self::next_object(data, &mut pos)? {
+            if read_u32(object, 12) != Some(LOG_CONTAINER) {
+                log.parse_object(object)?;
+                continue;
+            }
+            let compression = read_u16(object, BASE_HEADER_SIZE)
+                .ok_or("Truncated container")?;
+            let payload = &object[(BASE_HEADER_SIZE + 16).min(object.len())..];
+            match compression {
+                NO_COMPRESSION => stream.extend_from_slice(payload),
+                ZLIB_DEFLATE => stream.extend(
+                    miniz_oxide::inflate::decompress_to_vec_zlib(payload)
+                        .map_err(|e| format!("Invalid container: {e}"))?,
+                ),
+                method => {
+                    return Err(format!("Unknown compression {method}").into())
+                }
+            }
+
+            // objects completed by this container
+            let mut stream_pos = 0;
+            while let Some(object) =
+                Self::next_object(&stream, &mut stream_pos)?
+            {
+                log.parse_object(object)?;
+            }
+            stream.drain(..stream_pos);
+        }
+
+        Ok(log)
+    }
+
+    /// Next complete object at a position, skipping the padding after the
+    /// previous object, or `None` if not enough data is left
+    fn next_object<'a>(
+        data: &'a [u8],
+        pos: &mut usize,
+    ) -> Result<Option<&'a [u8]>, Box<dyn Error>> {
+        let padding = (0..8).find(|i| {
+            data.get(*pos + i..*pos + i + 4) == Some(OBJECT_SIGNATURE)
+        });
+        let start = match padding {
+            Some(padding) => *pos + padding,
+            None if *pos + 8 + 4 > data.len() => return Ok(None),
+            None => return Err(format!("No object at offset {pos}").into()),
+        };
+        let Some(size) = read_u32(data, start + 8) else {
+            return Ok(None);
+        };
+        if (size as usize) < BASE_HEADER_SIZE {
+            return Err(format!("Invalid object at offset {start}").into());
+        }
+        let Some(object) = data.get(start..start + size as usize) else {
+            return Ok(None);
+        };
+        *pos = start + size as usize;
+        Ok(Some(object))
+    }
+
+    /// Add the packet of a CAN object, ignoring other objects
+    fn parse_object(&mut self, object: &[u8]) -> Result<(), Box<dyn Error>> {
+        let truncated = || "Truncated object".to_string();
+        let header_size = read_u16(object, 4).ok_or_else(truncated)? as usize;
+        let kind = read_u32(object, 12).ok_or_else(truncated)?;
+        if !matches!(
+            kind,
+            CAN_MESSAGE
+                | CAN_MESSAGE2
+                | CAN_FD_MESSAGE
+                | CAN_FD_MESSAGE_64
+                | CAN_ERROR
+                | CAN_ERROR_EXT
+        ) {
+            return Ok(());
+        }
+        // flags and timestamp are at the same offsets in header versions 1
+        // and 2
+        let flags = read_u32(object, 16).ok_or_else(truncated)?;
+        let timestamp = read_u64(object, 24).ok_or_else(truncated)?;
+        let time_ns = match flags & TIME_ONE_NANS {
+            0 => timestamp.saturating_mul(10_000),
+            _ => timestamp,
+        };
+        let body = object.get(header_size..).ok_or_else(truncated)?;
+        let (channel, mut packet) = match kind {
+            CAN_MESSAGE | CAN_MESSAGE2 => Self::parse_message(body),
+            CAN_FD_MESSAGE => Self::parse_fd_message(body),
+            CAN_FD_MESSAGE_64 => Self::parse_fd_message_64(body),
+            _ => read_u16(body, 0).map(|channel| {
+                let packet = Packet {
+                    kind: Kind::Error,
+                    ..Default::default()
+                };
+                (channel as u32, packet)
+            }),
+        }
+        .ok_or_else(truncated)?;
+
+        let channel = channel.to_string();
+        let position = match self.channels.iter().position(|c| *c == channel) {
+            Some(position) => position,
+            None => {
+                self.channels.push(channel);
+                self.channels.len() - 1
+            }
+        };
+        packet.source = self.index + position;
+        packet.time = Some(self.start_time + Duration::from_nanos(time_ns));
+        self.packets.push(packet);
+        Ok(())
+    }
+
+    /// Set the ID of a packet from a message ID with the extended flag
+    fn set_id(packet: &mut Packet, id: u32) {
+        packet.extended = id & EXTENDED_FLAG != 0;
+        packet.id = id & !EXTENDED_FLAG;
+    }
+
+    /// Channel and packet of a CAN_MESSAGE(2)
+    fn parse_message(body: &[u8]) -> Option<(u32, Packet)> {
+        let channel = read_u16(body, 0)?;
+        let flags = read_u8(body, 2)?;
+        let dlc = read_u8(body, 3)?.min(8) as usize;
+        let mut packet = Packet::default();
+        Self::set_id(&mut packet, read_u32(body, 4)?);
+        if flags & REMOTE_FLAG != 0 {
+            packet.kind = Kind::Remote;
+        } else {
+            packet.bytes = body.get(8..8 + dlc)?.to_vec();
+        }
+        Some((channel as u32, packet))
+    }
+
+    /// Channel and packet of a CAN_FD_MESSAGE
+    fn parse_fd_message(body: &[u8]) -> Option<(u32, Packet)> {
+        let channel = read_u16(body, 0)?;
+        let flags = read_u8(body, 2)?;
+        let dlc = read_u8(body, 3)? as usize;
+        let fd_flags = read_u8(body, 13)?;
+        let length = match fd_flags & EDL_FLAG {
+            0 => dlc.min(8),
+            _ => dlc_length(dlc)?,
+        };
+        let mut packet = Packet::default();
+        Self::set_id(&mut packet, read_u32(body, 4)?);
+        if flags & REMOTE_FLAG != 0 {
+            packet.kind = Kind::Remote;
+        } else {
+            packet.bytes = body.get(20..20 + length)?.to_vec();
+        }
+        Some((channel as u32, packet))
+    }
+
+    /// Channel and packet of a CAN_FD_MESSAGE_64
+    fn parse_fd_message_64(body: &[u8]) -> Option<(u32, Packet)> {
+        let channel = read_u8(body, 0)?;
+        let length = (read_u8(body, 2)? as usize).min(64);
+        let flags = read_u32(body, 12)?;
+        let mut packet = Packet::default();
+        Self::set_id(&mut packet, read_u32(body, 4)?);
+        if flags & REMOTE_FLAG_64 != 0 {
+            packet.kind = Kind::Remote;
+        } else {
+            packet.bytes = body.get(40..40 + length)?.to_vec();
+        }
+        Some((channel as u32, packet))
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    /// Channel numbers, by source index from the first
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Object with a version 1 header and a timestamp in nanoseconds
+    fn object(kind: u32, ns: u64, body: &[u8]) -> Vec<u8> {
+        let mut data = OBJECT_SIGNATURE.to_vec();
+        data.extend(32u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend((32 + body.len() as u32).to_le_bytes());
+        data.extend(kind.to_le_bytes());
+        data.extend(TIME_ONE_NANS.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(ns.to_le_bytes());
+        data.extend(body);
+        data.resize(data.len() + data.len() % 4, 0);
+        data
+    }
+
+    fn container(stream: &[u8], compress: bool) -> Vec<u8> {
+        let payload = match compress {
+            true => miniz_oxide::deflate::compress_to_vec_zlib(stream, 6),
+            false => stream.to_vec(),
+        };
+        let mut data = OBJECT_SIGNATURE.to_vec();
+        data.extend(16u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend((32 + payload.len() as u32).to_le_bytes());
+        data.extend(LOG_CONTAINER.to_le_bytes());
+        let method = if compress {
+            ZLIB_DEFLATE
+        } else {
+            NO_COMPRESSION
+        };
+        data.extend(method.to_le_bytes());
+        data.extend([0; 6]);
+        data.extend((stream.len() as u32).to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(payload);
+        data.resize(data.len() + data.len() % 4, 0);
+        data
+    }
+
+    fn message(channel: u16, flags: u8, id: u32, data: &[u8]) -> Vec<u8> {
+        let mut body = channel.to_le_bytes().to_vec();
+        body.extend([flags, data.len() as u8]);
+        body.extend(id.to_le_bytes());
+        body.extend(data);
+        body.resize(16, 0);
+        body
+    }
+
+    #[test]
+    fn parse() {
+        let mut fd = message(2, 0, 0x101, &[]);
+        fd.extend([0; 5]);
+        fd[3] = 9; // DLC of 12 bytes
+        fd[13] = EDL_FLAG;
+        fd.resize(20, 0);
+        fd.extend(1..=64);
+
+        let mut fd_64 = vec![1, 15, 64, 0];
+        fd_64.extend((0x18fef100 | EXTENDED_FLAG).to_le_bytes());
+        fd_64.resize(40, 0);
+        fd_64.extend([0x55; 64]);
+
+        let mut error = 1u16.to_le_bytes().to_vec();
+        error.resize(32, 0);
+
+        let mut stream =
+            object(CAN_MESSAGE, 1000, &message(1, 0, 0x123, &[1, 2, 3]));
+        stream.extend(object(
+            CAN_MESSAGE2,
+            2000,
+            &message(1, REMOTE_FLAG, 0x7ff, &[0; 4]),
+        ));
+        stream.extend(object(CAN_FD_MESSAGE, 3000, &fd));
+        stream.extend(object(CAN_FD_MESSAGE_64, 4000, &fd_64));
+        stream.extend(object(CAN_ERROR_EXT, 5000, &error));
+        stream.extend(object(96, 6000, &[0; 8])); // global marker
+
+        // header, with the stream split across two containers
+        let mut data = FILE_SIGNATURE.to_vec();
+        data.extend(144u32.to_le_bytes());
+        data.resize(144, 0);
+        data.extend(container(&stream[..50], true));
+        data.extend(container(&stream[50..], false));
+        data.extend(object(CAN_MESSAGE, 1_000_000_000, &message(2, 0, 0, &[])));
+
+        let log = BlfParser::new_from_bytes(&data, 1).unwrap();
+        assert_eq!(log.channels(), ["1", "2"]);
+        let packets = log.packets();
+        assert_eq!(packets.len(), 6);
+
+        assert_eq!(packets[0].source, 1);
+        assert_eq!(packets[0].id, 0x123);
+        assert!(!packets[0].extended);
+        assert_eq!(packets[0].bytes, [1, 2, 3]);
+        assert_eq!(packets[1].kind, Kind::Remote);
+        assert!(packets[1].bytes.is_empty());
+
+        assert_eq!(packets[2].source, 2);
+        assert_eq!(packets[2].bytes, (1..=12).collect::<Vec<u8>>());
+        assert_eq!(packets[3].source, 1);
+        assert_eq!(packets[3].id, 0x18fef100);
+        assert!(packets[3].extended);
+        assert_eq!(packets[3].bytes, [0x55; 64]);
+        assert_eq!(packets[4].kind, Kind::Error);
+
+        assert_eq!(packets[5].source, 2);
+        assert_eq!(
+            packets[5].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_nanos(999_999_000)
+        );
+
+        assert!(BlfParser::new_from_bytes(b"LOGX", 0).is_err());
+        let mut corrupt = data.clone();
+        corrupt[144 + 40..144 + 48].fill(0xff);
+        assert!(BlfParser::new_from_bytes(&corrupt, 0).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! Logs of the Linux `candump -l` tool
+//!
+//! Each line holds a frame as `(seconds.micros) interface frame`, with
+//! frames written as `123#DEADBEEF` (data), `123##1DEADBEEF` (CAN FD, with
+//! a flags nibble) or `123#R` (remote). Extended IDs have eight digits,
+//! error frames the error flag `20000000` set in the ID.
+
+use crate::{replay::Replay, seconds_ns, Sink, SourceError};
+use candor::{Kind, Packet};
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Error flag in the ID of error frames
+const ERROR_FLAG: u32 = 0x2000_0000;
+
+/// Replays of the interfaces of a log, in order of appearance, with
+/// consecutive indices starting at `index`
+pub fn open(
+    name: &str,
+    index: usize,
+    default_baud: u32,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+) -> Result<Vec<Replay>, Box<dyn Error>> {
+    let log = CandumpParser::new_from_file(name, index)?;
+    Ok(Replay::split(
+        name,
+        index,
+        default_baud,
+        log.packets,
+        &log.interfaces,
+        tx,
+        errors,
+    ))
+}
+
+/// Packets of a candump log, with times relative to its first line
+pub struct CandumpParser {
+    packets: Vec<Packet>,
+    interfaces: Vec<String>,
+}
+
+impl CandumpParser {
+    pub fn new_from_file(
+        filename: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(filename)?;
+        let lines = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        Self::new_from_lines(lines, index)
+    }
+
+    pub fn new_from_text(
+        text: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_from_lines(
+            text.lines().map(|s| s.to_string()).collect(),
+            index,
+        )
+    }
+
+    /// Parse log lines, with the packets of each interface using the next
+    /// source index starting at `index`
+    pub fn new_from_lines(
+        lines: Vec<String>,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut packets: Vec<Packet> = Vec::with_capacity(lines.len());
+        let mut interfaces: Vec<String> = vec![];
+        let start_time = Instant::now();
+        let mut first_time: Option<u64> = None;
+
+        for (number, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid line {}: {line}", number + 1);
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 3 {
+                return Err(invalid().into());
+            }
+            let time_ns = cols[0]
+                .strip_prefix('(')
+                .and_then(|t| t.strip_suffix(')'))
+                .and_then(seconds_ns)
+                .ok_or_else(invalid)?;
+            let first = *first_time.get_or_insert(time_ns);
+
+            let source = match interfaces.iter().position(|i| i == cols[1]) {
+                Some(position) => index + position,
+                None => {
+                    interfaces.push(cols[1].to_string());
+                    index + interfaces.len() - 1
+                }
+            };
+
+            let mut packet = parse_frame(cols[2]).ok_or_else(invalid)?;
+            packet.source = source;
+            packet.time = Some(
+                start_time
+                    + Duration::from_nanos(time_ns.saturating_sub(first)),
+            );
+            packets.push(packet);
+        }
+
+        Ok(Self {
+            packets,
+            interfaces,
+        })
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    /// Interface names, by source index from the first
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+}
+
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let text = text.replace('.', "");
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse a frame such as `123#DEADBEEF`, without source and time
+fn parse_frame(text: &str) -> Option<Packet> {
+    let (id_text, data) = text.split_once('#')?;
+    let extended = match id_text.len() {
+        3 => false,
+        8 => true,
+        _ => return None,
+    };
+    let mut id = u32::from_str_radix(id_text, 16).ok()?;
+    let mut kind = Kind::Data;
+    if extended && id & ERROR_FLAG != 0 {
+        kind = Kind::Error;
+        id &= !ERROR_FLAG;
+    }
+    let bytes = if let Some(fd) = data.strip_prefix('#') {
+        // flags nibble, then the payload
+        let flags = fd.get(..1)?;
+        u8::from_str_radix(flags, 16).ok()?;
+        hex_bytes(&fd[1..])?
+    } else if let Some(length) = data.strip_prefix(['R', 'r']) {
+        if !length.is_empty() {
+            length.parse::<u8>().ok()?;
+        }
+        kind = Kind::Remote;
+        vec![]
+    } else {
+        hex_bytes(data)?
+    };
+    if bytes.len() > 64 {
+        return None;
+    }
+    Some(Packet {
+        id,
+        extended: extended && kind != Kind::Error,
+        bytes,
+        kind,
+        ..Default::default()
+    })
+}
+
+/// Writer of candump logs, with interfaces named by source index
+pub struct CandumpWriter<W: Write> {
+    writer: W,
+    interfaces: Vec<String>,
+    /// Wall clock time at a known instant, for the timestamps
+    clock: (Instant, SystemTime),
+}
+
+impl CandumpWriter<BufWriter<File>> {
+    pub fn create(filename: &str, interfaces: Vec<String>) -> io::Result<Self> {
+        Ok(Self::new(
+            BufWriter::new(File::create(filename)?),
+            interfaces,
+        ))
+    }
+}
+
+impl<W: Write> CandumpWriter<W> {
+    /// Writer naming the interface of each source index, with `canN` used
+    /// for sources beyond those given
+    pub fn new(writer: W, interfaces: Vec<String>) -> Self {
+        let interfaces = interfaces
+            .iter()
+            .map(|i| i.replace(char::is_whitespace, "_"))
+            .collect();
+        Self {
+            writer,
+            interfaces,
+            clock: (Instant::now(), SystemTime::now()),
+        }
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Sink for CandumpWriter<W> {
+    fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        let (instant, system) = self.clock;
+        let time = match packet.time {
+            Some(time) if time > instant => system + (time - instant),
+            Some(time) => system - (instant - time),
+            None => SystemTime::now(),
+        };
+        let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let interface = match self.interfaces.get(packet.source) {
+            Some(interface) => interface.clone(),
+            None => format!("can{}", packet.source),
+        };
+        let id = match (packet.kind, packet.extended) {
+            (Kind::Error, _) => format!("{:08X}", packet.id | ERROR_FLAG),
+            (_, true) => format!("{:08X}", packet.id),
+            (_, false) => format!("{:03X}", packet.id),
+        };
+        let data: String =
+            packet.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        let frame = match packet.kind {
+            Kind::Remote => format!("{id}#R"),
+            _ if packet.bytes.len() > 8 => format!("{id}##0{data}"),
+            _ => format!("{id}#{data}"),
+        };
+        writeln!(
+            self.writer,
+            "({}.{:06}) {interface} {frame}",
+            time.as_secs(),
+            time.subsec_micros()
+        )
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let log = r#"
+(1690000000.123456) can0 123#DEADBEEF
+(1690000000.124456) vcan1 18FEF100#0102030405060708
+(1690000000.125000) can0 7FF#
+(1690000000.126000) can0 100#R
+(1690000000.127000) vcan1 1F334455#r4
+(1690000000.128000) can0 101##1000102030405060708090A0B
+(1690000000.129000) can0 20000080#0000000000000000
+(1690000001.123456) vcan1 010#11.22.33 R
+"#;
+        let data = CandumpParser::new_from_text(log, 2).unwrap();
+        assert_eq!(data.interfaces(), ["can0", "vcan1"]);
+        let packets = data.packets();
+        assert_eq!(packets.len(), 8);
+
+        assert_eq!(packets[0].source, 2);
+        assert_eq!(packets[0].id, 0x123);
+        assert!(!packets[0].extended);
+        assert_eq!(packets[0].bytes, [0xde, 0xad, 0xbe, 0xef]);
+
+        assert_eq!(packets[1].source, 3);
+        assert_eq!(packets[1].id, 0x18fef100);
+        assert!(packets[1].extended);
+        assert_eq!(
+            packets[1].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_millis(1)
+        );
+
+        assert!(packets[2].bytes.is_empty());
+        assert_eq!(packets[2].kind, Kind::Data);
+        assert_eq!(packets[3].kind, Kind::Remote);
+        assert_eq!(packets[4].kind, Kind::Remote);
+        assert!(packets[4].extended);
+        assert_eq!(packets[5].bytes.len(), 12);
+        assert_eq!(packets[5].bytes[11], 0x0b);
+        assert_eq!(packets[6].kind, Kind::Error);
+        assert_eq!(packets[6].id, 0x80);
+        assert_eq!(packets[7].bytes, [0x11, 0x22, 0x33]);
+        assert_eq!(
+            packets[7].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_secs(1)
+        );
+
+        assert!(CandumpParser::new_from_text("(1.0) can0 1234#00", 0).is_err());
+        assert!(CandumpParser::new_from_text("can0 123#00", 0).is_err());
+        assert!(CandumpParser::new_from_text("(1.0) can0 123#0", 0).is_err());
+    }
+
+    #[test]
+    fn write_read() {
+        let log = r#"(1690000000.123456) can0 123#DEADBEEF
+(1690000000.124456) vcan1 18FEF100#0102030405060708
+(1690000000.125000) can0 7FF#
+(1690000000.126000) can0 100#R
+(1690000000.128000) can0 101##0000102030405060708090A0B
+(1690000000.129000) can0 20000080#0000000000000000
+"#;
+        let data = CandumpParser::new_from_text(log, 0).unwrap();
+        let mut writer = CandumpWriter::new(vec![], data.interfaces().to_vec());
+        // wall clock matching the log's first timestamp
+        writer.clock = (
+            data.packets()[0].time.unwrap(),
+            UNIX_EPOCH + Duration::from_micros(1690000000123456),
+        );
+        for packet in data.packets() {
+            writer.write(packet).unwrap();
+        }
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(text, log);
+    }
+}
@@ -1,9 +1,229 @@
+pub mod asc;
+pub mod blf;
+pub mod candump;
+pub mod mdf;
+pub mod replay;
 pub mod trc;
 
 #[cfg(feature = "socketcan")]
 pub mod socketcan;
 
+use candor::Packet;
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval at which a paused source checks whether to resume
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+
+/// Payload length of each CAN FD data length code
+const FD_LENGTHS: [usize; 16] =
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Payload length of a data length code
+pub(crate) fn dlc_length(dlc: usize) -> Option<usize> {
+    FD_LENGTHS.get(dlc).copied()
+}
+
+/// Data length code of a payload length, if a valid CAN (FD) length
+pub(crate) fn length_dlc(length: usize) -> Option<usize> {
+    FD_LENGTHS.iter().position(|l| *l == length)
+}
+
+/// Bytes of binary data at `pos`
+pub(crate) fn read<const N: usize>(data: &[u8], pos: usize) -> Option<[u8; N]> {
+    data.get(pos..pos.checked_add(N)?)?.try_into().ok()
+}
+
+/// Little-endian integers of binary data at `pos`
+pub(crate) fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    read(data, pos).map(u16::from_le_bytes)
+}
+
+pub(crate) fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    read(data, pos).map(u32::from_le_bytes)
+}
+
+pub(crate) fn read_u64(data: &[u8], pos: usize) -> Option<u64> {
+    read(data, pos).map(u64::from_le_bytes)
+}
+
+/// Nanoseconds of a `seconds.fraction` timestamp
+pub(crate) fn seconds_ns(text: &str) -> Option<u64> {
+    let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{fraction:0<9}").parse::<u64>().ok()?;
+    seconds
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(1_000_000_000)?
+        .checked_add(nanos)
+}
+
+/// Condition of a source
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Status {
+    /// Not started yet, or stopped
+    #[default]
+    Stopped,
+    Running,
+    Paused,
+    /// Recording replayed to its end
+    Ended,
+    /// Failed, with the reason sent as a [`SourceError`]
+    Error,
+    /// Interface went away
+    Disconnected,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Status::Stopped => "stopped",
+            Status::Running => "running",
+            Status::Paused => "paused",
+            Status::Ended => "ended",
+            Status::Error => "error",
+            Status::Disconnected => "disconnected",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Failure reported from the thread of a source
+#[derive(Clone, Debug)]
+pub struct SourceError {
+    /// Index of the source, as in [`candor::Packet::source`]
+    pub source: usize,
+    /// Status the source changed to
+    pub status: Status,
+    pub text: String,
+}
+
 pub trait Source {
     fn name(&self) -> String;
     fn baud(&self) -> u32;
+    /// Whether packets are received as they happen, rather than replayed
+    /// from a recording
+    fn live(&self) -> bool;
+    /// Start sending packets from a thread of the source
+    fn start(&mut self) -> io::Result<()>;
+    /// Stop sending packets and wait for the thread to finish
+    fn stop(&mut self);
+    /// Hold back or resume sending packets
+    fn pause(&mut self, paused: bool);
+    fn status(&self) -> Status;
+}
+
+/// Destination of packets, such as a trace file
+pub trait Sink {
+    fn write(&mut self, packet: &Packet) -> io::Result<()>;
+    /// Write out buffered packets
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// State shared between a source and its thread
+#[derive(Clone, Default)]
+pub(crate) struct Control {
+    status: Arc<Mutex<Status>>,
+    stop: Arc<AtomicBool>,
+    pause: Arc<AtomicBool>,
+}
+
+impl Control {
+    pub(crate) fn status(&self) -> Status {
+        *self.status.lock().unwrap()
+    }
+
+    pub(crate) fn set_status(&self, status: Status) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// Report a failure, changing to the given status
+    pub(crate) fn fail(
+        &self,
+        errors: &mpsc::Sender<SourceError>,
+        source: usize,
+        status: Status,
+        text: String,
+    ) {
+        self.set_status(status);
+        errors
+            .send(SourceError {
+                source,
+                status,
+                text,
+            })
+            .ok();
+    }
+
+    pub(crate) fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn paused(&self) -> bool {
+        self.pause.load(Ordering::Relaxed)
+    }
+
+    /// Prepare for a new thread
+    pub(crate) fn start(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.set_status(match self.paused() {
+            true => Status::Paused,
+            false => Status::Running,
+        });
+    }
+
+    pub(crate) fn pause(&self, paused: bool) {
+        self.pause.store(paused, Ordering::Relaxed);
+        let mut status = self.status.lock().unwrap();
+        *status = match (*status, paused) {
+            (Status::Running, true) => Status::Paused,
+            (Status::Paused, false) => Status::Running,
+            (status, _) => status,
+        };
+    }
+
+    /// Signal the thread to stop and wait for it
+    pub(crate) fn stop(&self, thread: Option<thread::JoinHandle<()>>) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = thread {
+            thread.thread().unpark();
+            thread.join().ok();
+        }
+        if matches!(self.status(), Status::Running | Status::Paused) {
+            self.set_status(Status::Stopped);
+        }
+    }
+
+    /// Wait while paused, returning how long, or `None` if stopped
+    pub(crate) fn hold(&self) -> Option<Duration> {
+        let start = Instant::now();
+        while self.paused() && !self.stopped() {
+            thread::park_timeout(PAUSE_POLL);
+        }
+        (!self.stopped()).then(|| start.elapsed())
+    }
+
+    /// Wait until the given time, returning `false` if stopped before
+    pub(crate) fn wait_until(&self, time: Instant) -> bool {
+        loop {
+            if self.stopped() {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= time {
+                return true;
+            }
+            thread::park_timeout(time - now);
+        }
+    }
 }
@@ -0,0 +1,958 @@
+//! ASAM MDF 4 measurement files with CAN bus logging (.mf4)
+//!
+//! Bus logging stores frames in channel groups acquired as
+//! `CAN_DataFrame`, `CAN_RemoteFrame` or `CAN_ErrorFrame`, with a master
+//! time channel and member channels such as `CAN_DataFrame.ID` and
+//! `CAN_DataFrame.DataBytes`. Data blocks may be split into lists and
+//! compressed; frame payloads may be stored with variable length.
+
+use crate::{
+    dlc_length, read, read_u16, read_u32, read_u64, replay::Replay, SourceError,
+};
+use candor::{Kind, Packet};
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Address of the header block, following the identification block
+const HEADER_ADDRESS: u64 = 64;
+/// Size of the id, reserved, length and link count of every block
+const BLOCK_HEADER_SIZE: usize = 24;
+
+// channel types
+const CN_VLSD: u8 = 1;
+const CN_MASTER: u8 = 2;
+const CN_VIRTUAL_MASTER: u8 = 3;
+/// Synchronization type of time master channels
+const SYNC_TIME: u8 = 1;
+/// Channel group flag of variable length signal data
+const CG_VLSD: u16 = 0x0001;
+
+// channel data types
+const UINT_BE: u8 = 1;
+const INT_LE: u8 = 2;
+const INT_BE: u8 = 3;
+const FLOAT_LE: u8 = 4;
+const FLOAT_BE: u8 = 5;
+
+// conversion types
+const CC_IDENTITY: u8 = 0;
+const CC_LINEAR: u8 = 1;
+
+/// Compression of data blocks with transposition before deflating
+const DZ_TRANSPOSED: u8 = 1;
+/// ID bit marking extended IDs, when there is no IDE channel
+const IDE_FLAG: u64 = 0x8000_0000;
+
+/// Replays of the bus channels of a file, in order of appearance, with
+/// consecutive indices starting at `index`
+pub fn open(
+    name: &str,
+    index: usize,
+    default_baud: u32,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+) -> Result<Vec<Replay>, Box<dyn Error>> {
+    let file = Mdf4Parser::new_from_file(name, index)?;
+    Ok(Replay::split(
+        name,
+        index,
+        default_baud,
+        file.packets,
+        &file.channels,
+        tx,
+        errors,
+    ))
+}
+
+fn read_f64(data: &[u8], pos: usize) -> Option<f64> {
+    read(data, pos).map(f64::from_le_bytes)
+}
+
+/// Block of a file, with its links to other blocks
+struct Block<'a> {
+    id: &'a [u8],
+    links: Vec<u64>,
+    data: &'a [u8],
+}
+
+impl<'a> Block<'a> {
+    fn read(file: &'a [u8], address: u64) -> Result<Self, Box<dyn Error>> {
+        let invalid = || format!("Invalid block at {address:#x}");
+        let pos = usize::try_from(address).map_err(|_| invalid())?;
+        let header = file.get(pos..).ok_or_else(invalid)?;
+        let id = header.get(..4).filter(|id| id.starts_with(b"##"));
+        let id = id.ok_or_else(invalid)?;
+        let length = read_u64(header, 8).ok_or_else(invalid)? as usize;
+        let count = read_u64(header, 16).ok_or_else(invalid)? as usize;
+        let data_start = count
+            .checked_mul(8)
+            .and_then(|l| l.checked_add(BLOCK_HEADER_SIZE))
+            .filter(|start| *start <= length)
+            .ok_or_else(invalid)?;
+        let block = header.get(..length).ok_or_else(invalid)?;
+        let links = (0..count)
+            .map(|i| read_u64(block, BLOCK_HEADER_SIZE + i * 8).unwrap())
+            .collect();
+        Ok(Self {
+            id: &id[2..],
+            links,
+            data: &block[data_start..],
+        })
+    }
+
+    /// Read a block of the given type
+    fn expect(
+        file: &'a [u8],
+        address: u64,
+        id: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        let block = Self::read(file, address)?;
+        if block.id != id {
+            return Err(format!(
+                "Expected {} block at {address:#x}",
+                String::from_utf8_lossy(id)
+            )
+            .into());
+        }
+        Ok(block)
+    }
+
+    /// Blocks of a list linked by their first link
+    fn chain(
+        file: &'a [u8],
+        first: u64,
+        id: &[u8],
+    ) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut blocks = vec![];
+        let mut visited = HashSet::new();
+        let mut address = first;
+        while address != 0 {
+            if !visited.insert(address) {
+                return Err(format!("Loop of blocks at {address:#x}").into());
+            }
+            let block = Self::expect(file, address, id)?;
+            address = block.links.first().copied().unwrap_or_default();
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    fn link(&self, index: usize) -> u64 {
+        self.links.get(index).copied().unwrap_or_default()
+    }
+
+    fn truncated(&self) -> String {
+        format!("Truncated {} block", String::from_utf8_lossy(self.id))
+    }
+}
+
+/// Text of a TX or MD block
+fn text(file: &[u8], address: u64) -> Result<String, Box<dyn Error>> {
+    if address == 0 {
+        return Ok(String::new());
+    }
+    let block = Block::read(file, address)?;
+    let text = String::from_utf8_lossy(block.data);
+    Ok(text.trim_end_matches('\0').to_string())
+}
+
+/// Contents of a data block, a (compressed) part of one, or list of them,
+/// failing on blocks already `visited`
+fn data(
+    file: &[u8],
+    address: u64,
+    visited: &mut HashSet<u64>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if address == 0 {
+        return Ok(vec![]);
+    }
+    if !visited.insert(address) {
+        return Err(format!("Loop of blocks at {address:#x}").into());
+    }
+    let block = Block::read(file, address)?;
+    match block.id {
+        b"DT" | b"SD" | b"RD" => Ok(block.data.to_vec()),
+        b"DZ" => inflate(&block),
+        b"DL" => {
+            let mut data = vec![];
+            for list in Block::chain(file, address, b"DL")? {
+                let count = read_u32(list.data, 4).ok_or(list.truncated())?;
+                for i in 0..count as usize {
+                    data.extend(self::data(file, list.link(1 + i), visited)?);
+                }
+            }
+            Ok(data)
+        }
+        b"HL" => data(file, block.link(0), visited),
+        id => Err(format!(
+            "Unexpected {} data block",
+            String::from_utf8_lossy(id)
+        )
+        .into()),
+    }
+}
+
+/// Decompress a DZ block
+fn inflate(block: &Block) -> Result<Vec<u8>, Box<dyn Error>> {
+    let zip_type = *block.data.get(2).ok_or(block.truncated())?;
+    let columns = read_u32(block.data, 4).ok_or(block.truncated())? as usize;
+    let length = read_u64(block.data, 16).ok_or(block.truncated())? as usize;
+    let compressed = 24usize
+        .checked_add(length)
+        .and_then(|end| block.data.get(24..end));
+    let data = miniz_oxide::inflate::decompress_to_vec_zlib(
+        compressed.ok_or(block.truncated())?,
+    )
+    .map_err(|e| format!("Invalid DZ block: {e}"))?;
+    if zip_type != DZ_TRANSPOSED || columns == 0 {
+        return Ok(data);
+    }
+    // whole rows were stored column by column
+    let rows = data.len() / columns;
+    let mut original = data.clone();
+    for row in 0..rows {
+        for column in 0..columns {
+            original[row * columns + column] = data[column * rows + row];
+        }
+    }
+    Ok(original)
+}
+
+/// Conversion from raw to physical values
+#[derive(Copy, Clone)]
+enum Conversion {
+    Identity,
+    Linear(f64, f64),
+}
+
+impl Conversion {
+    fn read(file: &[u8], address: u64) -> Result<Self, Box<dyn Error>> {
+        if address == 0 {
+            return Ok(Self::Identity);
+        }
+        let block = Block::expect(file, address, b"CC")?;
+        match block.data.first() {
+            Some(&CC_IDENTITY) => Ok(Self::Identity),
+            Some(&CC_LINEAR) => {
+                let offset = read_f64(block.data, 24);
+                let factor = read_f64(block.data, 32);
+                match (offset, factor) {
+                    (Some(offset), Some(factor)) => {
+                        Ok(Self::Linear(offset, factor))
+                    }
+                    _ => Err(block.truncated().into()),
+                }
+            }
+            _ => Err("Unsupported MDF conversion".into()),
+        }
+    }
+
+    fn apply(&self, value: f64) -> f64 {
+        match self {
+            Self::Identity => value,
+            Self::Linear(offset, factor) => offset + factor * value,
+        }
+    }
+}
+
+/// Channel of a channel group, located within its records
+struct Channel {
+    name: String,
+    channel_type: u8,
+    sync_type: u8,
+    data_type: u8,
+    bit_offset: usize,
+    byte_offset: usize,
+    bit_count: usize,
+    conversion: Conversion,
+    /// Signal data of variable length channels
+    data: u64,
+}
+
+impl Channel {
+    /// Channels of a list, including members of structures, failing on
+    /// lists already `visited`
+    fn read_all(
+        file: &[u8],
+        first: u64,
+        channels: &mut Vec<Self>,
+        visited: &mut HashSet<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !visited.insert(first) {
+            return Err(format!("Loop of blocks at {first:#x}").into());
+        }
+        for block in Block::chain(file, first, b"CN")? {
+            let truncated = || block.truncated();
+            let name = text(file, block.link(2))?;
+            let bit_offset = *block.data.get(3).ok_or_else(truncated)?;
+            if bit_offset > 7 {
+                return Err(format!("Invalid bit offset in {name}").into());
+            }
+            channels.push(Self {
+                // member names are prefixed by the structure's
+                name: name.rsplit('.').next().unwrap_or_default().to_string(),
+                channel_type: *block.data.first().ok_or_else(truncated)?,
+                sync_type: *block.data.get(1).ok_or_else(truncated)?,
+                data_type: *block.data.get(2).ok_or_else(truncated)?,
+                bit_offset: bit_offset as usize,
+                byte_offset: read_u32(block.data, 4).ok_or_else(truncated)?
+                    as usize,
+                bit_count: read_u32(block.data, 8).ok_or_else(truncated)?
+                    as usize,
+                conversion: Conversion::read(file, block.link(4))?,
+                data: block.link(5),
+            });
+            let composition = block.link(1);
+            if composition != 0 && Block::read(file, composition)?.id == b"CN" {
+                Self::read_all(file, composition, channels, visited)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes<'a>(&self, record: &'a [u8]) -> Option<&'a [u8]> {
+        let size = (self.bit_offset + self.bit_count).div_ceil(8);
+        record.get(self.byte_offset..self.byte_offset.checked_add(size)?)
+    }
+
+    /// Raw integer value, sign-extended for signed types
+    fn raw(&self, record: &[u8]) -> Option<u64> {
+        if self.bit_count == 0 || self.bit_count > 64 {
+            return None;
+        }
+        let bytes = self.bytes(record).filter(|b| b.len() <= 16)?;
+        let mut value: u128 = 0;
+        match self.data_type {
+            UINT_BE | INT_BE | FLOAT_BE => {
+                bytes.iter().for_each(|b| value = value << 8 | *b as u128)
+            }
+            _ => bytes
+                .iter()
+                .rev()
+                .for_each(|b| value = value << 8 | *b as u128),
+        }
+        let mask = u128::MAX >> (128 - self.bit_count);
+        let value = ((value >> self.bit_offset) & mask) as u64;
+        let signed = matches!(self.data_type, INT_LE | INT_BE);
+        Some(match signed && self.bit_count < 64 {
+            true => {
+                let shift = 64 - self.bit_count;
+                (((value << shift) as i64) >> shift) as u64
+            }
+            false => value,
+        })
+    }
+
+    /// Physical value
+    fn value(&self, record: &[u8]) -> Option<f64> {
+        let raw = self.raw(record)?;
+        let value = match (self.data_type, self.bit_count) {
+            (FLOAT_LE | FLOAT_BE, 64) => f64::from_bits(raw),
+            (FLOAT_LE | FLOAT_BE, 32) => f32::from_bits(raw as u32) as f64,
+            (FLOAT_LE | FLOAT_BE, _) => return None,
+            (INT_LE | INT_BE, _) => raw as i64 as f64,
+            _ => raw as f64,
+        };
+        Some(self.conversion.apply(value))
+    }
+}
+
+/// Channel group with its records
+struct Group {
+    record_id: u64,
+    vlsd: bool,
+    /// Size of fixed length records, without record ID
+    size: usize,
+    kind: Option<Kind>,
+    channels: Vec<Channel>,
+    records: Vec<Vec<u8>>,
+    /// Records of variable length groups, each prefixed by its length
+    signal_data: Vec<u8>,
+    address: u64,
+}
+
+/// Kind of frames of a bus logging group or channel name
+fn frame_kind(name: &str) -> Option<Kind> {
+    match name {
+        "CAN_DataFrame" => Some(Kind::Data),
+        "CAN_RemoteFrame" => Some(Kind::Remote),
+        "CAN_ErrorFrame" => Some(Kind::Error),
+        _ => None,
+    }
+}
+
+/// Packets of an MDF 4 file, with times relative to the start of the
+/// measurement
+pub struct Mdf4Parser {
+    packets: Vec<Packet>,
+    channels: Vec<String>,
+    index: usize,
+    start: SystemTime,
+    start_time: Instant,
+}
+
+impl Mdf4Parser {
+    pub fn new_from_file(
+        filename: &str,
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_from_bytes(&fs::read(filename)?, index)
+    }
+
+    /// Parse a file, with the packets of each bus channel using the next
+    /// source index starting at `index`
+    pub fn new_from_bytes(
+        file: &[u8],
+        index: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !file.starts_with(b"MDF") {
+            return Err("Not an MDF file".into());
+        }
+        let version =
+            String::from_utf8_lossy(file.get(8..16).unwrap_or_default());
+        if !version.starts_with("4.") {
+            return Err(
+                format!("Unsupported MDF version {}", version.trim()).into()
+            );
+        }
+        let header = Block::expect(file, HEADER_ADDRESS, b"HD")?;
+        let start_ns = read_u64(header.data, 0).ok_or(header.truncated())?;
+        let mut mdf = Self {
+            packets: vec![],
+            channels: vec![],
+            index,
+            start: UNIX_EPOCH + Duration::from_nanos(start_ns),
+            start_time: Instant::now(),
+        };
+        for group in Block::chain(file, header.link(0), b"DG")? {
+            mdf.read_data_group(file, &group)?;
+        }
+        // groups are stored one after the other
+        mdf.packets.sort_by_key(|p| p.time);
+        Ok(mdf)
+    }
+
+    fn read_data_group(
+        &mut self,
+        file: &[u8],
+        block: &Block,
+    ) -> Result<(), Box<dyn Error>> {
+        let id_size = *block.data.first().ok_or(block.truncated())? as usize;
+        let mut groups = vec![];
+        // addresses identify the groups holding variable length data
+        let mut address = block.link(1);
+        for cg in Block::chain(file, address, b"CG")? {
+            let truncated = || cg.truncated();
+            let flags = read_u16(cg.data, 16).ok_or_else(truncated)?;
+            let data_bytes = read_u32(cg.data, 24).ok_or_else(truncated)?;
+            let inval_bytes = read_u32(cg.data, 28).ok_or_else(truncated)?;
+            let mut channels = vec![];
+            Channel::read_all(
+                file,
+                cg.link(1),
+                &mut channels,
+                &mut HashSet::new(),
+            )?;
+            let kind = frame_kind(&text(file, cg.link(2))?)
+                .or_else(|| channels.iter().find_map(|c| frame_kind(&c.name)));
+            groups.push(Group {
+                record_id: read_u64(cg.data, 0).ok_or_else(truncated)?,
+                vlsd: flags & CG_VLSD != 0,
+                size: data_bytes as usize + inval_bytes as usize,
+                kind,
+                channels,
+                records: vec![],
+                signal_data: vec![],
+                address,
+            });
+            address = cg.link(0);
+        }
+
+        // split records by group
+        let records = data(file, block.link(2), &mut HashSet::new())?;
+        let mut pos = 0;
+        while pos < records.len() {
+            let group = match id_size {
+                0 if groups.len() == 1 => &mut groups[0],
+                1 | 2 | 4 | 8 => {
+                    let mut id = [0u8; 8];
+                    let bytes = records.get(pos..pos + id_size);
+                    let bytes = bytes.ok_or("Truncated MDF record")?;
+                    id[..id_size].copy_from_slice(bytes);
+                    pos += id_size;
+                    let id = u64::from_le_bytes(id);
+                    groups
+                        .iter_mut()
+                        .find(|g| g.record_id == id)
+                        .ok_or(format!("Unknown MDF record ID {id}"))?
+                }
+                _ => return Err("Invalid MDF record ID size".into()),
+            };
+            let size = match group.vlsd {
+                true => {
+                    4 + read_u32(&records, pos).ok_or("Truncated MDF record")?
+                        as usize
+                }
+                false => group.size,
+            };
+            let record =
+                records.get(pos..pos + size).ok_or("Truncated MDF record")?;
+            match group.vlsd {
+                true => group.signal_data.extend_from_slice(record),
+                false => group.records.push(record.to_vec()),
+            }
+            pos += size;
+        }
+
+        let signal_data: HashMap<u64, Vec<u8>> = groups
+            .iter_mut()
+            .filter(|g| g.vlsd)
+            .map(|g| (g.address, std::mem::take(&mut g.signal_data)))
+            .collect();
+        for group in groups.iter() {
+            self.read_frames(file, group, &signal_data)?;
+        }
+        Ok(())
+    }
+
+    /// Add the packets of a bus logging group, ignoring other groups
+    fn read_frames(
+        &mut self,
+        file: &[u8],
+        group: &Group,
+        signal_data: &HashMap<u64, Vec<u8>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(kind) = group.kind else {
+            return Ok(());
+        };
+        let channel =
+            |name: &str| group.channels.iter().find(|c| c.name == name);
+        let master = group.channels.iter().find(|c| {
+            matches!(c.channel_type, CN_MASTER | CN_VIRTUAL_MASTER)
+                && c.sync_type == SYNC_TIME
+        });
+        let master = master.ok_or("MDF bus logging group without time")?;
+        let (bus, id, ide) =
+            (channel("BusChannel"), channel("ID"), channel("IDE"));
+        let (dlc, length) = (channel("DLC"), channel("DataLength"));
+        let data_bytes = channel("DataBytes");
+
+        // variable length payloads, in signal data or a group
+        let payloads = match data_bytes {
+            Some(c) if c.channel_type == CN_VLSD => {
+                match signal_data.get(&c.data) {
+                    Some(data) => data.clone(),
+                    None => data(file, c.data, &mut HashSet::new())?,
+                }
+            }
+            _ => vec![],
+        };
+
+        for (number, record) in group.records.iter().enumerate() {
+            let invalid = || format!("Invalid MDF record {number}");
+            let seconds = match master.channel_type {
+                CN_VIRTUAL_MASTER => master.conversion.apply(number as f64),
+                _ => master.value(record).ok_or_else(invalid)?,
+            };
+            let time = Duration::try_from_secs_f64(seconds.max(0.0))
+                .ok()
+                .and_then(|offset| self.start_time.checked_add(offset))
+                .ok_or_else(invalid)?;
+            let mut packet = Packet {
+                kind,
+                time: Some(time),
+                ..Default::default()
+            };
+            let bus = match bus {
+                Some(bus) => bus.raw(record).ok_or_else(invalid)?,
+                None => 1,
+            };
+            packet.source = self.source(bus.to_string());
+
+            if kind != Kind::Error {
+                let raw =
+                    id.and_then(|id| id.raw(record)).ok_or_else(invalid)?;
+                packet.extended = match ide {
+                    Some(ide) => ide.raw(record).ok_or_else(invalid)? != 0,
+                    None => raw & IDE_FLAG != 0,
+                };
+                packet.id = (raw & !IDE_FLAG) as u32;
+            }
+            if kind == Kind::Data {
+                let bytes = match data_bytes {
+                    Some(c) if c.channel_type == CN_VLSD => {
+                        let offset =
+                            c.raw(record).ok_or_else(invalid)? as usize;
+                        let size =
+                            read_u32(&payloads, offset).ok_or_else(invalid)?;
+                        payloads.get(offset + 4..offset + 4 + size as usize)
+                    }
+                    Some(c) => c.bytes(record),
+                    None => Some(&[][..]),
+                };
+                let bytes = bytes.ok_or_else(invalid)?;
+                let count = match (length, dlc) {
+                    (Some(length), _) => length.raw(record),
+                    (None, Some(dlc)) => dlc
+                        .raw(record)
+                        .and_then(|dlc| dlc_length(dlc as usize))
+                        .map(|l| l as u64),
+                    (None, None) => Some(bytes.len() as u64),
+                };
+                let count = count.ok_or_else(invalid)? as usize;
+                packet.bytes = bytes[..count.min(bytes.len())].to_vec();
+            }
+            self.packets.push(packet);
+        }
+        Ok(())
+    }
+
+    /// Source index of a bus channel
+    fn source(&mut self, channel: String) -> usize {
+        let position = match self.channels.iter().position(|c| *c == channel) {
+            Some(position) => position,
+            None => {
+                self.channels.push(channel);
+                self.channels.len() - 1
+            }
+        };
+        self.index + position
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    /// Bus channel numbers, by source index from the first
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Start of the measurement, the time of packets relative to it
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// File built from its blocks, linked to blocks written before them
+    struct Writer {
+        file: Vec<u8>,
+    }
+
+    impl Writer {
+        fn new(start_ns: u64) -> Self {
+            let mut file = b"MDF     4.10    candor  ".to_vec();
+            file.resize(28, 0);
+            file.extend(410u16.to_le_bytes());
+            file.resize(64, 0);
+            let mut writer = Self { file };
+            let mut header = start_ns.to_le_bytes().to_vec();
+            header.resize(32, 0);
+            writer.block(b"HD", &[0; 6], &header);
+            writer
+        }
+
+        fn block(&mut self, id: &[u8; 2], links: &[u64], data: &[u8]) -> u64 {
+            self.file.resize(self.file.len().next_multiple_of(8), 0);
+            let address = self.file.len() as u64;
+            let length = BLOCK_HEADER_SIZE + links.len() * 8 + data.len();
+            self.file.extend(b"##");
+            self.file.extend(id);
+            self.file.extend([0; 4]);
+            self.file.extend((length as u64).to_le_bytes());
+            self.file.extend((links.len() as u64).to_le_bytes());
+            links.iter().for_each(|l| self.file.extend(l.to_le_bytes()));
+            self.file.extend(data);
+            address
+        }
+
+        fn text(&mut self, text: &str) -> u64 {
+            self.block(b"TX", &[], format!("{text}\0").as_bytes())
+        }
+
+        /// Linear conversion
+        fn conversion(&mut self, offset: f64, factor: f64) -> u64 {
+            let mut data = vec![CC_LINEAR, 0, 0, 0, 0, 0, 2, 0];
+            data.resize(24, 0);
+            data.extend(offset.to_le_bytes());
+            data.extend(factor.to_le_bytes());
+            self.block(b"CC", &[0; 4], &data)
+        }
+
+        /// Channel of `[type, sync type, data type, bit offset]`, with the
+        /// composition, conversion and signal data given as `links`
+        fn channel(
+            &mut self,
+            next: u64,
+            name: &str,
+            types: [u8; 4],
+            byte_offset: u32,
+            bit_count: u32,
+            [composition, conversion, data]: [u64; 3],
+        ) -> u64 {
+            let name = self.text(name);
+            let mut block = types.to_vec();
+            block.extend(byte_offset.to_le_bytes());
+            block.extend(bit_count.to_le_bytes());
+            block.resize(72, 0);
+            let links = [next, composition, name, 0, conversion, data, 0, 0];
+            self.block(b"CN", &links, &block)
+        }
+
+        fn group(
+            &mut self,
+            next: u64,
+            channels: u64,
+            name: &str,
+            record_id: u64,
+            flags: u16,
+            size: u32,
+        ) -> u64 {
+            let name = match name.is_empty() {
+                true => 0,
+                false => self.text(name),
+            };
+            let mut data = record_id.to_le_bytes().to_vec();
+            data.resize(16, 0);
+            data.extend(flags.to_le_bytes());
+            data.resize(24, 0);
+            data.extend(size.to_le_bytes());
+            data.extend([0; 4]);
+            self.block(b"CG", &[next, channels, name, 0, 0, 0], &data)
+        }
+
+        fn finish(mut self, first_group: u64) -> Vec<u8> {
+            let link = HEADER_ADDRESS as usize + BLOCK_HEADER_SIZE;
+            self.file[link..link + 8]
+                .copy_from_slice(&first_group.to_le_bytes());
+            self.file
+        }
+    }
+
+    fn frame(
+        id: u8,
+        micros: u32,
+        bus: u8,
+        can_id: u32,
+        dlc: u8,
+        length: u8,
+        offset: u64,
+    ) -> Vec<u8> {
+        let mut record = vec![id];
+        record.extend(micros.to_le_bytes());
+        record.push(bus);
+        record.extend(can_id.to_le_bytes());
+        record.extend([0xf0 | dlc, length]);
+        record.extend(offset.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut mdf = Writer::new(0);
+        // list linking to itself
+        let address = mdf.file.len().next_multiple_of(8) as u64;
+        let list = mdf.block(b"DL", &[0, address], &[0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(list, address);
+        assert!(data(&mdf.file, list, &mut HashSet::new()).is_err());
+
+        // compressed length beyond the address space
+        let mut header = b"DT".to_vec();
+        header.resize(16, 0);
+        header.extend(u64::MAX.to_le_bytes());
+        let zipped = mdf.block(b"DZ", &[], &header);
+        assert!(data(&mdf.file, zipped, &mut HashSet::new()).is_err());
+
+        // structure channel composed of itself
+        let mut channels = vec![];
+        let address = mdf.file.len().next_multiple_of(8) as u64;
+        let links = [0, address, 0, 0, 0, 0, 0, 0];
+        let structure = mdf.block(b"CN", &links, &[0; 72]);
+        let read = Channel::read_all(
+            &mdf.file,
+            structure,
+            &mut channels,
+            &mut HashSet::new(),
+        );
+        assert!(read.is_err());
+
+        // bit offset beyond the first byte
+        let channel = mdf.channel(0, "ID", [0, 0, 0, 8], 0, 8, [0; 3]);
+        let read = Channel::read_all(
+            &mdf.file,
+            channel,
+            &mut channels,
+            &mut HashSet::new(),
+        );
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn parse() {
+        let start_ns = 1_700_000_000_123_456_789;
+        let mut mdf = Writer::new(start_ns);
+        const UINT: u8 = 0;
+        const BYTES: u8 = 10;
+
+        // sorted error frames, transposed and compressed
+        let bus = mdf.channel(0, "BusChannel", [0, 0, UINT, 0], 8, 8, [0; 3]);
+        let time = [CN_MASTER, SYNC_TIME, FLOAT_LE, 0];
+        let time = mdf.channel(bus, "Timestamp", time, 0, 64, [0; 3]);
+        let errors = mdf.group(0, time, "CAN_ErrorFrame", 0, 0, 9);
+        let mut records = vec![];
+        for (seconds, bus) in [(0.0025f64, 1u8), (1.0, 3)] {
+            records.extend(seconds.to_le_bytes());
+            records.push(bus);
+        }
+        let mut transposed = vec![0; records.len()];
+        for row in 0..2 {
+            for column in 0..9 {
+                transposed[column * 2 + row] = records[row * 9 + column];
+            }
+        }
+        let compressed =
+            miniz_oxide::deflate::compress_to_vec_zlib(&transposed, 6);
+        let mut dz = b"DT".to_vec();
+        dz.extend([DZ_TRANSPOSED, 0]);
+        dz.extend(9u32.to_le_bytes());
+        dz.extend((records.len() as u64).to_le_bytes());
+        dz.extend((compressed.len() as u64).to_le_bytes());
+        dz.extend(compressed);
+        let dz = mdf.block(b"DZ", &[], &dz);
+        let second = mdf.block(b"DG", &[0, errors, dz, 0], &[0; 8]);
+
+        // unsorted data and remote frames, with payloads in a group
+        let payloads = mdf.group(0, 0, "", 3, CG_VLSD, 0);
+        let micros = mdf.conversion(0.0, 1e-6);
+        let dlc = mdf.channel(0, "DLC", [0, 0, UINT, 0], 9, 4, [0; 3]);
+        let ide = mdf.channel(dlc, "IDE", [0, 0, UINT, 5], 8, 1, [0; 3]);
+        let id = mdf.channel(ide, "ID", [0, 0, UINT, 0], 5, 29, [0; 3]);
+        let bus = mdf.channel(id, "BusChannel", [0, 0, UINT, 0], 4, 8, [0; 3]);
+        let remote = [0, 0, BYTES, 0];
+        let remote =
+            mdf.channel(0, "CAN_RemoteFrame", remote, 4, 48, [bus, 0, 0]);
+        let time = [CN_MASTER, SYNC_TIME, UINT, 0];
+        let time =
+            mdf.channel(remote, "Timestamp", time, 0, 32, [0, micros, 0]);
+        let remotes = mdf.group(payloads, time, "CAN_RemoteFrame", 2, 0, 10);
+
+        let bytes = [0, 0, payloads];
+        let bytes = mdf.channel(
+            0,
+            "CAN_DataFrame.DataBytes",
+            [CN_VLSD, 0, BYTES, 0],
+            11,
+            64,
+            bytes,
+        );
+        let length = mdf.channel(
+            bytes,
+            "CAN_DataFrame.DataLength",
+            [0, 0, UINT, 0],
+            10,
+            8,
+            [0; 3],
+        );
+        let dlc = mdf.channel(
+            length,
+            "CAN_DataFrame.DLC",
+            [0, 0, UINT, 0],
+            9,
+            4,
+            [0; 3],
+        );
+        let id = mdf.channel(
+            dlc,
+            "CAN_DataFrame.ID",
+            [0, 0, UINT, 0],
+            5,
+            32,
+            [0; 3],
+        );
+        let bus = mdf.channel(
+            id,
+            "CAN_DataFrame.BusChannel",
+            [0, 0, UINT, 0],
+            4,
+            8,
+            [0; 3],
+        );
+        let frame_type = [0, 0, BYTES, 0];
+        let frame_channel =
+            mdf.channel(0, "CAN_DataFrame", frame_type, 4, 120, [bus, 0, 0]);
+        let time = [CN_MASTER, SYNC_TIME, UINT, 0];
+        let time = mdf.channel(
+            frame_channel,
+            "Timestamp",
+            time,
+            0,
+            32,
+            [0, micros, 0],
+        );
+        let frames = mdf.group(remotes, time, "", 1, 0, 19);
+
+        let mut records = frame(1, 1000, 1, 0x123, 3, 3, 0);
+        records.extend([3, 3, 0, 0, 0, 1, 2, 3]);
+        let mut remote = frame(2, 2000, 2, 0x18fef100, 4, 0, 0);
+        remote[9] |= 0x20; // IDE
+        records.extend(&remote[..11]);
+        records.extend([3, 12, 0, 0, 0]);
+        records.extend(1..=12);
+        records.extend(frame(1, 3000, 2, 0x101 | IDE_FLAG as u32, 9, 12, 7));
+        let first = mdf.block(b"DT", &[], &records[..30]);
+        let rest = mdf.block(b"DT", &[], &records[30..]);
+        let mut list = vec![0; 4];
+        list.extend(2u32.to_le_bytes());
+        list.extend(0u64.to_le_bytes());
+        list.extend(30u64.to_le_bytes());
+        let list = mdf.block(b"DL", &[0, first, rest], &list);
+        let first = mdf.block(b"DG", &[second, frames, list, 0], &[1; 8]);
+        let file = mdf.finish(first);
+
+        let data = Mdf4Parser::new_from_bytes(&file, 1).unwrap();
+        assert_eq!(data.channels(), ["1", "2", "3"]);
+        assert_eq!(data.start(), UNIX_EPOCH + Duration::from_nanos(start_ns));
+        let packets = data.packets();
+        assert_eq!(packets.len(), 5);
+
+        assert_eq!(packets[0].source, 1);
+        assert_eq!(packets[0].id, 0x123);
+        assert!(!packets[0].extended);
+        assert_eq!(packets[0].bytes, [1, 2, 3]);
+
+        assert_eq!(packets[1].source, 2);
+        assert_eq!(packets[1].kind, Kind::Remote);
+        assert_eq!(packets[1].id, 0x18fef100);
+        assert!(packets[1].extended);
+        assert!(packets[1].bytes.is_empty());
+
+        assert_eq!(packets[2].source, 1);
+        assert_eq!(packets[2].kind, Kind::Error);
+
+        assert_eq!(packets[3].source, 2);
+        assert_eq!(packets[3].id, 0x101);
+        assert!(packets[3].extended);
+        assert_eq!(packets[3].bytes, (1..=12).collect::<Vec<u8>>());
+
+        assert_eq!(packets[4].source, 3);
+        assert_eq!(
+            packets[4].time.unwrap() - packets[0].time.unwrap(),
+            Duration::from_millis(999)
+        );
+
+        assert!(Mdf4Parser::new_from_bytes(b"MDF     3.30    ", 0).is_err());
+        let mut corrupt = file.clone();
+        corrupt[first as usize + 2] = b'X';
+        assert!(Mdf4Parser::new_from_bytes(&corrupt, 0).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Replay of recorded packets as a source
+
+use crate::{Control, Source, SourceError, Status};
+use candor::Packet;
+
+use std::{
+    io,
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+    time::Instant,
+};
+
+/// Source sending recorded packets with their original timing, repeating
+/// from the start when the end is reached
+pub struct Replay {
+    name: String,
+    baud: u32,
+    index: usize,
+    packets: Arc<Vec<Packet>>,
+    start: Option<Instant>,
+    end: Option<Instant>,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+    control: Control,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Replay {
+    pub fn new(
+        name: &str,
+        index: usize,
+        baud: u32,
+        packets: Vec<Packet>,
+        tx: mpsc::Sender<Packet>,
+        errors: mpsc::Sender<SourceError>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            baud,
+            index,
+            packets: Arc::new(packets),
+            start: None,
+            end: None,
+            tx,
+            errors,
+            control: Control::default(),
+            thread: None,
+        }
+    }
+
+    /// Replays of a recording of several buses, one per bus, with the
+    /// packets of each bus using consecutive source indices from `index`
+    pub fn split(
+        name: &str,
+        index: usize,
+        baud: u32,
+        packets: Vec<Packet>,
+        buses: &[String],
+        tx: mpsc::Sender<Packet>,
+        errors: mpsc::Sender<SourceError>,
+    ) -> Vec<Self> {
+        let start = packets.iter().filter_map(|p| p.time).min();
+        let end = packets.iter().filter_map(|p| p.time).max();
+        let mut split = vec![vec![]; buses.len()];
+        for packet in packets {
+            let bus = packet.source.checked_sub(index);
+            if let Some(packets) = bus.and_then(|bus| split.get_mut(bus)) {
+                packets.push(packet);
+            }
+        }
+        split
+            .into_iter()
+            .zip(buses)
+            .enumerate()
+            .map(|(i, (packets, bus))| {
+                let name = match buses.len() {
+                    1 => name.to_string(),
+                    _ => format!("{name}:{bus}"),
+                };
+                let mut replay = Self::new(
+                    &name,
+                    index + i,
+                    baud,
+                    packets,
+                    tx.clone(),
+                    errors.clone(),
+                );
+                // buses repeat together
+                if let (Some(start), Some(end)) = (start, end) {
+                    replay.set_span(start, end);
+                }
+                replay
+            })
+            .collect()
+    }
+
+    /// Set the times the recording starts and ends, if before the first
+    /// and after the last packet, such as for replays of parts of a
+    /// recording to repeat together
+    pub fn set_span(&mut self, start: Instant, end: Instant) {
+        self.start = Some(start);
+        self.end = Some(end);
+    }
+}
+
+fn replay(
+    packets: &[Packet],
+    (start, end): (Option<Instant>, Option<Instant>),
+    index: usize,
+    tx: &mpsc::Sender<Packet>,
+    errors: &mpsc::Sender<SourceError>,
+    control: &Control,
+) {
+    if packets.iter().any(|p| p.time.is_none()) {
+        let text = "Recording without timestamps".to_string();
+        control.fail(errors, index, Status::Error, text);
+        return;
+    }
+    if packets.is_empty() {
+        control.set_status(Status::Ended);
+        return;
+    }
+    let first = start.or(packets[0].time).unwrap();
+    let last = end.or(packets[packets.len() - 1].time).unwrap();
+    // replay time of the start of the recording
+    let mut base = Instant::now();
+    loop {
+        for packet in packets.iter() {
+            let time = packet.time.unwrap().saturating_duration_since(first);
+            if !control.wait_until(base + time) {
+                return;
+            }
+            match control.hold() {
+                Some(paused) => base += paused,
+                None => return,
+            }
+            let mut packet = packet.clone();
+            packet.time = Some(Instant::now());
+            if tx.send(packet).is_err() {
+                // nobody left to receive
+                control.set_status(Status::Ended);
+                return;
+            }
+        }
+        // the next loop starts where this one ends, without drifting
+        base += last.saturating_duration_since(first);
+        if !control.wait_until(base) {
+            return;
+        }
+    }
+}
+
+impl Source for Replay {
+    fn name(&self) -> String {
+        let path = Path::new(&self.name);
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    fn live(&self) -> bool {
+        false
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        self.stop();
+        self.control.start();
+        let packets = self.packets.clone();
+        let (tx, errors) = (self.tx.clone(), self.errors.clone());
+        let control = self.control.clone();
+        let (span, index) = ((self.start, self.end), self.index);
+        self.thread = Some(thread::spawn(move || {
+            replay(&packets, span, index, &tx, &errors, &control)
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.control.stop(self.thread.take());
+    }
+
+    fn pause(&mut self, paused: bool) {
+        self.control.pause(paused);
+    }
+
+    fn status(&self) -> Status {
+        self.control.status()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn lifecycle() {
+        let start = Instant::now();
+        let packets = (0..3u64)
+            .map(|i| Packet {
+                id: i as u32,
+                time: Some(start + Duration::from_millis(i * 5)),
+                ..Default::default()
+            })
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        let (errors, _) = mpsc::channel();
+        let mut replay =
+            Replay::new("dir/log.trc", 1, 500000, packets, tx, errors);
+        assert_eq!(replay.name(), "log.trc");
+        assert!(!replay.live());
+        assert_eq!(replay.status(), Status::Stopped);
+
+        replay.start().unwrap();
+        assert_eq!(replay.status(), Status::Running);
+        let ids: Vec<u32> = rx.iter().take(4).map(|p| p.id).collect();
+        assert_eq!(ids, vec![0, 1, 2, 0]);
+
+        replay.pause(true);
+        assert_eq!(replay.status(), Status::Paused);
+        replay.pause(false);
+        assert_eq!(replay.status(), Status::Running);
+
+        replay.stop();
+        assert_eq!(replay.status(), Status::Stopped);
+        while rx.try_recv().is_ok() {}
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+
+        let (tx, _) = mpsc::channel();
+        let (errors, errors_rx) = mpsc::channel();
+        let packet = Packet::default();
+        let mut replay = Replay::new("log", 2, 0, vec![packet], tx, errors);
+        replay.start().unwrap();
+        let error = errors_rx.recv().unwrap();
+        assert_eq!(error.source, 2);
+        assert_eq!(error.status, Status::Error);
+        replay.stop();
+        assert_eq!(replay.status(), Status::Error);
+    }
+
+    #[test]
+    fn timing() {
+        // recorded long before the replay starts
+        let start = Instant::now() - Duration::from_secs(10);
+        let packets = (0..3u64)
+            .map(|i| Packet {
+                id: i as u32,
+                time: Some(start + Duration::from_millis(i * 5)),
+                ..Default::default()
+            })
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        let (errors, _) = mpsc::channel();
+        let mut replay = Replay::new("log", 0, 0, packets, tx, errors);
+        replay.start().unwrap();
+        let times: Vec<Instant> =
+            rx.iter().take(6).filter_map(|p| p.time).collect();
+        assert!(times[2] - times[0] >= Duration::from_millis(10));
+        assert!(times[5] - times[0] >= Duration::from_millis(20));
+        replay.stop();
+    }
+}
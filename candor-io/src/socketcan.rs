@@ -1,14 +1,24 @@
-use crate::Source;
-use candor::Packet;
-use socketcan::{
-    BlockingCan, CanInterface, CanSocket, EmbeddedFrame, Frame, Socket,
+use crate::{Control, Source, SourceError, Status};
+use candor::{Kind, Packet};
+use socketcan::{CanInterface, CanSocket, EmbeddedFrame, Frame, Socket};
+use std::{
+    io,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
-use std::{io, sync::mpsc, thread, time::Instant};
 
-#[derive(Default, Clone)]
+/// Interval at which a receiving thread checks whether to stop
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
 pub struct SocketCanSource {
     name: String,
     baud: u32,
+    index: usize,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+    control: Control,
+    thread: Option<thread::JoinHandle<()>>,
 }
 
 impl SocketCanSource {
@@ -17,6 +27,7 @@ impl SocketCanSource {
         index: usize,
         default_baud: u32,
         tx: mpsc::Sender<Packet>,
+        errors: mpsc::Sender<SourceError>,
     ) -> io::Result<Self> {
         let iface = CanInterface::open(name)?;
         let bit_rate = iface.bit_rate();
@@ -26,30 +37,70 @@ impl SocketCanSource {
             default_baud
         };
 
-        let mut rx = CanSocket::open(name)?;
-
-        thread::spawn(move || {
-            while let Ok(res) = rx.receive() {
-                let packet = Packet {
-                    source: index,
-                    time: Some(Instant::now()),
-                    extended: res.is_extended(),
-                    id: res.raw_id(),
-                    bytes: res.data().to_vec(),
-                };
-                if tx.send(packet).is_err() {
-                    println!("Error sending frame event");
-                }
-            }
-        });
-
         Ok(Self {
             name: name.to_string(),
             baud,
+            index,
+            tx,
+            errors,
+            control: Control::default(),
+            thread: None,
         })
     }
 }
 
+fn receive(
+    rx: CanSocket,
+    index: usize,
+    tx: &mpsc::Sender<Packet>,
+    errors: &mpsc::Sender<SourceError>,
+    control: &Control,
+) {
+    while !control.stopped() {
+        let frame = match rx.read_frame() {
+            Ok(frame) => frame,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                let status = match e.kind() {
+                    io::ErrorKind::NetworkDown => Status::Disconnected,
+                    _ => Status::Error,
+                };
+                control.fail(errors, index, status, e.to_string());
+                return;
+            }
+        };
+        // keep reading while paused, so no backlog builds up
+        if control.paused() {
+            continue;
+        }
+        let packet = Packet {
+            source: index,
+            time: Some(Instant::now()),
+            extended: frame.is_extended(),
+            id: frame.raw_id(),
+            bytes: frame.data().to_vec(),
+            kind: if frame.is_error_frame() {
+                Kind::Error
+            } else if frame.is_remote_frame() {
+                Kind::Remote
+            } else {
+                Kind::Data
+            },
+        };
+        if tx.send(packet).is_err() {
+            // nobody left to receive
+            return;
+        }
+    }
+}
+
 impl Source for SocketCanSource {
     fn name(&self) -> String {
         self.name.clone()
@@ -58,4 +109,34 @@ impl Source for SocketCanSource {
     fn baud(&self) -> u32 {
         self.baud
     }
+
+    fn live(&self) -> bool {
+        true
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        self.stop();
+        let rx = CanSocket::open(&self.name)?;
+        rx.set_read_timeout(READ_TIMEOUT)?;
+        self.control.start();
+        let (tx, errors) = (self.tx.clone(), self.errors.clone());
+        let control = self.control.clone();
+        let index = self.index;
+        self.thread = Some(thread::spawn(move || {
+            receive(rx, index, &tx, &errors, &control)
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.control.stop(self.thread.take());
+    }
+
+    fn pause(&mut self, paused: bool) {
+        self.control.pause(paused);
+    }
+
+    fn status(&self) -> Status {
+        self.control.status()
+    }
 }
@@ -1,85 +1,38 @@
-use crate::Source;
-use candor::Packet;
+use crate::{dlc_length, length_dlc, replay::Replay, Sink, SourceError};
+use candor::{Kind, Packet};
 
-use std::{f32, u32, u8};
 use std::{
     fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
+    io::{self, BufRead, BufReader, BufWriter, Write},
     sync::mpsc,
-    thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use std::error::Error;
 
-pub struct TrcSource {
-    name: String,
-    baud: u32,
+/// Replays of the buses of a trace, in order of appearance, with
+/// consecutive indices starting at `index`
+pub fn open(
+    name: &str,
+    index: usize,
+    default_baud: u32,
+    sync_time: bool,
+    tx: mpsc::Sender<Packet>,
+    errors: mpsc::Sender<SourceError>,
+) -> Result<Vec<Replay>, Box<dyn Error>> {
+    let file = TrcParser::new_from_file(name, index, sync_time)?;
+    Ok(Replay::split(
+        name,
+        index,
+        default_baud,
+        file.packets,
+        &file.buses,
+        tx,
+        errors,
+    ))
 }
 
-impl TrcSource {
-    pub fn new(
-        name: &str,
-        index: usize,
-        default_baud: u32,
-        sync_time: bool,
-        tx: mpsc::Sender<Packet>,
-    ) -> Result<Self, Box<dyn Error>> {
-        let file = TrcParser::new_from_file(name, index, sync_time)?;
-        thread::spawn(move || {
-            let count = file.packets.len();
-            let mut index = 0;
-            let start_time = Instant::now();
-            let mut sleep_time = start_time;
-            let mut offset = Duration::default();
-            loop {
-                let mut packet = file.packets.get(index).unwrap().clone();
-                let time = packet.time.unwrap() + offset;
-                let delta = time - sleep_time;
-
-                packet.time = Some(Instant::now());
-
-                if tx.send(packet).is_err() {
-                    println!("Error sending frame event");
-                }
-
-                if delta >= Duration::from_millis(0) {
-                    thread::sleep(delta);
-                    sleep_time = Instant::now();
-                }
-
-                index += 1;
-                if index >= count {
-                    index = 0;
-                    offset = Instant::now() - start_time;
-                    //                    break; // DEBUG: stop upon wrap
-                }
-            }
-        });
-        Ok(Self {
-            name: name.to_string(),
-            baud: default_baud,
-        })
-    }
-}
-
-impl Source for TrcSource {
-    fn name(&self) -> String {
-        let path = Path::new(&self.name);
-        path.file_name()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap()
-            .to_owned()
-    }
-
-    fn baud(&self) -> u32 {
-        self.baud
-    }
-}
-
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum TrcVersion {
     V1_0,
     V1_1,
@@ -92,6 +45,7 @@ pub enum TrcVersion {
 pub struct TrcParser {
     packets: Vec<Packet>,
     version: TrcVersion,
+    buses: Vec<String>,
 }
 
 impl TrcParser {
@@ -121,12 +75,15 @@ impl TrcParser {
         )
     }
 
+    /// Parse trace lines, with the packets of each bus using the next
+    /// source index starting at `index`
     pub fn new_from_lines(
         lines: Vec<String>,
         index: usize,
         sync_time: bool,
     ) -> Result<Self, Box<dyn Error>> {
         let mut packets: Vec<Packet> = Vec::with_capacity(lines.len());
+        let mut buses: Vec<String> = vec![];
         let start_time = Instant::now();
         let mut first_time: Option<u64> = None;
 
@@ -168,8 +125,8 @@ impl TrcParser {
                 }
 
                 fn float_ns(s: &str) -> Result<u64, Box<dyn Error>> {
-                    let ms = s.parse::<f32>()? as u64;
-                    Ok(ms * 1000000)
+                    let ms = s.parse::<f64>()?;
+                    Ok((ms * 1e6).round() as u64)
                 }
 
                 fn int_ns(s: &str) -> Result<u64, Box<dyn Error>> {
@@ -193,7 +150,7 @@ impl TrcParser {
                             (true, true) => 7,
                         };
                         if cols.len() < 6
-                            || cols[dlc + 1] == "RTR"
+                            || cols.get(dlc + 1).is_some_and(|c| c == "RTR")
                             || (cols[2] != "DT" && cols[2] != "FD")
                         {
                             continue;
@@ -225,7 +182,16 @@ impl TrcParser {
                     continue;
                 }
 
-                let dlc = usize::from_str_radix(&cols[dlc_col], 16)?;
+                let dlc = match version {
+                    TrcVersion::V2_0 | TrcVersion::V2_1 => {
+                        let value = cols[dlc_col].parse::<usize>()?;
+                        match columns.contains("L") {
+                            true => dlc_length(value).ok_or("Invalid DLC")?,
+                            false => value,
+                        }
+                    }
+                    _ => usize::from_str_radix(&cols[dlc_col], 16)?,
+                };
                 let data_col = dlc_col + 1;
                 if cols.len() < data_col + dlc
                     || (dlc > 0 && cols[data_col] == "RTR")
@@ -233,6 +199,24 @@ impl TrcParser {
                     continue;
                 }
 
+                // bus column of 1.3, and of 2.x if listed in the columns
+                let bus = match version {
+                    TrcVersion::V1_3 => &cols[2],
+                    TrcVersion::V2_0 | TrcVersion::V2_1
+                        if columns.contains("B") =>
+                    {
+                        &cols[3]
+                    }
+                    _ => "1",
+                };
+                let source = match buses.iter().position(|b| b == bus) {
+                    Some(position) => position,
+                    None => {
+                        buses.push(bus.to_string());
+                        buses.len() - 1
+                    }
+                };
+
                 let mut bytes: Vec<u8> = Vec::with_capacity(dlc);
                 for i in 0..dlc {
                     bytes.push(
@@ -242,16 +226,216 @@ impl TrcParser {
                 }
 
                 packets.push(Packet {
-                    source: index,
+                    source: index + source,
                     time: Some(start_time + Duration::from_nanos(time_ns)),
                     extended: cols[id_col].len() > 4,
                     id,
                     bytes,
+                    ..Default::default()
                 });
             }
         }
 
-        Ok(Self { packets, version })
+        // a trace without packets still replays one bus
+        if buses.is_empty() {
+            buses.push("1".to_string());
+        }
+        Ok(Self {
+            packets,
+            version,
+            buses,
+        })
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    /// Bus numbers, by source index from the first
+    pub fn buses(&self) -> &[String] {
+        &self.buses
+    }
+
+    pub fn version(&self) -> TrcVersion {
+        self.version
+    }
+}
+
+/// Days from the 1899-12-30 epoch of `;$STARTTIME` to the Unix epoch
+const STARTTIME_UNIX_DAYS: f64 = 25569.0;
+
+/// Writer of PEAK trace files, version 1.1, 2.0 or 2.1
+///
+/// Times are written relative to the first packet; version 2.1 writes the
+/// bus as the packet's source plus one. Only data frames are written, all
+/// as received.
+pub struct TrcWriter<W: Write> {
+    writer: W,
+    version: TrcVersion,
+    count: usize,
+    start: Option<Instant>,
+    /// Wall clock time at a known instant, for `;$STARTTIME`
+    clock: (Instant, SystemTime),
+}
+
+impl TrcWriter<BufWriter<File>> {
+    pub fn create(filename: &str, version: TrcVersion) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(filename)?), version)
+    }
+}
+
+impl<W: Write> TrcWriter<W> {
+    pub fn new(writer: W, version: TrcVersion) -> io::Result<Self> {
+        match version {
+            TrcVersion::V1_1 | TrcVersion::V2_0 | TrcVersion::V2_1 => {
+                Ok(Self {
+                    writer,
+                    version,
+                    count: 0,
+                    start: None,
+                    clock: (Instant::now(), SystemTime::now()),
+                })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Writing TRC version {version:?} not supported"),
+            )),
+        }
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self, start: Instant) -> io::Result<()> {
+        let (instant, system) = self.clock;
+        let start = match start > instant {
+            true => system + (start - instant),
+            false => system - (instant - start),
+        };
+        let days = start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+            / 86400.0
+            + STARTTIME_UNIX_DAYS;
+        let version = match self.version {
+            TrcVersion::V1_1 => "1.1",
+            TrcVersion::V2_0 => "2.0",
+            _ => "2.1",
+        };
+        let w = &mut self.writer;
+        writeln!(w, ";$FILEVERSION={version}")?;
+        writeln!(w, ";$STARTTIME={days:.10}")?;
+        match self.version {
+            TrcVersion::V1_1 => write!(
+                w,
+                r#";
+;   Generated by CANdor
+;
+;   Message Number
+;   |         Time Offset (ms)
+;   |         |        Type
+;   |         |        |        ID (hex)
+;   |         |        |        |     Data Length
+;   |         |        |        |     |   Data Bytes (hex) ...
+;   |         |        |        |     |   |
+;---+--   ----+----  --+--  ----+---  +  -+ -- -- -- -- -- -- --
+"#
+            ),
+            TrcVersion::V2_0 => write!(
+                w,
+                r#";$COLUMNS=N,O,T,I,d,l,D
+;
+;   Generated by CANdor
+;
+;   Message   Time    Type ID     Rx/Tx
+;   Number    Offset  |    [hex]  |  Data Length
+;   |         [ms]    |    |      |  |  Data [hex] ...
+;   |         |       |    |      |  |  |
+;---+-- ------+------ +- --+----- +- +- +- -- -- -- -- -- -- --
+"#
+            ),
+            _ => write!(
+                w,
+                r#";$COLUMNS=N,O,T,B,I,d,R,L,D
+;
+;   Generated by CANdor
+;
+;   Message   Time    Type    ID     Rx/Tx
+;   Number    Offset  |  Bus  [hex]  |  Reserved
+;   |         [ms]    |  |    |      |  |  Data Length Code
+;   |         |       |  |    |      |  |  |    Data [hex] ...
+;   |         |       |  |    |      |  |  |    |
+;---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --
+"#
+            ),
+        }
+    }
+}
+
+impl<W: Write> Sink for TrcWriter<W> {
+    fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        if packet.kind != Kind::Data {
+            return Ok(());
+        }
+        let invalid =
+            |text: &str| io::Error::new(io::ErrorKind::InvalidInput, text);
+        let time = packet.time.unwrap_or_else(Instant::now);
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.write_header(time)?;
+                *self.start.insert(time)
+            }
+        };
+        let length = packet.bytes.len();
+        let dlc = match (self.version, length_dlc(length)) {
+            (TrcVersion::V1_1, _) if length > 8 => {
+                return Err(invalid("CAN FD not supported by TRC 1.1"))
+            }
+            (_, Some(dlc)) => dlc,
+            (_, None) => return Err(invalid("Invalid CAN FD length")),
+        };
+        let ms = time.saturating_duration_since(start).as_secs_f64() * 1000.0;
+        let id = match packet.extended {
+            true => format!("{:08X}", packet.id),
+            false => format!("{:04X}", packet.id),
+        };
+        let data: Vec<String> =
+            packet.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        let data = data.join(" ");
+        let kind = if length > 8 { "FD" } else { "DT" };
+        self.count += 1;
+        let n = self.count;
+        let w = &mut self.writer;
+        match self.version {
+            TrcVersion::V1_1 => {
+                writeln!(w, "{n:>6}) {ms:>11.1}  Rx     {id:>8}  {dlc}  {data}")
+            }
+            TrcVersion::V2_0 => {
+                writeln!(
+                    w,
+                    "{n:>7} {ms:>13.3} {kind} {id:>8} Rx {length:<2} {data}"
+                )
+            }
+            _ => {
+                let bus = packet.source + 1;
+                writeln!(
+                    w,
+                    "{n:>7} {ms:>13.3} {kind} {bus:<2} {id:>8} Rx -  {dlc:<4} {data}"
+                )
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.start.is_none() {
+            self.write_header(Instant::now())?;
+            self.start = Some(Instant::now());
+        }
+        self.writer.flush()
     }
 }
 
@@ -480,4 +664,103 @@ mod test {
         assert!(!data.packets[4].extended);
         assert_eq!(data.packets[9].bytes[7], 0xff);
     }
+
+    #[test]
+    fn buses() {
+        let trc = r#"
+;$FILEVERSION=1.3
+;$STARTTIME=44548.6028595139
+     1)        17535.4 2  Tx    00000103 -  8    00 00 00 00 00 00 00 00
+     2)        17700.3 1  Tx    00000100 -  8    00 00 00 00 00 00 00 00
+     3)        17873.8 2  Tx        0101 -  1    55
+"#;
+        let data = TrcParser::new_from_text(trc, 3, false).unwrap();
+        assert_eq!(data.buses(), ["2", "1"]);
+        let sources: Vec<usize> =
+            data.packets().iter().map(|p| p.source).collect();
+        assert_eq!(sources, [3, 4, 3]);
+
+        let trc = r#"
+;$FILEVERSION=2.1
+;$STARTTIME=44548.6028595139
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+      1     17535.400 DT 1  00000201 Tx -  8    02 00 01 00 00 00 00 00
+      2     17540.300 DT 3      0100 Rx -  2    01 02
+      3     17700.300 DT 1      0100 Tx -  0
+"#;
+        let data = TrcParser::new_from_text(trc, 0, false).unwrap();
+        assert_eq!(data.buses(), ["1", "3"]);
+        let sources: Vec<usize> =
+            data.packets().iter().map(|p| p.source).collect();
+        assert_eq!(sources, [0, 1, 0]);
+    }
+
+    #[test]
+    fn write_read() {
+        let start = Instant::now();
+        let packet =
+            |us: u64, extended: bool, id: u32, bytes: Vec<u8>| Packet {
+                source: 0,
+                time: Some(start + Duration::from_micros(us)),
+                extended,
+                id,
+                bytes,
+                ..Default::default()
+            };
+        let classic = vec![
+            packet(0, false, 0x123, vec![0xde, 0xad, 0xbe, 0xef]),
+            packet(1500, true, 0x18fef100, vec![1, 2, 3, 4, 5, 6, 7, 8]),
+            packet(2000, false, 0x7ff, vec![]),
+            packet(123456700, false, 0, vec![0xff]),
+        ];
+        let mut fd = classic.clone();
+        fd.push(packet(123456789, true, 0x100, (0..64).collect()));
+        fd.push(packet(123456790, false, 0x101, vec![0x55; 12]));
+        // version 2.1 keeps the bus of each packet
+        let mut buses = fd.clone();
+        for packet in buses.iter_mut().skip(1).step_by(2) {
+            packet.source = 1;
+        }
+
+        for (version, packets) in [
+            (TrcVersion::V1_1, &classic),
+            (TrcVersion::V2_0, &fd),
+            (TrcVersion::V2_1, &buses),
+        ] {
+            let mut writer = TrcWriter::new(vec![], version).unwrap();
+            for packet in packets.iter() {
+                writer.write(packet).unwrap();
+            }
+            let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+            let data = TrcParser::new_from_text(&text, 0, true).unwrap();
+            assert_eq!(data.version(), version);
+            assert_eq!(data.packets().len(), packets.len());
+            let buses = match version {
+                TrcVersion::V2_1 => vec!["1", "2"],
+                _ => vec!["1"],
+            };
+            assert_eq!(data.buses(), buses);
+
+            let first = data.packets()[0].time.unwrap();
+            for (read, written) in data.packets().iter().zip(packets.iter()) {
+                assert_eq!(
+                    read.time.unwrap() - first,
+                    written.time.unwrap() - start
+                );
+                assert_eq!(
+                    (read.source, read.extended, read.id, &read.bytes),
+                    (
+                        written.source,
+                        written.extended,
+                        written.id,
+                        &written.bytes
+                    )
+                );
+            }
+        }
+
+        let mut writer = TrcWriter::new(vec![], TrcVersion::V1_1).unwrap();
+        assert!(writer.write(&fd[4]).is_err());
+        assert!(TrcWriter::new(vec![], TrcVersion::V1_3).is_err());
+    }
 }
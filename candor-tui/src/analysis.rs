@@ -0,0 +1,231 @@
+//! Reverse-engineering views of undocumented messages
+
+use crate::{popup::Popup, App, Entry};
+use candor::analysis::{Correlation, Series};
+use candor::db::{dbc, score::Score, Database};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use ratatui::Frame;
+
+/// Packets a channel must have seen before a candidate database is
+/// attached automatically
+const ATTACH_PACKETS: u32 = 1000;
+/// Minimum score of a candidate database to attach automatically
+const ATTACH_SCORE: f64 = 0.5;
+
+/// Candidate databases with their file names
+pub(crate) type Candidates = Vec<(String, Database)>;
+/// Indices of the candidate databases with their score, best first
+pub(crate) type Scores = Vec<(usize, Score)>;
+
+impl App {
+    /// Load the database files of a directory, skipping those that fail
+    /// to load with a message for each
+    pub(crate) fn load_candidates(
+        directory: &str,
+    ) -> Result<(Candidates, Vec<String>), Box<dyn Error>> {
+        let mut candidates: Candidates = vec![];
+        let mut skipped = vec![];
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            if !["dbc", "kcd", "sym", "arxml"].contains(&extension.as_str()) {
+                continue;
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let filename = path.to_string_lossy();
+            match Database::from_file(&filename) {
+                Ok(database) => candidates.push((name.into(), database)),
+                Err(e) => skipped.push(format!("{name}: {e}")),
+            }
+        }
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        skipped.sort();
+        Ok((candidates, skipped))
+    }
+
+    /// Rank the candidate databases by their score on each channel
+    pub(crate) fn update_scores(&mut self) {
+        self.scores = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let mut scores: Scores = self
+                    .candidates
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, database))| {
+                        (index, channel.stats.score(database))
+                    })
+                    .collect();
+                scores.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+                scores
+            })
+            .collect();
+    }
+
+    /// Attach the best matching candidate database to channels without
+    /// database, once they have seen enough traffic
+    pub(crate) fn attach_candidates(&mut self) {
+        for channel in 0..self.channels.len() {
+            let stats = &self.channels[channel].stats;
+            if !stats.databases().is_empty() || stats.packets < ATTACH_PACKETS {
+                continue;
+            }
+            let best = self.scores.get(channel).and_then(|s| s.first());
+            let Some((index, score)) = best.copied() else {
+                continue;
+            };
+            if score.total >= ATTACH_SCORE {
+                let (name, database) = &self.candidates[index];
+                self.status = format!(
+                    "Attached {name} to {}",
+                    self.channels[channel].source.name()
+                );
+                let database = database.clone();
+                self.channels[channel].stats.add_database(database);
+            }
+        }
+    }
+
+    /// Popup with the candidate databases ranked for each channel
+    pub(crate) fn draw_scores(&mut self, frame: &mut Frame) {
+        let mut lines = vec![];
+        for channel in 0..self.channels.len() {
+            lines.push(self.channels[channel].source.name());
+            let scores = self.scores.get(channel).map_or(&[][..], |s| s);
+            for (index, score) in scores.iter().take(5) {
+                let name = &self.candidates[*index].0;
+                lines.push(format!("  {} {name}", score.text()));
+            }
+        }
+        let text = match self.candidates.is_empty() {
+            true => "No candidate databases (--candidates)".to_string(),
+            false => lines.join("\n"),
+        };
+        let popup = Popup::default()
+            .title(" Database Matches (M=close) ")
+            .content(text)
+            .preformatted(true);
+        let area = popup.fit(frame.area());
+        frame.render_widget(popup, area);
+    }
+
+    /// Channel and message index of the selected message view row
+    pub(crate) fn selected_message(&self) -> Option<(usize, usize)> {
+        let selected = self.table_state.selected()?;
+        match self.entries().into_iter().nth(selected)? {
+            Entry::Message(channel, index) => Some((channel, index)),
+            Entry::Node(..) => None,
+        }
+    }
+
+    /// Reference signal given with `--reference`, from a recording or the
+    /// first channel decoding the named signal
+    fn reference(&self) -> Option<Series> {
+        if let Some(recording) = &self.recording {
+            let start = self.channels.iter().filter_map(|c| c.stats.start());
+            return Some(recording.series(start.min()?));
+        }
+        let name = self.cli.reference.as_deref()?;
+        self.channels
+            .iter()
+            .find_map(|c| c.stats.signal_series(name))
+    }
+
+    /// Rank the bit fields of unknown messages on all channels by their
+    /// correlation with the reference signal
+    pub(crate) fn update_correlation(&mut self) {
+        self.correlation = match self.reference() {
+            Some(reference) => {
+                let mut correlations: Vec<(usize, Correlation)> = self
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, channel)| {
+                        let correlations = channel.stats.correlate(&reference);
+                        correlations.into_iter().map(move |c| (index, c))
+                    })
+                    .collect();
+                correlations.sort_by(|a, b| {
+                    b.1.coefficient.abs().total_cmp(&a.1.coefficient.abs())
+                });
+                let lines: Vec<String> = correlations
+                    .iter()
+                    .take(20)
+                    .map(|(index, correlation)| {
+                        let name = self.channels[*index].source.name();
+                        format!("{name} {}", correlation.text())
+                    })
+                    .collect();
+                match lines.is_empty() {
+                    true => "Not enough packets captured yet".to_string(),
+                    false => lines.join("\n"),
+                }
+            }
+            None => "No reference signal (--reference)".to_string(),
+        };
+    }
+
+    /// Popup with the bit fields correlating best with the reference
+    /// signal, as last ranked
+    pub(crate) fn draw_correlation(&mut self, frame: &mut Frame) {
+        let popup = Popup::default()
+            .title(" Correlation (C=close) ")
+            .content(self.correlation.as_str())
+            .preformatted(true);
+        let area = popup.fit(frame.area());
+        frame.render_widget(popup, area);
+    }
+
+    /// Popup with the definition of the selected message inferred from
+    /// its recent packets, in DBC notation
+    pub(crate) fn draw_draft(&mut self, frame: &mut Frame) {
+        let text = match self.selected_message() {
+            Some((channel, index)) => {
+                let stats = &self.channels[channel].stats;
+                match stats.draft(&stats.messages()[index]) {
+                    Some(message) => dbc::format_message(&message),
+                    None => "Not enough packets captured yet".to_string(),
+                }
+            }
+            None => "No message selected".to_string(),
+        };
+
+        let popup = Popup::default()
+            .title(" Inferred Definition (I=close) ")
+            .content(text)
+            .preformatted(true);
+        let area = popup.fit(frame.area());
+        frame.render_widget(popup, area);
+    }
+
+    /// Write a DBC skeleton of every channel to the `--export` file
+    pub(crate) fn export_dbc(&mut self) {
+        let Some(filename) = self.cli.export.clone() else {
+            self.status = "No export file (--export)".to_string();
+            return;
+        };
+        let path = Path::new(&filename);
+        let mut written = vec![];
+        for (index, channel) in self.channels.iter().enumerate() {
+            let path = match self.channels.len() {
+                1 => path.to_path_buf(),
+                _ => path.with_extension(format!("{index}.dbc")),
+            };
+            let text = dbc::format(&channel.stats.export());
+            if let Err(e) = fs::write(&path, text) {
+                self.status = format!("{}: {e}", path.display());
+                return;
+            }
+            written.push(path.display().to_string());
+        }
+        self.status = format!("Exported {}", written.join(", "));
+    }
+}
@@ -0,0 +1,80 @@
+//! Diagnostic (UDS) request/response view
+
+use crate::App;
+use candor::uds::{self, Transaction};
+
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Text,
+    widgets::{Block, Cell, Row, Table},
+    Frame,
+};
+
+impl App {
+    pub(crate) fn draw_diagnostics(&mut self, frame: &mut Frame, area: Rect) {
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+
+        // merge transactions of all channels, newest first
+        let mut transactions: Vec<&Transaction> = self
+            .channels
+            .iter()
+            .flat_map(|c| c.stats.transactions().iter())
+            .collect();
+        transactions.sort_by_key(|t| t.time);
+
+        let mut rows: Vec<Row> = Vec::with_capacity(transactions.len());
+        for transaction in transactions.iter().rev() {
+            let mut style =
+                Style::default().fg(self.channel_color(transaction.source));
+
+            let mut id = "".to_string();
+            if self.show_source {
+                let name = self.channels[transaction.source].source.name();
+                id.push_str(format!("{}\n", name).as_str());
+            }
+            id.push_str(format!("{:X}", transaction.id).as_str());
+
+            let request = uds::describe(&transaction.request, &self.dids);
+            let response = match &transaction.response {
+                Some(response) => {
+                    if response.first() == Some(&uds::NEGATIVE_RESPONSE) {
+                        style = style.fg(Color::Red);
+                    }
+                    uds::describe(response, &self.dids)
+                }
+                None if transaction.pending > 0 => {
+                    vec!["(response pending)".to_string()]
+                }
+                None => vec![],
+            };
+
+            let latency = match transaction.latency {
+                Some(latency) => format!("{:.1?}", latency),
+                None => "".to_string(),
+            };
+
+            let height =
+                request.len().max(response.len()).max(id.lines().count());
+            let cols = [id, request.join("\n"), response.join("\n"), latency];
+            let row =
+                Row::new(cols.into_iter().map(|s| Cell::from(Text::from(s))))
+                    .height(height as u16)
+                    .style(style);
+            rows.push(row);
+        }
+
+        let cols = [
+            Constraint::Length(10),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(10),
+        ];
+        let header = " ID ────── Request ── Response ── Latency (U=messages) ";
+        let table = Table::new(rows, cols)
+            .row_highlight_style(selected_style)
+            .block(Block::bordered().title(header));
+
+        frame.render_widget(table, area);
+    }
+}
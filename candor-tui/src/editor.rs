@@ -0,0 +1,302 @@
+//! Interactive signal definition for the selected message
+
+use crate::{popup::Popup, App};
+use candor::db::{self, dbc, flip_bit, ByteOrder, Database, ValueType};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use ratatui::{
+    crossterm::event::KeyCode,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    Frame,
+};
+
+const FIELDS: [&str; 9] = [
+    "Name", "Start", "Size", "Order", "Sign", "Factor", "Offset", "Unit",
+    "File",
+];
+
+/// Signal being defined, with its fields as entered
+pub(crate) struct SignalEditor {
+    channel: usize,
+    index: usize,
+    field: usize,
+    name: String,
+    start_bit: String,
+    size: String,
+    byte_order: ByteOrder,
+    signed: bool,
+    factor: String,
+    offset: String,
+    unit: String,
+    /// DBC file to save the message definition to, if any
+    file: String,
+}
+
+impl SignalEditor {
+    fn new(channel: usize, index: usize, name: String, file: String) -> Self {
+        Self {
+            channel,
+            index,
+            field: 0,
+            name,
+            start_bit: "0".into(),
+            size: "8".into(),
+            byte_order: ByteOrder::LittleEndian,
+            signed: false,
+            factor: "1".into(),
+            offset: "0".into(),
+            unit: String::new(),
+            file,
+        }
+    }
+
+    /// Signal as currently entered, if valid
+    fn signal(&self) -> Option<db::Signal> {
+        if self.name.is_empty() || self.name.contains(char::is_whitespace) {
+            return None;
+        }
+        let size = self.size.parse().ok().filter(|s| (1..=64).contains(s))?;
+        let mut signal =
+            db::Signal::new(&self.name, self.start_bit.parse().ok()?, size);
+        signal.byte_order = self.byte_order;
+        if self.signed {
+            signal.value_type = ValueType::Signed;
+        }
+        signal.factor = self.factor.parse().ok()?;
+        signal.offset = self.offset.parse().ok()?;
+        signal.unit = self.unit.clone();
+        Some(signal)
+    }
+
+    fn text(&mut self) -> Option<&mut String> {
+        match self.field {
+            0 => Some(&mut self.name),
+            1 => Some(&mut self.start_bit),
+            2 => Some(&mut self.size),
+            5 => Some(&mut self.factor),
+            6 => Some(&mut self.offset),
+            7 => Some(&mut self.unit),
+            8 => Some(&mut self.file),
+            _ => None,
+        }
+    }
+
+    fn value(&self, field: usize) -> String {
+        match field {
+            0 => self.name.clone(),
+            1 => self.start_bit.clone(),
+            2 => self.size.clone(),
+            3 => match self.byte_order {
+                ByteOrder::LittleEndian => "Intel (little-endian)".into(),
+                ByteOrder::BigEndian => "Motorola (big-endian)".into(),
+            },
+            4 => match self.signed {
+                true => "signed".into(),
+                false => "unsigned".into(),
+            },
+            5 => self.factor.clone(),
+            6 => self.offset.clone(),
+            7 => self.unit.clone(),
+            _ => self.file.clone(),
+        }
+    }
+
+    /// Step the start bit or size, or toggle byte order or sign
+    fn adjust(&mut self, by: i64) {
+        match self.field {
+            1 | 2 => {
+                let text = self.text().expect("numeric field");
+                let value = text.parse::<i64>().unwrap_or_default() + by;
+                *text = value.clamp(0, 511).to_string();
+            }
+            3 => {
+                self.byte_order = match self.byte_order {
+                    ByteOrder::LittleEndian => ByteOrder::BigEndian,
+                    ByteOrder::BigEndian => ByteOrder::LittleEndian,
+                };
+            }
+            4 => self.signed = !self.signed,
+            _ => {}
+        }
+    }
+}
+
+/// Whether a bit (DBC numbering) belongs to a signal
+fn selected(signal: &db::Signal, bit: usize) -> bool {
+    match signal.byte_order {
+        ByteOrder::LittleEndian => {
+            (signal.start_bit..signal.start_bit + signal.size).contains(&bit)
+        }
+        ByteOrder::BigEndian => {
+            let start = flip_bit(signal.start_bit);
+            (start..start + signal.size).contains(&flip_bit(bit))
+        }
+    }
+}
+
+impl App {
+    /// Start defining a signal of the selected message
+    pub(crate) fn edit_signal(&mut self) {
+        let Some((channel, index)) = self.selected_message() else {
+            return;
+        };
+        let stats = &self.channels[channel].stats;
+        let count = stats
+            .dbc_message(&stats.messages()[index])
+            .map(|m| m.signals.len())
+            .unwrap_or_default();
+        let name = format!("Signal_{}", count + 1);
+        // saved to the channel's DBC file by default
+        let file = self.channels[channel]
+            .dbcs
+            .iter()
+            .find(|f| f.to_lowercase().ends_with(".dbc"))
+            .cloned()
+            .unwrap_or_default();
+        self.editor = Some(SignalEditor::new(channel, index, name, file));
+    }
+
+    /// Add or replace the definition of a message in a DBC file, creating
+    /// the file if needed
+    fn save_definition(
+        &self,
+        channel: usize,
+        index: usize,
+        filename: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let stats = &self.channels[channel].stats;
+        let Some(message) = stats.dbc_message(&stats.messages()[index]) else {
+            return Ok(());
+        };
+        let mut database = match Path::new(filename).exists() {
+            true => Database::from_file(filename)?,
+            false => Database::default(),
+        };
+        database.insert(message.clone());
+        fs::write(filename, dbc::format(&database))?;
+        Ok(())
+    }
+
+    /// Handle a key while defining a signal
+    pub(crate) fn editor_key(&mut self, code: KeyCode) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+        match code {
+            KeyCode::Esc => self.editor = None,
+            KeyCode::Enter => {
+                let Some(signal) = editor.signal() else {
+                    self.status = "Invalid signal".to_string();
+                    return;
+                };
+                let (channel, index) = (editor.channel, editor.index);
+                let file = editor.file.clone();
+                self.editor = None;
+                let name = signal.name.clone();
+                self.channels[channel].stats.define_signal(index, signal);
+                self.status = match file.is_empty() {
+                    true => format!("Defined {name} in memory only, no file"),
+                    false => {
+                        match self.save_definition(channel, index, &file) {
+                            Ok(()) => format!("Saved {name} to {file}"),
+                            Err(e) => format!("{file}: {e}"),
+                        }
+                    }
+                };
+                if self.cli.export.is_some() {
+                    self.export_dbc();
+                }
+            }
+            KeyCode::Up | KeyCode::BackTab => {
+                editor.field = (editor.field + FIELDS.len() - 1) % FIELDS.len()
+            }
+            KeyCode::Down | KeyCode::Tab => {
+                editor.field = (editor.field + 1) % FIELDS.len()
+            }
+            KeyCode::Left => editor.adjust(-1),
+            KeyCode::Right => editor.adjust(1),
+            KeyCode::Char(' ') if matches!(editor.field, 3 | 4) => {
+                editor.adjust(1)
+            }
+            KeyCode::Char(c) => {
+                if let Some(text) = editor.text() {
+                    text.push(c);
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(text) = editor.text() {
+                    text.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Popup with the signal being defined, decoded live from the latest
+    /// packet of its message
+    pub(crate) fn draw_editor(&mut self, frame: &mut Frame) {
+        let Some(editor) = self.editor.as_ref() else {
+            return;
+        };
+        let stats = &self.channels[editor.channel].stats;
+        let packet = &stats.messages()[editor.index].current;
+        let signal = editor.signal();
+
+        let mut lines: Vec<Line> = FIELDS
+            .iter()
+            .enumerate()
+            .map(|(field, name)| {
+                let text = format!("{name:<8}{}", editor.value(field));
+                let style = match field == editor.field {
+                    true => Style::default().add_modifier(Modifier::REVERSED),
+                    false => Style::default(),
+                };
+                Line::styled(text, style)
+            })
+            .collect();
+        lines.push(Line::default());
+
+        // payload bits, most significant first, with the signal's marked
+        let mut bits = vec![Span::raw("Bits    ")];
+        for (byte, value) in packet.bytes.iter().enumerate() {
+            for bit in (0..8).rev() {
+                let text = ((value >> bit) & 1).to_string();
+                let marked = signal
+                    .as_ref()
+                    .is_some_and(|s| selected(s, byte * 8 + bit));
+                bits.push(match marked {
+                    true => Span::styled(
+                        text,
+                        Style::default().add_modifier(Modifier::REVERSED),
+                    ),
+                    false => Span::raw(text),
+                });
+            }
+            bits.push(Span::raw(" "));
+        }
+        lines.push(Line::from(bits));
+
+        let value = match &signal {
+            Some(signal) => match signal.value(&packet.bytes) {
+                Some(value) => format!("{value}{}", signal.unit),
+                None => "(outside of payload)".to_string(),
+            },
+            None => "(invalid)".to_string(),
+        };
+        lines.push(Line::from(format!("Value   {value}")));
+
+        let title = format!(
+            " Define Signal of {}(Enter=apply, Esc=cancel, ←/→=adjust) ",
+            packet.id_string().trim_start()
+        );
+        let popup = Popup::default()
+            .title(title)
+            .content(lines)
+            .preformatted(true);
+        let area = popup.fit(frame.area());
+        frame.render_widget(popup, area);
+    }
+}
@@ -1,8 +1,14 @@
 //! CANdor TUI
 
-use candor::{stats::Stats, Packet};
-use candor_io::trc::TrcSource;
-use candor_io::Source;
+use candor::analysis::Recording;
+use candor::{
+    canopen, db, e2e, isotp, j1939, nmea2000, obd, stats::Stats, uds, xcp,
+    Packet,
+};
+use candor_io::candump::{self, CandumpWriter};
+use candor_io::trc::{self, TrcVersion, TrcWriter};
+use candor_io::{asc, blf, mdf};
+use candor_io::{Sink, Source, SourceError, Status};
 
 use clap::Parser;
 use regex::Regex;
@@ -12,6 +18,11 @@ use std::sync::mpsc;
 use std::time::{Duration, Instant};
 use std::{collections::VecDeque, thread};
 
+mod analysis;
+mod diagnostics;
+mod editor;
+mod nodes;
+use nodes::Entry;
 mod popup;
 use popup::Popup;
 
@@ -44,19 +55,50 @@ const CHANNEL_COLORS: [Color; 10] = [
 
 enum AppEvent {
     Packet(Packet),
+    SourceError(SourceError),
     Key(KeyEvent),
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut app = App::new()?;
+    let args = Args::parse();
+    if args.lint {
+        return lint(&args);
+    }
+
+    let mut app = App::new(args)?;
     let terminal = ratatui::init();
     let result = app.run(terminal);
+    app.stop_sources();
 
     ratatui::restore();
 
     result
 }
 
+/// Print the consistency warnings of the database files given with the
+/// sources, or of database files given directly
+fn lint(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut warnings = 0;
+    for source in args.sources.iter() {
+        let (name, mut dbcs) = App::parse_source(source);
+        if dbcs.is_empty() {
+            dbcs.push(name);
+        }
+        let mut stats = Stats::new(0);
+        for dbc in args.dbc.iter().chain(dbcs.iter()) {
+            stats.add_dbc(App::find_dbc(&args.dbc_path, dbc))?;
+        }
+        for warning in stats.warnings() {
+            println!("{warning}");
+        }
+        warnings += stats.warnings().len();
+    }
+    match warnings {
+        0 => Ok(()),
+        n => Err(format!("{n} warnings").into()),
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -71,6 +113,90 @@ struct Args {
     #[arg(short, long)]
     sync_time: bool,
 
+    /// Database file for all sources, in addition to those given per source
+    #[arg(long)]
+    dbc: Vec<String>,
+
+    /// Directory to look up database files in
+    #[arg(long)]
+    dbc_path: Vec<String>,
+
+    /// Decode extended IDs as SAE J1939
+    #[arg(short, long)]
+    j1939: bool,
+
+    /// Decode extended IDs as NMEA 2000, reassembling fast-packets
+    #[arg(long)]
+    nmea2000: bool,
+
+    /// NMEA 2000 parameter group definitions, in addition to the built-in
+    /// common PGNs (implies --nmea2000)
+    #[arg(long)]
+    pgns: Option<String>,
+
+    /// Decode standard IDs as CANopen communication objects
+    #[arg(long)]
+    canopen: bool,
+
+    /// Decode OBD-II responses (0x7E8-0x7EF)
+    #[arg(long)]
+    obd: bool,
+
+    /// ISO-TP request:response ID pair (hex) with optional addressing
+    /// (e.g. 7e0:7e8, 6f1:6f2:ext, 6f1:6f2:mixed)
+    #[arg(short, long)]
+    isotp: Vec<String>,
+
+    /// UDS data identifier definitions for the diagnostics view
+    #[arg(long)]
+    dids: Option<String>,
+
+    /// XCP on CAN master:slave ID pair (hex), if not given by the A2L file
+    #[arg(long)]
+    xcp: Option<String>,
+
+    /// A2L file with XCP measurement definitions
+    #[arg(long)]
+    a2l: Option<String>,
+
+    /// End-to-end protection (E2E/CRC/counter) configuration file
+    #[arg(long)]
+    e2e: Option<String>,
+
+    /// Reference signal to correlate unknown bit fields with, as
+    /// `[<message>.]<signal>` from a database or a CSV file of
+    /// `<seconds>,<value>`
+    #[arg(long)]
+    reference: Option<String>,
+
+    /// Number of packets kept per message for analysis
+    #[arg(long, default_value_t = 256)]
+    history: usize,
+
+    /// DBC file to export all seen messages to (E), one per channel with
+    /// the channel index added for several channels
+    #[arg(long)]
+    export: Option<String>,
+
+    /// Trace file to record all received packets to (.trc, or .log for
+    /// candump)
+    #[arg(long)]
+    record: Option<String>,
+
+    /// Directory of candidate database files to score against the traffic
+    #[arg(long)]
+    candidates: Option<String>,
+
+    /// Attach the best matching candidate database to channels without
+    /// database
+    #[arg(long)]
+    auto_attach: bool,
+
+    /// Check the database files of the sources (or the given database
+    /// files) for consistency and exit
+    #[arg(long)]
+    lint: bool,
+
     /// Don't use colors
     #[arg(short, long)]
     no_color: bool,
@@ -83,6 +209,8 @@ struct Args {
 struct Channel {
     source: Box<dyn Source>,
     stats: Stats,
+    /// Database files loaded, for saving signal definitions
+    dbcs: Vec<String>,
 }
 
 struct App {
@@ -98,6 +226,23 @@ struct App {
     show_period: bool,
     show_source: bool,
     show_dump: bool,
+    show_isotp: bool,
+    show_diagnostics: bool,
+    show_draft: bool,
+    show_correlation: bool,
+    /// Bit fields ranked by correlation, as shown
+    correlation: String,
+    recording: Option<Recording>,
+    status: String,
+    editor: Option<editor::SignalEditor>,
+    candidates: analysis::Candidates,
+    /// Candidate databases ranked on each channel
+    scores: Vec<analysis::Scores>,
+    show_scores: bool,
+    group_nodes: bool,
+    paused: bool,
+    recorder: Option<Box<dyn Sink>>,
+    dids: uds::Dids,
     enable_decode: bool,
     show_undecoded: bool,
     show_ascii: bool,
@@ -107,55 +252,197 @@ struct App {
 }
 
 impl App {
-    fn new() -> Result<Self, Box<dyn Error>> {
-        let args = Args::parse();
-
+    fn new(args: Args) -> Result<Self, Box<dyn Error>> {
         // attach packet channel to all sources
         let (tx_events, rx_events) = mpsc::channel::<AppEvent>();
         let (tx_packets, rx_packets) = mpsc::channel::<Packet>();
+        let (tx_errors, rx_errors) = mpsc::channel::<SourceError>();
+        let pairs = args
+            .isotp
+            .iter()
+            .map(|p| isotp::Pair::parse(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dids = match &args.dids {
+            Some(filename) => uds::Dids::from_file(filename)?,
+            None => uds::Dids::default(),
+        };
+        let nmea2000 = match &args.pgns {
+            Some(filename) => {
+                let mut definitions = nmea2000::Definitions::default();
+                definitions.extend(nmea2000::Definitions::from_file(filename)?);
+                Some(definitions)
+            }
+            None => args.nmea2000.then(nmea2000::Definitions::default),
+        };
+        let xcp = match (&args.xcp, &args.a2l) {
+            (None, None) => None,
+            (ids, a2l) => {
+                let ids = ids.as_deref().map(xcp::Ids::parse).transpose()?;
+                let a2l = match a2l {
+                    Some(filename) => xcp::a2l::A2l::from_file(filename)?,
+                    None => Default::default(),
+                };
+                let decoder = xcp::Decoder::new(ids, a2l);
+                if decoder.ids().is_none() {
+                    return Err("XCP master/slave IDs required".into());
+                }
+                Some(decoder)
+            }
+        };
+        let protections = match &args.e2e {
+            Some(filename) => e2e::from_file(filename)?,
+            None => Default::default(),
+        };
+        let recording = match &args.reference {
+            Some(reference) if reference.to_lowercase().ends_with(".csv") => {
+                Some(Recording::from_file(reference)?)
+            }
+            _ => None,
+        };
+        let (candidates, skipped) = match &args.candidates {
+            Some(directory) => App::load_candidates(directory)?,
+            None => Default::default(),
+        };
         let mut channels: Vec<Channel> = vec![];
         for iface in args.sources.iter() {
             let index = channels.len();
             let (ifname, dbcs) = App::parse_source(iface);
             let path = Path::new(&ifname);
             let extension = match path.extension() {
-                Some(s) => s.to_str().unwrap_or(""),
-                None => "",
+                Some(s) => s.to_str().unwrap_or("").to_lowercase(),
+                None => String::new(),
             };
 
-            let source: Box<dyn Source> = match extension {
-                "trc" => Box::new(TrcSource::new(
+            let sources: Vec<Box<dyn Source>> = match extension.as_str() {
+                // one channel per bus of the trace
+                "trc" => trc::open(
                     &ifname,
                     index,
                     args.baud,
                     args.sync_time,
                     tx_packets.clone(),
-                )?),
+                    tx_errors.clone(),
+                )?
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Source>)
+                .collect(),
+                // one channel per CAN channel of the trace
+                "asc" => asc::open(
+                    &ifname,
+                    index,
+                    args.baud,
+                    tx_packets.clone(),
+                    tx_errors.clone(),
+                )?
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Source>)
+                .collect(),
+                "blf" => blf::open(
+                    &ifname,
+                    index,
+                    args.baud,
+                    tx_packets.clone(),
+                    tx_errors.clone(),
+                )?
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Source>)
+                .collect(),
+                // one channel per bus channel of the measurement
+                "mf4" => mdf::open(
+                    &ifname,
+                    index,
+                    args.baud,
+                    tx_packets.clone(),
+                    tx_errors.clone(),
+                )?
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Source>)
+                .collect(),
+                // one channel per interface of the log
+                "log" => candump::open(
+                    &ifname,
+                    index,
+                    args.baud,
+                    tx_packets.clone(),
+                    tx_errors.clone(),
+                )?
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Source>)
+                .collect(),
 
                 #[cfg(not(feature = "socketcan"))]
                 _ => return Err("Invalid argument".into()),
 
                 #[cfg(feature = "socketcan")]
-                _ => Box::new(SocketCanSource::new(
+                _ => vec![Box::new(SocketCanSource::new(
                     &ifname,
                     index,
                     args.baud,
                     tx_packets.clone(),
-                )?),
+                    tx_errors.clone(),
+                )?)],
             };
 
-            let baud = source.baud();
-            let mut channel = Channel {
-                source,
-                stats: Stats::new(baud),
-            };
-            for dbc in dbcs {
-                channel.stats.add_dbc(dbc)?;
+            for source in sources {
+                let baud = source.baud();
+                let mut channel = Channel {
+                    source,
+                    stats: Stats::new(baud),
+                    dbcs: vec![],
+                };
+                if args.j1939 {
+                    channel.stats.enable_j1939();
+                }
+                if args.canopen {
+                    channel.stats.enable_canopen();
+                }
+                if args.obd {
+                    channel.stats.enable_obd();
+                }
+                if let Some(decoder) = &xcp {
+                    channel.stats.enable_xcp(decoder.clone());
+                }
+                if !pairs.is_empty() {
+                    channel.stats.enable_isotp(pairs.clone());
+                }
+                channel.stats.add_e2e(protections.clone());
+                channel.stats.set_history(args.history);
+                if let (Some(name), None) = (&args.reference, &recording) {
+                    channel.stats.watch_signal(name);
+                }
+                for dbc in args.dbc.iter().chain(dbcs.iter()) {
+                    let dbc = App::find_dbc(&args.dbc_path, dbc);
+                    channel.dbcs.push(dbc.clone());
+                    channel.stats.add_dbc(dbc)?;
+                }
+                if let Some(definitions) = &nmea2000 {
+                    channel.stats.enable_nmea2000(definitions);
+                }
+                channels.push(channel);
             }
-            channels.push(channel);
         }
 
+        let recorder = match &args.record {
+            Some(filename) => Some(App::recorder(filename, &channels)?),
+            None => None,
+        };
+
         let show_source = args.no_color && channels.len() > 1;
+        let show_isotp = !pairs.is_empty();
+
+        let warnings: usize =
+            channels.iter().map(|c| c.stats.warnings().len()).sum();
+        let mut status = match warnings {
+            0 => String::new(),
+            n => format!("{n} database warnings (see --lint)"),
+        };
+        if !skipped.is_empty() {
+            if !status.is_empty() {
+                status.push_str("; ");
+            }
+            status
+                .push_str(&format!("Skipped candidate {}", skipped.join(", ")));
+        }
 
         // thread for user input events
         thread::spawn({
@@ -170,13 +457,27 @@ impl App {
         // thread for incoming packets
         thread::spawn({
             let tx = tx_events.clone();
-            move || loop {
-                if let Ok(packet) = rx_packets.recv() {
+            move || {
+                while let Ok(packet) = rx_packets.recv() {
                     tx.send(AppEvent::Packet(packet)).ok();
                 }
             }
         });
 
+        // thread for source failures
+        thread::spawn({
+            let tx = tx_events.clone();
+            move || {
+                while let Ok(error) = rx_errors.recv() {
+                    tx.send(AppEvent::SourceError(error)).ok();
+                }
+            }
+        });
+
+        for channel in channels.iter_mut() {
+            channel.source.start()?;
+        }
+
         Ok(Self {
             cli: args,
             events: rx_events,
@@ -189,6 +490,21 @@ impl App {
             idle: false,
             show_source,
             show_dump: true,
+            show_isotp,
+            show_diagnostics: false,
+            show_draft: false,
+            show_correlation: false,
+            correlation: String::new(),
+            recording,
+            status,
+            editor: None,
+            candidates,
+            scores: vec![],
+            show_scores: false,
+            group_nodes: false,
+            paused: false,
+            recorder,
+            dids,
             show_period: true,
             enable_decode: true,
             show_undecoded: true,
@@ -215,6 +531,16 @@ impl App {
                 for channel in self.channels.iter_mut() {
                     channel.stats.periodic();
                 }
+                if self.show_correlation {
+                    self.update_correlation();
+                }
+                if self.show_scores || self.cli.auto_attach {
+                    self.update_scores();
+                }
+                if self.cli.auto_attach {
+                    self.attach_candidates();
+                }
+                self.reload_dbcs();
                 stats_time = now;
             }
 
@@ -233,6 +559,7 @@ impl App {
                         .expect("channel for id");
 
                     channel.stats.process_packet(&packet);
+                    self.record(&packet);
 
                     self.packets.push_front(packet);
                     if self.packets.len() > 100 {
@@ -240,7 +567,17 @@ impl App {
                     }
                     self.idle = false;
                 }
+                Ok(AppEvent::SourceError(error)) => {
+                    let name = self.channels[error.source].source.name();
+                    self.status =
+                        format!("{name} {}: {}", error.status, error.text);
+                    self.idle = false;
+                }
                 // user input
+                Ok(AppEvent::Key(key)) if self.editor.is_some() => {
+                    self.idle = false;
+                    self.editor_key(key.code);
+                }
                 Ok(AppEvent::Key(key)) => {
                     self.idle = false;
                     match key.code {
@@ -249,6 +586,29 @@ impl App {
                         KeyCode::Char('D') => {
                             self.show_dump = !self.show_dump;
                         }
+                        KeyCode::Char('T') => {
+                            self.show_isotp = !self.show_isotp;
+                        }
+                        KeyCode::Char('U') => {
+                            self.show_diagnostics = !self.show_diagnostics;
+                        }
+                        KeyCode::Char('I') => {
+                            self.show_draft = !self.show_draft;
+                        }
+                        KeyCode::Char('C') => {
+                            self.show_correlation = !self.show_correlation;
+                            self.update_correlation();
+                        }
+                        KeyCode::Char('E') => self.export_dbc(),
+                        KeyCode::Char('N') => self.edit_signal(),
+                        KeyCode::Char('R') => self.pause_sources(),
+                        KeyCode::Char('G') => {
+                            self.group_nodes = !self.group_nodes;
+                        }
+                        KeyCode::Char('M') => {
+                            self.show_scores = !self.show_scores;
+                            self.update_scores();
+                        }
                         KeyCode::Char('S') => {
                             self.show_source = !self.show_source;
                         }
@@ -307,43 +667,124 @@ impl App {
         Ok(())
     }
 
-    /// Parse <ifname>[:<filename.dbc>] specifier to allow associating
-    /// DBC file(s) with a source interface
+    /// Parse <ifname>[:<filename.dbc>...] specifier to allow associating
+    /// database files (DBC, KCD, SYM, ARXML or EDS/DCF, with an optional
+    /// `#<bus>` suffix selecting a KCD bus, ARXML cluster or CANopen node
+    /// ID) with a source interface, separated by `:` or `,`
     fn parse_source(name: &str) -> (String, Vec<String>) {
-        let mut dbcs: Vec<String> = vec![];
-
-        let re = Regex::new(r"([^:]+)([:]*)(.*)").unwrap();
+        let re = Regex::new(r"([^:]+):?(.*)").unwrap();
         let c = re.captures(name).unwrap();
 
         let ifname = c.get(1).unwrap().as_str().to_string();
-        let sep = c.get(2).unwrap().as_str();
+        let dbcs = c
+            .get(2)
+            .unwrap()
+            .as_str()
+            .split([':', ','])
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        (ifname, dbcs)
+    }
 
-        if sep == ":" {
-            let dbc = c.get(3).unwrap().as_str().to_string();
-            dbcs.push(dbc);
+    /// Look up a database file in the `--dbc-path` directories unless it
+    /// exists as given
+    fn find_dbc(search_path: &[String], name: &str) -> String {
+        let (filename, bus) = db::split_bus(name);
+        if Path::new(filename).exists() {
+            return name.to_string();
+        }
+        let found = search_path
+            .iter()
+            .map(|directory| Path::new(directory).join(filename))
+            .find(|path| path.exists());
+        match (found, bus) {
+            (Some(path), Some(bus)) => format!("{}#{bus}", path.display()),
+            (Some(path), None) => path.display().to_string(),
+            (None, _) => name.to_string(),
+        }
+    }
+
+    /// Reload database files changed on disk
+    fn reload_dbcs(&mut self) {
+        for channel in self.channels.iter_mut() {
+            let (reloaded, failed) = channel.stats.reload_dbcs();
+            let mut status = vec![];
+            if !reloaded.is_empty() {
+                status.push(format!("Reloaded {}", reloaded.join(", ")));
+            }
+            status.extend(failed.iter().map(|e| format!("Reload failed: {e}")));
+            if !status.is_empty() {
+                self.status = status.join("; ");
+            }
         }
-        (ifname, dbcs)
     }
 
     fn channel_color(&self, index: usize) -> Color {
         if self.cli.no_color {
             Color::White
         } else {
-            CHANNEL_COLORS[index]
+            CHANNEL_COLORS[index % CHANNEL_COLORS.len()]
+        }
+    }
+
+    /// Pause or resume the replay of all recorded sources
+    fn pause_sources(&mut self) {
+        self.paused = !self.paused;
+        for channel in self.channels.iter_mut() {
+            if !channel.source.live() {
+                channel.source.pause(self.paused);
+            }
+        }
+    }
+
+    /// Stop all sources, waiting for their threads to finish, and write
+    /// out the recording
+    fn stop_sources(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.source.stop();
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.flush().ok();
+        }
+    }
+
+    /// Sink for recording packets, by file extension
+    fn recorder(
+        filename: &str,
+        channels: &[Channel],
+    ) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "trc" => {
+                Ok(Box::new(TrcWriter::create(filename, TrcVersion::V2_1)?))
+            }
+            "log" => {
+                let interfaces =
+                    channels.iter().map(|c| c.source.name()).collect();
+                Ok(Box::new(CandumpWriter::create(filename, interfaces)?))
+            }
+            _ => Err(format!("Unsupported recording format {filename}").into()),
+        }
+    }
+
+    /// Record a packet, stopping the recording on failure
+    fn record(&mut self, packet: &Packet) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        if let Err(e) = recorder.write(packet) {
+            self.status = format!("Recording stopped: {e}");
+            self.recorder = None;
         }
     }
 
     fn max_selection(&self) -> usize {
-        self.channels
-            .iter()
-            .map(|c| {
-                c.stats
-                    .messages()
-                    .iter()
-                    .filter(|m| self.show_undecoded || m.dbc.is_some())
-                    .count()
-            })
-            .sum::<usize>()
+        self.entries().len()
     }
 
     fn expand(&mut self) {
@@ -396,9 +837,18 @@ d = Show/Hide Decoded Data
 u = Show/Hide Undecoded Data
 W/w = Increase/Decrease Data View Width
 <, > = Change Bus Ordering
+G = Group Messages by Transmitting Node
+I = Show Inferred Definition of Selected Message
+C = Show Bit Fields Correlating with --reference
+E = Export Seen Messages to --export DBC
+N = Define Signal of Selected Message
+M = Show Candidate Database Matches
 
 GENERAL
 D = Toggle Live Packet Dump
+T = Toggle ISO-TP Transfers
+U = Toggle Diagnostics (UDS) View
+R = Pause/Resume Replay of Recorded Sources
 Q = Quit
 "#,
         );
@@ -449,23 +899,74 @@ Q = Quit
         frame.render_widget(summary, area);
     }
 
+    fn draw_isotp(&mut self, frame: &mut Frame, area: Rect) {
+        if area.height == 0 {
+            return;
+        }
+
+        // merge events of all channels, newest first
+        let mut events: Vec<&isotp::Event> = self
+            .channels
+            .iter()
+            .flat_map(|c| c.stats.isotp_events().iter())
+            .collect();
+        events.sort_by_key(|e| match e {
+            isotp::Event::Pdu(pdu) => pdu.time,
+            isotp::Event::Error { time, .. } => *time,
+        });
+
+        let mut lines: Vec<Line> = Vec::with_capacity(area.height as usize);
+        for event in events.iter().rev().take(area.height as usize) {
+            let (source, id) = match event {
+                isotp::Event::Pdu(pdu) => (pdu.source, pdu.id),
+                isotp::Event::Error { source, id, .. } => (*source, *id),
+            };
+            let mut text = "".to_string();
+            if self.show_source {
+                let name = self.channels[source].source.name();
+                text.push_str(format!("{:8}", name).as_str());
+            }
+            text.push_str(format!("{:8X} ", id).as_str());
+
+            let mut style = Style::new().fg(self.channel_color(source));
+            match event {
+                isotp::Event::Pdu(pdu) => {
+                    text.push(if pdu.request { '>' } else { '<' });
+                    if let Some(address) = pdu.address {
+                        text.push_str(format!(" @{:02X}", address).as_str());
+                    }
+                    text.push_str(format!(" [{}] ", pdu.data.len()).as_str());
+                    for byte in pdu.data.iter() {
+                        text.push_str(format!(" {:02x}", byte).as_str());
+                    }
+                }
+                isotp::Event::Error { error, .. } => {
+                    text.push_str(format!("! {}", error).as_str());
+                    style = style.fg(Color::Red);
+                }
+            }
+            lines.push(Line::from(text).style(style));
+        }
+        let transfers = Paragraph::new(lines)
+            .block(Block::bordered().title(" ISO-TP  (T=hide) "));
+        frame.render_widget(transfers, area);
+    }
+
     fn draw_messages(&mut self, frame: &mut Frame, area: Rect) {
         let selected_style = Style::default().add_modifier(Modifier::REVERSED);
 
         let mut rows: Vec<Row> = Vec::with_capacity(area.height as usize);
-        let channel_count = self.channels.len();
-        let mut order = self.order;
-        let max_height = area.height - 2;
-        for _ in 0..channel_count {
-            let channel = self.channels.get(order).unwrap();
-
-            let messages = channel.stats.messages();
-            for message_index in channel.stats.ordering().iter() {
-                let message = messages.get(*message_index).unwrap();
-                if !self.show_undecoded && message.dbc.is_none() {
+        for entry in self.entries() {
+            let (order, message_index) = match entry {
+                Entry::Node(channel, node) => {
+                    rows.push(self.node_row(channel, &node));
                     continue;
                 }
-
+                Entry::Message(channel, index) => (channel, index),
+            };
+            let channel = self.channels.get(order).unwrap();
+            let message = channel.stats.messages().get(message_index).unwrap();
+            {
                 let color = self.channel_color(message.current.source);
                 let row_style = Style::default().fg(color);
 
@@ -478,13 +979,39 @@ Q = Quit
                 };
 
                 // Message name / ID
+                let j1939 = (channel.stats.j1939() && message.current.extended)
+                    .then(|| j1939::Id::from_id(message.current.id));
+                let name = dbc_message
+                    .map(|m| m.name.clone())
+                    .or_else(|| {
+                        j1939
+                            .and_then(|j| j1939::pgn_name(j.pgn))
+                            .map(|n| n.to_string())
+                    })
+                    .or_else(|| {
+                        let standard = !message.current.extended;
+                        let canopen = (standard && channel.stats.canopen())
+                            .then(|| {
+                                canopen::Object::classify(message.current.id)
+                            })
+                            .flatten();
+                        match canopen {
+                            Some(object) => Some(object.text()),
+                            None => (standard && channel.stats.obd())
+                                .then(|| obd::id_name(message.current.id))
+                                .flatten(),
+                        }
+                    });
                 let mut id = "".to_string();
-                if let Some(msg) = dbc_message {
-                    id.push_str(msg.message_name().as_str());
+                if let Some(name) = name {
+                    id.push_str(&name);
                     id.push('\n');
                     height += 1;
                 }
-                id.push_str(&message.current.id_string());
+                match j1939 {
+                    Some(j1939) => id.push_str(&j1939.text()),
+                    None => id.push_str(&message.current.id_string()),
+                }
 
                 let mut cols = vec![id];
 
@@ -528,7 +1055,7 @@ Q = Quit
                 // signals
                 if self.expanded {
                     if let Some(msg) = dbc_message {
-                        for signal in msg.signals().iter() {
+                        for signal in msg.signals.iter() {
                             let value = channel.stats.signal_text(
                                 msg,
                                 signal,
@@ -537,11 +1064,42 @@ Q = Quit
                             if value.is_empty() {
                                 continue;
                             }
+                            let text = format!("\n  {} {}", signal.name, value);
+                            data.push_str(&text);
+                            height += 1;
+                        }
+                    } else if let Some(values) =
+                        channel.stats.obd_values(message)
+                    {
+                        for value in values.values() {
                             let text =
-                                format!("\n  {} {}", signal.name(), value);
+                                format!("\n  {} {}", value.name, value.text);
                             data.push_str(&text);
                             height += 1;
                         }
+                    } else {
+                        let lines = channel
+                            .stats
+                            .canopen_text(message)
+                            .into_iter()
+                            .chain(channel.stats.xcp_text(message));
+                        // reverse-engineering hints for unknown messages
+                        let mut lines: Vec<String> = lines.collect();
+                        if lines.is_empty() && message.dbc.is_none() {
+                            lines = channel
+                                .stats
+                                .analysis(message)
+                                .map(|a| a.lines())
+                                .unwrap_or_default();
+                        }
+                        for line in lines {
+                            data.push_str(&format!("\n  {}", line));
+                            height += 1;
+                        }
+                    }
+                    if let Some(text) = channel.stats.e2e_text(message) {
+                        data.push_str(&format!("\n  {}", text));
+                        height += 1;
                     }
                 }
 
@@ -555,7 +1113,6 @@ Q = Quit
 
                 rows.push(row);
             }
-            order = self.next_channel(order);
         }
 
         let mut header = " Message────────────────".to_string();
@@ -594,6 +1151,8 @@ Q = Quit
         )])
         .alignment(Alignment::Right);
         frame.render_widget(&hints, area);
+        let status = Line::from(self.status.as_str()).centered();
+        frame.render_widget(&status, area);
 
         let area = area.inner(Margin::new(0, 1));
         let constraints = vec![
@@ -603,8 +1162,12 @@ Q = Quit
         let cols = Layout::horizontal(constraints).split(area);
         self.visible_messages = cols[0].height - 2;
 
-        // main messages panel
-        self.draw_messages(frame, cols[0]);
+        // main messages or diagnostics panel
+        if self.show_diagnostics {
+            self.draw_diagnostics(frame, cols[0]);
+        } else {
+            self.draw_messages(frame, cols[0]);
+        }
 
         // interfaces & summary
         let mut r: Vec<Constraint> = self
@@ -612,18 +1175,33 @@ Q = Quit
             .iter()
             .map(|_| Constraint::Length(5))
             .collect();
-        r.push(Constraint::Fill(1));
+        let panels = [self.show_dump, self.show_isotp]
+            .iter()
+            .filter(|s| **s)
+            .count();
+        for _ in 0..panels.max(1) {
+            r.push(Constraint::Fill(1));
+        }
         let rows = Layout::vertical(&r).split(cols[1]);
 
         for (row, channel) in self.channels.iter().enumerate() {
             let stat = &channel.stats;
             let area = rows[row];
+            let color = match channel.source.status() {
+                Status::Error | Status::Disconnected => Color::Red,
+                _ => self.channel_color(row),
+            };
             let block = Block::bordered()
-                .border_style(Style::new().fg(self.channel_color(row)))
+                .border_style(Style::new().fg(color))
                 .title(format!(
-                    " {} @ {}bps ",
+                    " {} @ {}bps {}, {} ",
                     channel.source.name(),
                     channel.source.baud(),
+                    match channel.source.live() {
+                        true => "live",
+                        false => "recorded",
+                    },
+                    channel.source.status(),
                 ));
             let inner = block.inner(area);
             frame.render_widget(block, area);
@@ -640,17 +1218,46 @@ Q = Quit
             let text_area =
                 Rect::new(inner.x, inner.y + 1, inner.width, inner.height - 1);
             let message_count = stat.messages().len();
-            let text = format!(
+            let mut text = format!(
                 "{} packets, {} unique by ID",
                 stat.packets, message_count
             );
+            if stat.remote > 0 {
+                text.push_str(&format!(", {} remote", stat.remote));
+            }
+            if stat.errors > 0 {
+                text.push_str(&format!(", {} error frames", stat.errors));
+            }
             let load = Paragraph::new(text);
             frame.render_widget(load, text_area);
         }
 
         // stream dump
+        let mut panel = self.channels.len();
         if self.show_dump {
-            self.draw_dump(frame, rows[r.len() - 1]);
+            self.draw_dump(frame, rows[panel]);
+            panel += 1;
+        }
+
+        // reassembled transport PDUs
+        if self.show_isotp {
+            self.draw_isotp(frame, rows[panel]);
+        }
+
+        if self.show_draft {
+            self.draw_draft(frame);
+        }
+
+        if self.show_correlation {
+            self.draw_correlation(frame);
+        }
+
+        if self.show_scores {
+            self.draw_scores(frame);
+        }
+
+        if self.editor.is_some() {
+            self.draw_editor(frame);
         }
 
         if self.show_help {
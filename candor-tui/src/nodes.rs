@@ -0,0 +1,82 @@
+//! Message view rows, optionally grouped by transmitting node
+
+use crate::App;
+use candor::stats::Node;
+
+use ratatui::{
+    style::{Modifier, Style},
+    widgets::{Cell, Row},
+};
+
+/// Row of the message view
+pub(crate) enum Entry {
+    /// Node heading, by channel
+    Node(usize, Node),
+    /// Message, by channel and message index
+    Message(usize, usize),
+}
+
+impl App {
+    /// Rows of the message view, by channel in bus order
+    pub(crate) fn entries(&self) -> Vec<Entry> {
+        let mut entries = vec![];
+        let mut order = self.order;
+        for _ in 0..self.channels.len() {
+            let stats = &self.channels[order].stats;
+            let visible = |index: &usize| {
+                self.show_undecoded || stats.messages()[*index].dbc.is_some()
+            };
+            if self.group_nodes {
+                for mut node in stats.nodes() {
+                    node.messages.retain(visible);
+                    if node.messages.is_empty() && node.defined == 0 {
+                        continue;
+                    }
+                    let messages = node.messages.clone();
+                    entries.push(Entry::Node(order, node));
+                    for index in messages {
+                        entries.push(Entry::Message(order, index));
+                    }
+                }
+            } else {
+                for index in stats.ordering().iter().filter(|i| visible(i)) {
+                    entries.push(Entry::Message(order, *index));
+                }
+            }
+            order = self.next_channel(order);
+        }
+        entries
+    }
+
+    /// Heading row of a node with its statistics
+    pub(crate) fn node_row(&self, channel: usize, node: &Node) -> Row<'static> {
+        let mut text = format!(
+            "{}/{} messages, {} packets, {:.1}% of bus",
+            node.messages.len(),
+            node.defined,
+            node.packets,
+            node.share * 100.0
+        );
+        if !node.missing.is_empty() {
+            let mut missing =
+                node.missing[..node.missing.len().min(3)].join(", ");
+            if node.missing.len() > 3 {
+                missing.push_str(", …");
+            }
+            text.push_str(&format!(
+                ", {} missing: {missing}",
+                node.missing.len()
+            ));
+        }
+
+        let mut cols = vec![format!("▾ {}", node.name)];
+        if self.show_period {
+            cols.push(String::new());
+        }
+        cols.push(text);
+        let style = Style::default()
+            .fg(self.channel_color(channel))
+            .add_modifier(Modifier::BOLD);
+        Row::new(cols.into_iter().map(Cell::from)).style(style)
+    }
+}
@@ -1,7 +1,7 @@
 use derive_setters::Setters;
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
+    layout::{Margin, Rect},
     style::Style,
     text::{Line, Text},
     widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
@@ -16,6 +16,18 @@ pub struct Popup<'a> {
     border_style: Style,
     title_style: Style,
     style: Style,
+    /// Keep the lines of the content as they are, left-aligned
+    preformatted: bool,
+}
+
+impl Popup<'_> {
+    /// Area in the middle of `area`, as high as the content
+    pub fn fit(&self, area: Rect) -> Rect {
+        let height = (self.content.height() as u16 + 2).min(area.height);
+        let inner = area.inner(Margin::new(area.width / 8, 0));
+        let y = area.y + (area.height - height) / 2;
+        Rect::new(inner.x, y, inner.width, height)
+    }
 }
 
 impl Widget for Popup<'_> {
@@ -26,11 +38,15 @@ impl Widget for Popup<'_> {
             .style(self.title_style)
             .borders(Borders::ALL)
             .border_style(self.border_style);
-        Paragraph::new(self.content)
-            .wrap(Wrap { trim: true })
+        let paragraph = Paragraph::new(self.content)
+            .wrap(Wrap {
+                trim: !self.preformatted,
+            })
             .style(self.style)
-            .block(block)
-            .centered()
-            .render(area, buf);
+            .block(block);
+        match self.preformatted {
+            true => paragraph.render(area, buf),
+            false => paragraph.centered().render(area, buf),
+        }
     }
 }
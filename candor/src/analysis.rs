@@ -0,0 +1,734 @@
+//! Payload analysis for reverse-engineering undocumented messages
+//!
+//! Examines the recent payload history of a message and proposes rolling
+//! counters, constant bytes and checksum bytes, infers a draft message
+//! definition, or ranks bit fields by correlation with a reference signal.
+//! Bit positions use the DBC numbering (`byte * 8 + bit`).
+
+use crate::db::{self, ByteOrder};
+use crate::e2e::{crc8, H2F, J1850};
+use crate::invalid;
+use crate::Packet;
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Minimum number of payloads needed for a proposal
+const MIN_SAMPLES: usize = 16;
+/// Fraction of transitions that must match, allowing for lost frames
+const MIN_RATIO: f64 = 0.9;
+/// Fraction of a bit's changes that must coincide with a change of the next
+/// lower bit (a carry) to consider them part of the same value
+const MIN_CARRY: f64 = 0.75;
+/// Change rate from which adjacent bits are considered noise-like parts of
+/// the same value
+const NOISE_RATE: f64 = 0.3;
+
+/// Bit field incrementing by one with every frame, modulo `modulo`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Counter {
+    pub start_bit: usize,
+    pub size: usize,
+    pub modulo: u16,
+}
+
+/// Checksum algorithm, computed over all other payload bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Xor,
+    Sum,
+    /// CRC-8/SAE-J1850
+    Crc8J1850,
+    /// AUTOSAR CRC8H2F
+    Crc8H2F,
+    /// CRC-8/SMBUS (polynomial 0x07, no XOR)
+    Crc8,
+}
+
+impl Algorithm {
+    const ALL: [Algorithm; 5] = [
+        Algorithm::Xor,
+        Algorithm::Sum,
+        Algorithm::Crc8J1850,
+        Algorithm::Crc8H2F,
+        Algorithm::Crc8,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Xor => "XOR",
+            Self::Sum => "sum",
+            Self::Crc8J1850 => "CRC8 J1850",
+            Self::Crc8H2F => "CRC8 H2F",
+            Self::Crc8 => "CRC8",
+        }
+    }
+
+    /// Checksum of a payload, skipping the checksum byte itself
+    pub fn compute(&self, bytes: &[u8], skip: usize) -> u8 {
+        let (before, after) = (&bytes[..skip], &bytes[skip + 1..]);
+        let others = before.iter().chain(after.iter());
+        match self {
+            Self::Xor => others.fold(0, |a, b| a ^ b),
+            Self::Sum => others.fold(0u8, |a, b| a.wrapping_add(*b)),
+            Self::Crc8J1850 => {
+                crc8(J1850, crc8(J1850, 0xff, before), after) ^ 0xff
+            }
+            Self::Crc8H2F => crc8(H2F, crc8(H2F, 0xff, before), after) ^ 0xff,
+            Self::Crc8 => crc8(0x07, crc8(0x07, 0, before), after),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    pub byte: usize,
+    pub algorithm: Algorithm,
+}
+
+/// Proposed fields of a message
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Annotations {
+    pub counters: Vec<Counter>,
+    pub checksums: Vec<Checksum>,
+    /// Bytes that never changed, with their value
+    pub constants: Vec<(usize, u8)>,
+}
+
+impl Annotations {
+    /// Analyze consecutive packets of a message, oldest first
+    ///
+    /// Only packets with the length of the latest one are considered.
+    /// Returns `None` if there are too few of them.
+    pub fn analyze<'a>(
+        packets: impl IntoIterator<Item = &'a Packet>,
+    ) -> Option<Self> {
+        let packets: Vec<&Packet> = packets.into_iter().collect();
+        let payloads = payloads(&packets);
+        let len = payloads.first()?.len();
+        if payloads.len() < MIN_SAMPLES {
+            return None;
+        }
+
+        let mut annotations = Self::default();
+        for byte in 0..len {
+            let first = payloads[0][byte];
+            if payloads.iter().all(|p| p[byte] == first) {
+                annotations.constants.push((byte, first));
+            }
+        }
+
+        // largest fields first, so the bits of a counter aren't also
+        // reported as smaller counters
+        let mut covered = vec![false; len * 8];
+        for size in (2..=8).rev() {
+            for start_bit in 0..len * 8 {
+                if start_bit % 8 + size > 8
+                    || covered[start_bit..start_bit + size].iter().any(|c| *c)
+                {
+                    continue;
+                }
+                if let Some(counter) = counter(&payloads, start_bit, size) {
+                    covered[start_bit..start_bit + size].fill(true);
+                    annotations.counters.push(counter);
+                }
+            }
+        }
+        annotations.counters.sort_by_key(|c| c.start_bit);
+
+        for byte in 0..len {
+            let field = byte * 8..byte * 8 + 8;
+            if covered[field].iter().any(|c| *c)
+                || annotations.constants.iter().any(|(b, _)| *b == byte)
+            {
+                continue;
+            }
+            let algorithm = Algorithm::ALL.into_iter().find(|a| {
+                let matches = payloads
+                    .iter()
+                    .filter(|p| a.compute(p, byte) == p[byte])
+                    .count();
+                matches as f64 >= payloads.len() as f64 * 0.95
+            });
+            if let Some(algorithm) = algorithm {
+                annotations.checksums.push(Checksum { byte, algorithm });
+            }
+        }
+
+        // the XOR of all bytes being zero makes every byte a checksum of
+        // the others, so assume it's the last one
+        let xor = |c: &&Checksum| c.algorithm == Algorithm::Xor;
+        if annotations.checksums.iter().filter(xor).count() > 1 {
+            let last = *annotations.checksums.iter().rfind(xor).unwrap();
+            annotations
+                .checksums
+                .retain(|c| c.algorithm != Algorithm::Xor || *c == last);
+        }
+
+        Some(annotations)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+            && self.checksums.is_empty()
+            && self.constants.is_empty()
+    }
+
+    /// Annotation lines for display
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .counters
+            .iter()
+            .map(|c| {
+                format!(
+                    "? counter {}|{} (0..{})",
+                    c.start_bit,
+                    c.size,
+                    c.modulo - 1
+                )
+            })
+            .collect();
+        for checksum in self.checksums.iter() {
+            lines.push(format!(
+                "? checksum byte {} ({})",
+                checksum.byte,
+                checksum.algorithm.name()
+            ));
+        }
+        if !self.constants.is_empty() {
+            let constants: Vec<String> = self
+                .constants
+                .iter()
+                .map(|(byte, value)| format!("{byte}={value:02x}"))
+                .collect();
+            lines.push(format!("? constant {}", constants.join(" ")));
+        }
+        lines
+    }
+}
+
+/// Payloads with the length of the latest packet
+fn payloads<'a>(packets: &[&'a Packet]) -> Vec<&'a [u8]> {
+    let len = packets.last().map(|p| p.bytes.len()).unwrap_or_default();
+    packets
+        .iter()
+        .map(|p| p.bytes.as_slice())
+        .filter(|b| b.len() == len)
+        .collect()
+}
+
+/// Infer a draft definition of a message from consecutive packets, oldest
+/// first
+///
+/// Bits changing together are grouped into values, following carries
+/// between bytes to tell little from big-endian values.  Single bits
+/// changing rarely are proposed as flags, and detected counters and
+/// checksums are included under those names.  Bits that never changed are
+/// left out.
+pub fn infer<'a>(
+    id: u32,
+    extended: bool,
+    packets: impl IntoIterator<Item = &'a Packet>,
+) -> Option<db::Message> {
+    let packets: Vec<&Packet> = packets.into_iter().collect();
+    let annotations = Annotations::analyze(packets.iter().copied())?;
+    let payloads = payloads(&packets);
+    let bits = payloads[0].len() * 8;
+    let transitions = (payloads.len() - 1) as f64;
+
+    let bit = |p: &[u8], b: usize| (p[b / 8] >> (b % 8)) & 1;
+    let changes: Vec<Vec<bool>> = payloads
+        .windows(2)
+        .map(|w| (0..bits).map(|b| bit(w[0], b) != bit(w[1], b)).collect())
+        .collect();
+    let flips: Vec<usize> = (0..bits)
+        .map(|b| changes.iter().filter(|c| c[b]).count())
+        .collect();
+    let rate = |b: usize| flips[b] as f64 / transitions;
+
+    // how strongly bit `high` continues the value ending at bit `low`
+    let link = |low: usize, high: usize| -> f64 {
+        if flips[low] == 0 || flips[high] == 0 {
+            return 0.0;
+        }
+        let carries = changes.iter().filter(|c| c[high] && c[low]).count();
+        let carry = carries as f64 / flips[high] as f64;
+        if low / 8 == high / 8
+            && rate(low) >= NOISE_RATE
+            && rate(high) >= NOISE_RATE
+        {
+            carry.max(MIN_CARRY)
+        } else {
+            carry
+        }
+    };
+
+    // next more significant bit of each bit, if part of the same value;
+    // the top bit of a byte continues in the following (little-endian) or
+    // preceding (big-endian) byte
+    let mut next: Vec<Option<usize>> = vec![None; bits];
+    let mut previous: Vec<Option<(usize, f64)>> = vec![None; bits];
+    for low in 0..bits {
+        let candidates = match low % 8 {
+            7 => vec![Some(low + 1), (low >= 15).then(|| low - 15)],
+            _ => vec![Some(low + 1)],
+        };
+        for high in candidates.into_iter().flatten() {
+            if high >= bits {
+                continue;
+            }
+            let strength = link(low, high);
+            let better = previous[high].is_none_or(|(_, s)| strength > s);
+            if strength >= MIN_CARRY && better {
+                if let Some((other, _)) = previous[high] {
+                    next[other] = None;
+                }
+                if let Some(old) = next[low] {
+                    previous[old] = None;
+                }
+                next[low] = Some(high);
+                previous[high] = Some((low, strength));
+            }
+        }
+    }
+
+    let mut covered = vec![false; bits];
+    for counter in annotations.counters.iter() {
+        covered[counter.start_bit..counter.start_bit + counter.size].fill(true);
+    }
+    for checksum in annotations.checksums.iter() {
+        covered[checksum.byte * 8..checksum.byte * 8 + 8].fill(true);
+    }
+
+    let mut signals: Vec<(usize, db::Signal)> = vec![];
+    for lsb in (0..bits).filter(|b| flips[*b] > 0 && previous[*b].is_none()) {
+        let mut chain = vec![lsb];
+        while let Some(high) = next[*chain.last().unwrap()] {
+            chain.push(high);
+        }
+        if chain.iter().any(|b| covered[*b]) {
+            continue;
+        }
+        let msb = *chain.last().unwrap();
+        let big_endian = chain.windows(2).any(|w| w[1] < w[0]);
+        let first = *chain.iter().min().unwrap();
+        let signal = if chain.len() == 1 && rate(lsb) < NOISE_RATE {
+            db::Signal::new(&format!("Flag_{lsb}"), lsb, 1)
+        } else if big_endian {
+            let mut signal =
+                db::Signal::new(&format!("Signal_{msb}"), msb, chain.len());
+            signal.byte_order = ByteOrder::BigEndian;
+            signal
+        } else {
+            db::Signal::new(&format!("Signal_{lsb}"), lsb, chain.len())
+        };
+        signals.push((first, signal));
+    }
+    for counter in annotations.counters.iter() {
+        let mut signal =
+            db::Signal::new("Counter", counter.start_bit, counter.size);
+        if annotations.counters.len() > 1 {
+            signal.name = format!("Counter_{}", counter.start_bit);
+        }
+        signals.push((counter.start_bit, signal));
+    }
+    for checksum in annotations.checksums.iter() {
+        let start_bit = checksum.byte * 8;
+        let mut signal = db::Signal::new("Checksum", start_bit, 8);
+        if annotations.checksums.len() > 1 {
+            signal.name = format!("Checksum_{}", checksum.byte);
+        }
+        signals.push((start_bit, signal));
+    }
+    signals.sort_by_key(|(first, _)| *first);
+
+    // observed range
+    let mut signals: Vec<db::Signal> =
+        signals.into_iter().map(|(_, s)| s).collect();
+    for signal in signals.iter_mut() {
+        let values = payloads.iter().filter_map(|p| signal.raw(p));
+        let (min, max) = values
+            .fold((u64::MAX, 0), |(min, max), v| (min.min(v), max.max(v)));
+        (signal.min, signal.max) = (min as f64, max as f64);
+    }
+
+    Some(db::Message {
+        id,
+        extended,
+        name: format!("MSG_{id:X}"),
+        size: payloads[0].len(),
+        signals,
+        ..Default::default()
+    })
+}
+
+/// Typical period of consecutive packets in milliseconds, the median of the
+/// intervals between them
+pub fn cycle_time<'a>(
+    packets: impl IntoIterator<Item = &'a Packet>,
+) -> Option<u32> {
+    let times: Vec<Instant> =
+        packets.into_iter().filter_map(|p| p.time).collect();
+    let mut intervals: Vec<Duration> = times
+        .windows(2)
+        .map(|w| w[1].saturating_duration_since(w[0]))
+        .collect();
+    intervals.sort();
+    let median = intervals.get(intervals.len() / 2)?;
+    Some((median.as_secs_f64() * 1000.0).round() as u32)
+}
+
+/// Time series of a signal, ordered by time
+pub type Series = Vec<(Instant, f64)>;
+
+/// Reference signal recorded separately, as time in seconds from the start
+/// of the trace and value
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Recording(pub Vec<(f64, f64)>);
+
+impl Recording {
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    /// Parse CSV lines of `<time>,<value>`, with an optional header line
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut samples = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut columns = line.split([',', ';', '\t']).map(str::trim);
+            // times must be representable as offsets from the trace start
+            let time = columns
+                .next()
+                .and_then(|t| t.parse::<f64>().ok())
+                .filter(|t| Duration::try_from_secs_f64(t.abs()).is_ok());
+            let value = columns.next().and_then(|v| v.parse::<f64>().ok());
+            match (time, value) {
+                (Some(time), Some(value)) => samples.push((time, value)),
+                _ if samples.is_empty() && number == 0 => {}
+                _ => {
+                    return Err(invalid(format!(
+                        "line {}: expected <time>,<value>",
+                        number + 1
+                    )))
+                }
+            }
+        }
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self(samples))
+    }
+
+    /// Time series with the trace starting at `start`
+    pub fn series(&self, start: Instant) -> Series {
+        self.0
+            .iter()
+            .filter_map(|(time, value)| {
+                let offset = Duration::try_from_secs_f64(*time).ok()?;
+                Some((start.checked_add(offset)?, *value))
+            })
+            .collect()
+    }
+}
+
+/// Bit field of a message correlating with a reference signal
+#[derive(Clone, Debug, PartialEq)]
+pub struct Correlation {
+    pub id: u32,
+    pub signal: db::Signal,
+    /// Pearson correlation coefficient
+    pub coefficient: f64,
+}
+
+impl Correlation {
+    pub fn text(&self) -> String {
+        let order = match self.signal.byte_order {
+            ByteOrder::LittleEndian => 1,
+            ByteOrder::BigEndian => 0,
+        };
+        format!(
+            "{:X} {}|{}@{} r={:+.3}",
+            self.id,
+            self.signal.start_bit,
+            self.signal.size,
+            order,
+            self.coefficient
+        )
+    }
+}
+
+/// Rank bit fields of consecutive packets of a message by their
+/// correlation with a reference signal
+///
+/// Candidates are all bytes, 16-bit little and big-endian values at every
+/// byte offset and the signals of the inferred definition.  Each packet is
+/// paired with the latest reference value at its time.
+pub fn correlate<'a>(
+    packets: impl IntoIterator<Item = &'a Packet>,
+    reference: &[(Instant, f64)],
+) -> Vec<Correlation> {
+    let packets: Vec<&Packet> = packets.into_iter().collect();
+    let Some(last) = packets.last() else {
+        return vec![];
+    };
+    let len = last.bytes.len();
+
+    let mut candidates = vec![];
+    for byte in 0..len {
+        candidates.push(db::Signal::new("", byte * 8, 8));
+        if byte + 1 < len {
+            candidates.push(db::Signal::new("", byte * 8, 16));
+            let mut signal = db::Signal::new("", byte * 8 + 7, 16);
+            signal.byte_order = ByteOrder::BigEndian;
+            candidates.push(signal);
+        }
+    }
+    if let Some(message) = infer(last.id, last.extended, packets.clone()) {
+        for signal in message.signals {
+            let known = candidates.iter().any(|c| {
+                (c.start_bit, c.size, c.byte_order)
+                    == (signal.start_bit, signal.size, signal.byte_order)
+            });
+            if !known {
+                candidates.push(signal);
+            }
+        }
+    }
+
+    // reference value of each packet
+    let samples: Vec<(&[u8], f64)> = packets
+        .iter()
+        .filter(|p| p.bytes.len() == len)
+        .filter_map(|p| {
+            let time = p.time?;
+            let index = reference.partition_point(|(t, _)| *t <= time);
+            Some((p.bytes.as_slice(), reference.get(index.checked_sub(1)?)?.1))
+        })
+        .collect();
+
+    let mut correlations: Vec<Correlation> = candidates
+        .into_iter()
+        .filter_map(|signal| {
+            let pairs: Vec<(f64, f64)> = samples
+                .iter()
+                .filter_map(|(bytes, r)| Some((signal.raw(bytes)? as f64, *r)))
+                .collect();
+            let coefficient = pearson(&pairs)?;
+            Some(Correlation {
+                id: last.id,
+                signal,
+                coefficient,
+            })
+        })
+        .collect();
+    correlations
+        .sort_by(|a, b| b.coefficient.abs().total_cmp(&a.coefficient.abs()));
+    correlations
+}
+
+/// Pearson correlation coefficient, `None` for too few samples or constant
+/// values
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < MIN_SAMPLES {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        xy += dx * dy;
+        xx += dx * dx;
+        yy += dy * dy;
+    }
+    (xx > 0.0 && yy > 0.0).then(|| xy / (xx * yy).sqrt())
+}
+
+/// Check whether a bit field behaves like a rolling counter
+fn counter(
+    payloads: &[&[u8]],
+    start_bit: usize,
+    size: usize,
+) -> Option<Counter> {
+    let mask = ((1u16 << size) - 1) as u8;
+    let values: Vec<u8> = payloads
+        .iter()
+        .map(|p| (p[start_bit / 8] >> (start_bit % 8)) & mask)
+        .collect();
+
+    // the top bit must be used, or a smaller field would describe it
+    let max = *values.iter().max()?;
+    if max < 1 << (size - 1) {
+        return None;
+    }
+    let modulo = max as u16 + 1;
+    let increments = values
+        .windows(2)
+        .filter(|w| (w[0] as u16 + 1) % modulo == w[1] as u16)
+        .count();
+    let ratio = increments as f64 / (values.len() - 1) as f64;
+    (ratio >= MIN_RATIO).then_some(Counter {
+        start_bit,
+        size,
+        modulo,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn annotations() {
+        let packets: Vec<Packet> = (0..40u8)
+            .map(|i| {
+                // counter 0..14 in the high nibble of byte 1, with a
+                // changing low nibble, a checksum in byte 7
+                let mut bytes = vec![
+                    0,
+                    (i % 15) << 4 | i.wrapping_mul(7) & 0xf,
+                    0x55,
+                    i,
+                    0,
+                    0,
+                    0,
+                    0,
+                ];
+                bytes[4] = crc8(0x07, 0, &[i]);
+                bytes[7] = Algorithm::Crc8J1850.compute(&bytes, 7);
+                Packet {
+                    bytes,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let annotations = Annotations::analyze(packets.iter()).unwrap();
+        assert_eq!(
+            annotations.counters,
+            vec![
+                Counter {
+                    start_bit: 12,
+                    size: 4,
+                    modulo: 15
+                },
+                Counter {
+                    start_bit: 24,
+                    size: 6,
+                    modulo: 40
+                },
+            ]
+        );
+        assert_eq!(
+            annotations.checksums,
+            vec![Checksum {
+                byte: 7,
+                algorithm: Algorithm::Crc8J1850
+            }]
+        );
+        assert_eq!(
+            annotations.constants,
+            vec![(0, 0), (2, 0x55), (5, 0), (6, 0)]
+        );
+
+        assert!(Annotations::analyze(packets[..10].iter()).is_none());
+    }
+
+    #[test]
+    fn inference() {
+        let packets: Vec<Packet> = (0..100u16)
+            .map(|i| {
+                let flags = (i / 10 % 2) as u8 | ((i / 7 % 2) as u8) << 3;
+                let little = (250 + 3 * i).to_le_bytes();
+                let big = (0x2f0 + 3 * i).to_be_bytes();
+                let mut bytes = vec![
+                    flags,
+                    little[0],
+                    little[1],
+                    big[0],
+                    big[1],
+                    (i % 16) as u8,
+                    0,
+                    0,
+                ];
+                bytes[7] = Algorithm::Xor.compute(&bytes, 7);
+                Packet {
+                    bytes,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let message = infer(0x123, false, packets.iter()).unwrap();
+        assert_eq!(message.size, 8);
+        let layout: Vec<(&str, usize, usize, ByteOrder)> = message
+            .signals
+            .iter()
+            .map(|s| (s.name.as_str(), s.start_bit, s.size, s.byte_order))
+            .collect();
+        use ByteOrder::*;
+        assert_eq!(
+            layout,
+            vec![
+                ("Flag_0", 0, 1, LittleEndian),
+                ("Flag_3", 3, 1, LittleEndian),
+                ("Signal_8", 8, 10, LittleEndian),
+                ("Signal_26", 26, 11, BigEndian),
+                ("Counter", 40, 4, LittleEndian),
+                ("Checksum", 56, 8, LittleEndian),
+            ]
+        );
+        assert_eq!(message.signals[2].min, 250.0);
+        assert_eq!(message.signals[3].max, (0x2f0 + 3 * 99) as f64);
+    }
+
+    #[test]
+    fn correlation() {
+        let start = Instant::now();
+        let speed = |i: u16| ((i as f64) / 10.0).sin() * 50.0 + 60.0;
+        let packets: Vec<Packet> = (0..200u16)
+            .map(|i| {
+                // speed in 0.01 km/h as big-endian value at byte 2
+                let raw = (speed(i) * 100.0) as u16;
+                let noise = crc8(0x07, 0, &[i as u8]);
+                let mut bytes = vec![noise, 0, 0, 0, noise ^ 0x5a, 0, 0, 0];
+                bytes[2..4].copy_from_slice(&raw.to_be_bytes());
+                Packet {
+                    time: Some(start + Duration::from_millis(i as u64 * 10)),
+                    bytes,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let recording =
+            Recording::parse(
+                &std::iter::once("time,speed".to_string())
+                    .chain((0..200u16).map(|i| {
+                        format!("{:.3},{:.2}", i as f64 * 0.01, speed(i))
+                    }))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .unwrap();
+        assert_eq!(recording.0.len(), 200);
+        assert!(Recording::parse("0,1\ninf,2").is_err());
+        assert!(Recording::parse("0,1\n1e30,2").is_err());
+
+        let correlations = correlate(packets.iter(), &recording.series(start));
+        let best = &correlations[0];
+        assert_eq!(
+            (
+                best.signal.start_bit,
+                best.signal.size,
+                best.signal.byte_order
+            ),
+            (23, 16, ByteOrder::BigEndian)
+        );
+        assert!(best.coefficient > 0.99);
+        assert_eq!(best.text(), "0 23|16@0 r=+1.000");
+    }
+}
@@ -0,0 +1,446 @@
+//! CANopen (CiA 301) protocol decoding
+//!
+//! Identifiers are classified by the predefined connection set into their
+//! communication objects, and NMT, heartbeat, emergency and SDO frames
+//! decoded to text.  SDO transfers (expedited and segmented) are tracked
+//! per node.  PDO contents are decoded through EDS/DCF databases (see
+//! [`crate::db::eds`]).
+
+use crate::Packet;
+use std::collections::HashMap;
+
+/// Communication object of a CANopen identifier
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Object {
+    Nmt,
+    Sync,
+    Emergency(u8),
+    Time,
+    /// Transmit PDO number (1 - 4) of a node
+    Tpdo(u8, u8),
+    /// Receive PDO number (1 - 4) of a node
+    Rpdo(u8, u8),
+    /// SDO server to client (response)
+    SdoTx(u8),
+    /// SDO client to server (request)
+    SdoRx(u8),
+    Heartbeat(u8),
+    Lss,
+}
+
+impl Object {
+    /// Classify an 11-bit identifier by the predefined connection set
+    pub fn classify(id: u32) -> Option<Self> {
+        let node = (id & 0x7f) as u8;
+        let object = match (id & 0x780, node) {
+            (0x000, 0) => Self::Nmt,
+            (0x080, 0) => Self::Sync,
+            (0x080, _) => Self::Emergency(node),
+            (0x100, 0) => Self::Time,
+            (0x180, _) => Self::Tpdo(1, node),
+            (0x200, _) => Self::Rpdo(1, node),
+            (0x280, _) => Self::Tpdo(2, node),
+            (0x300, _) => Self::Rpdo(2, node),
+            (0x380, _) => Self::Tpdo(3, node),
+            (0x400, _) => Self::Rpdo(3, node),
+            (0x480, _) => Self::Tpdo(4, node),
+            (0x500, _) => Self::Rpdo(4, node),
+            (0x580, _) => Self::SdoTx(node),
+            (0x600, _) => Self::SdoRx(node),
+            (0x780, 0x64 | 0x65) => Self::Lss,
+            (0x700, _) => Self::Heartbeat(node),
+            _ => return None,
+        };
+        if node == 0 && !matches!(object, Self::Nmt | Self::Sync | Self::Time) {
+            return None;
+        }
+        Some(object)
+    }
+
+    /// Short description, e.g. `TPDO1 node 5`
+    pub fn text(&self) -> String {
+        match self {
+            Self::Nmt => "NMT".into(),
+            Self::Sync => "SYNC".into(),
+            Self::Time => "TIME".into(),
+            Self::Lss => "LSS".into(),
+            Self::Emergency(node) => format!("EMCY node {node}"),
+            Self::Tpdo(pdo, node) => format!("TPDO{pdo} node {node}"),
+            Self::Rpdo(pdo, node) => format!("RPDO{pdo} node {node}"),
+            Self::SdoTx(node) => format!("SDO tx node {node}"),
+            Self::SdoRx(node) => format!("SDO rx node {node}"),
+            Self::Heartbeat(node) => format!("Heartbeat node {node}"),
+        }
+    }
+}
+
+pub fn nmt_command_name(command: u8) -> &'static str {
+    match command {
+        0x01 => "Start",
+        0x02 => "Stop",
+        0x80 => "Enter pre-operational",
+        0x81 => "Reset node",
+        0x82 => "Reset communication",
+        _ => "Unknown",
+    }
+}
+
+pub fn nmt_state_name(state: u8) -> &'static str {
+    match state & 0x7f {
+        0x00 => "Boot-up",
+        0x04 => "Stopped",
+        0x05 => "Operational",
+        0x7f => "Pre-operational",
+        _ => "Unknown",
+    }
+}
+
+/// Error code class of an emergency message
+pub fn emergency_name(code: u16) -> &'static str {
+    match code >> 8 {
+        0x00 => "Error reset or no error",
+        0x10 => "Generic error",
+        0x20..=0x2f => "Current",
+        0x30..=0x3f => "Voltage",
+        0x40..=0x4f => "Temperature",
+        0x50 => "Device hardware",
+        0x60..=0x6f => "Device software",
+        0x70 => "Additional modules",
+        0x80 => "Monitoring",
+        0x81 => "Communication",
+        0x82 => "Protocol error",
+        0x90 => "External error",
+        0xf0 => "Additional functions",
+        0xff => "Device specific",
+        _ => "Unknown",
+    }
+}
+
+pub fn abort_name(code: u32) -> &'static str {
+    match code {
+        0x0503_0000 => "Toggle bit not alternated",
+        0x0504_0000 => "SDO protocol timed out",
+        0x0504_0001 => "Invalid command specifier",
+        0x0504_0005 => "Out of memory",
+        0x0601_0000 => "Unsupported access to an object",
+        0x0601_0001 => "Attempt to read a write only object",
+        0x0601_0002 => "Attempt to write a read only object",
+        0x0602_0000 => "Object does not exist",
+        0x0604_0041 => "Object cannot be mapped to the PDO",
+        0x0604_0042 => "PDO length exceeded",
+        0x0604_0043 => "General parameter incompatibility",
+        0x0606_0000 => "Hardware error",
+        0x0607_0010 => "Data type does not match",
+        0x0609_0011 => "Sub-index does not exist",
+        0x0609_0030 => "Invalid value",
+        0x0609_0031 => "Value too high",
+        0x0609_0032 => "Value too low",
+        0x0800_0000 => "General error",
+        0x0800_0020 => "Data cannot be transferred or stored",
+        0x0800_0021 => "Local control",
+        0x0800_0022 => "Device state",
+        _ => "Unknown",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Completed (or aborted) SDO transfer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub node: u8,
+    pub index: u16,
+    pub subindex: u8,
+    /// Read from (upload) or written to (download) the server
+    pub upload: bool,
+    pub data: Vec<u8>,
+    pub abort: Option<u32>,
+}
+
+impl Transfer {
+    pub fn text(&self) -> String {
+        let direction = if self.upload { "Read" } else { "Write" };
+        let object = format!("{:04X}:{:02X}", self.index, self.subindex);
+        match self.abort {
+            Some(code) => format!(
+                "{direction} {object} aborted {code:08X} {}",
+                abort_name(code)
+            ),
+            None => {
+                let value = match self.data.len() {
+                    1..=4 => {
+                        let mut value = [0u8; 4];
+                        value[..self.data.len()].copy_from_slice(&self.data);
+                        format!("{}", u32::from_le_bytes(value))
+                    }
+                    _ if self.data.iter().all(|b| (0x20..0x7f).contains(b)) => {
+                        format!("\"{}\"", String::from_utf8_lossy(&self.data))
+                    }
+                    _ => hex(&self.data),
+                };
+                format!("{direction} {object} = {value}")
+            }
+        }
+    }
+}
+
+/// Transfer in progress
+struct Session {
+    index: u16,
+    subindex: u8,
+    upload: bool,
+    data: Vec<u8>,
+    /// All data has been sent, awaiting the server's confirmation
+    complete: bool,
+}
+
+/// Tracks SDO transfers of all nodes
+#[derive(Default)]
+pub struct Decoder {
+    sessions: HashMap<u8, Session>,
+    /// Last completed transfer by node
+    transfers: HashMap<u8, Transfer>,
+}
+
+impl Clone for Decoder {
+    fn clone(&self) -> Self {
+        // transfers in progress are not carried over
+        Self {
+            transfers: self.transfers.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Data of an expedited initiate frame, honouring the size indication
+fn expedited(bytes: &[u8]) -> &[u8] {
+    let unused = if bytes[0] & 0x01 != 0 {
+        ((bytes[0] >> 2) & 3) as usize
+    } else {
+        0
+    };
+    &bytes[4..8 - unused]
+}
+
+/// Data of a segment frame
+fn segment(bytes: &[u8]) -> &[u8] {
+    let unused = ((bytes[0] >> 1) & 7) as usize;
+    &bytes[1..8 - unused]
+}
+
+impl Decoder {
+    /// Last completed SDO transfer of a node
+    pub fn transfer(&self, node: u8) -> Option<&Transfer> {
+        self.transfers.get(&node)
+    }
+
+    /// Process a packet, returning an SDO transfer once it completes
+    pub fn process(&mut self, packet: &Packet) -> Option<Transfer> {
+        let bytes = packet.bytes.as_slice();
+        if packet.extended || bytes.len() < 8 {
+            return None;
+        }
+        let (node, request) = match Object::classify(packet.id)? {
+            Object::SdoRx(node) => (node, true),
+            Object::SdoTx(node) => (node, false),
+            _ => return None,
+        };
+        let index = u16::from_le_bytes([bytes[1], bytes[2]]);
+        let subindex = bytes[3];
+        let mut open = |upload, data: &[u8], complete| {
+            let session = Session {
+                index,
+                subindex,
+                upload,
+                data: data.to_vec(),
+                complete,
+            };
+            self.sessions.insert(node, session);
+        };
+
+        match (request, bytes[0] >> 5) {
+            // initiate download
+            (true, 1) => {
+                if bytes[0] & 0x02 != 0 {
+                    open(false, expedited(bytes), true);
+                } else {
+                    open(false, &[], false);
+                }
+            }
+            // download segment
+            (true, 0) => {
+                let session = self.sessions.get_mut(&node)?;
+                session.data.extend_from_slice(segment(bytes));
+                session.complete = bytes[0] & 0x01 != 0;
+            }
+            // initiate upload
+            (true, 2) => open(true, &[], false),
+            // initiate upload response
+            (false, 2) => {
+                if bytes[0] & 0x02 != 0 {
+                    open(true, expedited(bytes), true);
+                } else {
+                    open(true, &[], false);
+                    return None;
+                }
+            }
+            // upload segment response
+            (false, 0) => {
+                let session = self.sessions.get_mut(&node)?;
+                session.data.extend_from_slice(segment(bytes));
+                session.complete = bytes[0] & 0x01 != 0;
+            }
+            // initiate download and download segment responses
+            (false, 3 | 1) => {}
+            // abort by either side
+            (_, 4) => {
+                let session = self.sessions.remove(&node);
+                let transfer = Transfer {
+                    node,
+                    index,
+                    subindex,
+                    upload: session.is_some_and(|s| s.upload),
+                    data: vec![],
+                    abort: Some(u32::from_le_bytes([
+                        bytes[4], bytes[5], bytes[6], bytes[7],
+                    ])),
+                };
+                self.transfers.insert(node, transfer.clone());
+                return Some(transfer);
+            }
+            _ => return None,
+        }
+
+        // transfers complete with the last server frame
+        if request || !self.sessions.get(&node)?.complete {
+            return None;
+        }
+        let session = self.sessions.remove(&node)?;
+        let transfer = Transfer {
+            node,
+            index: session.index,
+            subindex: session.subindex,
+            upload: session.upload,
+            data: session.data,
+            abort: None,
+        };
+        self.transfers.insert(node, transfer.clone());
+        Some(transfer)
+    }
+}
+
+/// Describe a frame by its communication object: NMT commands, node
+/// states, emergency codes and SDO commands
+pub fn describe(packet: &Packet) -> Option<String> {
+    if packet.extended {
+        return None;
+    }
+    let bytes = packet.bytes.as_slice();
+    let object = Object::classify(packet.id)?;
+    let text = match object {
+        Object::Nmt => {
+            let [command, node, ..] = bytes else {
+                return None;
+            };
+            let target = match node {
+                0 => "all nodes".to_string(),
+                node => format!("node {node}"),
+            };
+            format!("{} {target}", nmt_command_name(*command))
+        }
+        Object::Heartbeat(_) => nmt_state_name(*bytes.first()?).to_string(),
+        Object::Emergency(_) => {
+            let [low, high, register, ..] = bytes else {
+                return None;
+            };
+            let code = u16::from_le_bytes([*low, *high]);
+            format!(
+                "{code:04X} {} (register {register:02X})",
+                emergency_name(code)
+            )
+        }
+        Object::SdoRx(_) | Object::SdoTx(_) if bytes.len() >= 8 => {
+            let request = matches!(object, Object::SdoRx(_));
+            let object = format!(
+                "{:04X}:{:02X}",
+                u16::from_le_bytes([bytes[1], bytes[2]]),
+                bytes[3]
+            );
+            match (request, bytes[0] >> 5) {
+                (true, 1) => format!("Initiate download {object}"),
+                (true, 2) => format!("Initiate upload {object}"),
+                (false, 2) => format!("Upload response {object}"),
+                (false, 3) => format!("Download response {object}"),
+                (true, 0) => "Download segment".to_string(),
+                (false, 0) => "Upload segment".to_string(),
+                (true, 3) => "Upload segment request".to_string(),
+                (false, 1) => "Download segment response".to_string(),
+                (_, 4) => format!("Abort {object}"),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn objects() {
+        assert_eq!(Object::classify(0x000), Some(Object::Nmt));
+        assert_eq!(Object::classify(0x080), Some(Object::Sync));
+        assert_eq!(Object::classify(0x085), Some(Object::Emergency(5)));
+        assert_eq!(Object::classify(0x285), Some(Object::Tpdo(2, 5)));
+        assert_eq!(Object::classify(0x60a), Some(Object::SdoRx(10)));
+        assert_eq!(Object::classify(0x77f), Some(Object::Heartbeat(127)));
+        assert_eq!(Object::classify(0x7e5), Some(Object::Lss));
+        assert_eq!(Object::classify(0x180), None);
+
+        let text = describe(&Packet::new(0x000, &[0x01, 0x00])).unwrap();
+        assert_eq!(text, "Start all nodes");
+        let text = describe(&Packet::new(0x705, &[0x05])).unwrap();
+        assert_eq!(text, "Operational");
+        let emcy = [0x10, 0x81, 0x11, 0, 0, 0, 0, 0];
+        let text = describe(&Packet::new(0x085, &emcy)).unwrap();
+        assert_eq!(text, "8110 Communication (register 11)");
+    }
+
+    #[test]
+    fn sdo_transfers() {
+        let mut sdo = Decoder::default();
+        // expedited upload of the device type
+        let request = [0x40, 0x00, 0x10, 0x00, 0, 0, 0, 0];
+        assert!(sdo.process(&Packet::new(0x605, &request)).is_none());
+        let response = [0x43, 0x00, 0x10, 0x00, 0x92, 0x01, 0x02, 0x00];
+        let transfer = sdo.process(&Packet::new(0x585, &response)).unwrap();
+        assert_eq!(transfer.text(), "Read 1000:00 = 131474");
+
+        // segmented upload of the device name
+        let request = [0x40, 0x08, 0x10, 0x00, 0, 0, 0, 0];
+        assert!(sdo.process(&Packet::new(0x605, &request)).is_none());
+        let response = [0x41, 0x08, 0x10, 0x00, 10, 0, 0, 0];
+        assert!(sdo.process(&Packet::new(0x585, &response)).is_none());
+        let segment = [0x00, b'C', b'A', b'N', b'o', b'p', b'e', b'n'];
+        assert!(sdo.process(&Packet::new(0x585, &segment)).is_none());
+        let segment = [0x19, b' ', b'I', b'O', 0, 0, 0, 0];
+        let transfer = sdo.process(&Packet::new(0x585, &segment)).unwrap();
+        assert_eq!(transfer.text(), "Read 1008:00 = \"CANopen IO\"");
+
+        // download aborted by the server
+        let request = [0x2b, 0x17, 0x10, 0x00, 0xe8, 0x03, 0, 0];
+        assert!(sdo.process(&Packet::new(0x605, &request)).is_none());
+        let abort = [0x80, 0x17, 0x10, 0x00, 0x02, 0x00, 0x01, 0x06];
+        let transfer = sdo.process(&Packet::new(0x585, &abort)).unwrap();
+        assert_eq!(transfer.abort, Some(0x0601_0002));
+        assert_eq!(sdo.transfer(5), Some(&transfer));
+    }
+}
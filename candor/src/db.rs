@@ -0,0 +1,384 @@
+//! Message database model shared by all supported database file formats
+
+pub mod arxml;
+pub mod dbc;
+pub mod eds;
+pub mod kcd;
+pub mod lint;
+pub mod score;
+pub mod sym;
+
+use crate::{e2e, j1939};
+use bitvec::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Collection of message definitions for a bus
+#[derive(Default, Clone, Debug)]
+pub struct Database {
+    /// Network nodes (ECUs)
+    pub nodes: Vec<String>,
+    /// Match extended IDs by J1939 parameter group, ignoring the source
+    /// address
+    pub j1939: bool,
+    /// End-to-end protections by message ID
+    pub e2e: e2e::Protections,
+    messages: Vec<Message>,
+    ids: BTreeMap<u32, usize>,
+    pgns: BTreeMap<u32, usize>,
+}
+
+/// Message (frame) definition
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Message {
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    /// Payload size in bytes
+    pub size: usize,
+    /// Name of the transmitting node, if any
+    pub transmitter: Option<String>,
+    /// Nominal transmission period in milliseconds
+    pub cycle_time: Option<u32>,
+    pub signals: Vec<Signal>,
+    pub comment: String,
+}
+
+/// Signal definition
+///
+/// Bit positions follow the DBC convention: little-endian signals start at
+/// their least significant bit, big-endian signals at their most
+/// significant bit, with bit 0 being the LSB of the first byte.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signal {
+    pub name: String,
+    pub start_bit: usize,
+    pub size: usize,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub multiplex: Multiplex,
+    pub receivers: Vec<String>,
+    pub comment: String,
+    /// Descriptions of raw values, ordered by value
+    pub values: Vec<(i64, String)>,
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    #[default]
+    Unsigned,
+    Signed,
+    /// IEEE 754 single (32-bit) or double (64-bit) precision
+    Float,
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Multiplex {
+    #[default]
+    Plain,
+    /// Signal selecting which multiplexed signals are present
+    Multiplexor,
+    /// Signal present when the multiplexor has the given value
+    Multiplexed(u64),
+}
+
+impl Database {
+    pub fn new(nodes: Vec<String>, messages: Vec<Message>) -> Self {
+        // get a map of message IDs to their corresponding index
+        let mut ids: BTreeMap<u32, usize> = Default::default();
+        let mut pgns: BTreeMap<u32, usize> = Default::default();
+        for (index, message) in messages.iter().enumerate() {
+            ids.insert(message.id, index);
+            if message.extended {
+                pgns.entry(j1939::Id::from_id(message.id).pgn)
+                    .or_insert(index);
+            }
+        }
+        Self {
+            nodes,
+            messages,
+            ids,
+            pgns,
+            ..Default::default()
+        }
+    }
+
+    /// Load a database file, choosing the format by file extension
+    /// (`.arxml`, `.kcd`, `.sym`, `.eds`/`.dcf`, otherwise DBC)
+    ///
+    /// Formats describing several buses take a `<file>#<bus>` suffix to
+    /// select the ARXML cluster or KCD bus to load.  For CANopen EDS/DCF
+    /// files the suffix gives the node ID.
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        let (filename, bus) = split_bus(filename);
+        let buffer = fs::read(filename)?;
+        let text = String::from_utf8_lossy(&buffer);
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match (extension.as_str(), bus) {
+            ("arxml", _) => arxml::parse(&text, bus),
+            ("kcd", _) => kcd::parse(&text, bus),
+            ("eds" | "dcf", _) => eds::parse(&text, bus),
+            (_, Some(bus)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{filename} has no bus {bus} to select"),
+            )),
+            ("sym", _) => sym::parse(&text),
+            _ => dbc::parse(&buffer),
+        }
+    }
+
+    pub fn messages(&self) -> &Vec<Message> {
+        &self.messages
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.index(id).is_some()
+    }
+
+    pub fn message(&self, id: u32) -> Option<&Message> {
+        self.index(id).and_then(|i| self.messages.get(i))
+    }
+
+    /// Add a message definition, replacing the one matching its ID
+    pub fn insert(&mut self, message: Message) {
+        match self.index(message.id) {
+            Some(index) => self.messages[index] = message,
+            None => {
+                let index = self.messages.len();
+                self.ids.insert(message.id, index);
+                if message.extended {
+                    self.pgns
+                        .entry(j1939::Id::from_id(message.id).pgn)
+                        .or_insert(index);
+                }
+                self.messages.push(message);
+            }
+        }
+    }
+
+    fn index(&self, id: u32) -> Option<usize> {
+        match self.ids.get(&id) {
+            Some(index) => Some(*index),
+            None if self.j1939 && id > 0x7ff => {
+                self.pgns.get(&j1939::Id::from_id(id).pgn).copied()
+            }
+            None => None,
+        }
+    }
+}
+
+impl Message {
+    /// Signal selecting the multiplexed signals of this message, if any
+    pub fn multiplexor(&self) -> Option<&Signal> {
+        self.signals
+            .iter()
+            .find(|s| s.multiplex == Multiplex::Multiplexor)
+    }
+
+    /// Check whether a signal is present in the given packet data, taking
+    /// the multiplexor value into account
+    pub fn is_present(&self, signal: &Signal, bytes: &[u8]) -> bool {
+        match signal.multiplex {
+            Multiplex::Multiplexed(value) => self
+                .multiplexor()
+                .and_then(|m| m.raw(bytes))
+                .is_some_and(|v| v == value),
+            _ => true,
+        }
+    }
+}
+
+impl Signal {
+    pub fn new(name: &str, start_bit: usize, size: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            start_bit,
+            size,
+            byte_order: ByteOrder::LittleEndian,
+            value_type: ValueType::Unsigned,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+            unit: String::new(),
+            multiplex: Multiplex::Plain,
+            receivers: vec![],
+            comment: String::new(),
+            values: vec![],
+        }
+    }
+
+    /// Parse a signal in DBC notation without the `SG_` prefix,
+    /// receivers or limits: `<name> : <start>|<size>@<order><sign>
+    /// (<factor>,<offset>) ["<unit>"]`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid signal {text}");
+        let (name, rest) = text.split_once(':').ok_or_else(invalid)?;
+        let (layout, rest) = rest.trim().split_once(' ').ok_or_else(invalid)?;
+        let (start, rest_layout) =
+            layout.split_once('|').ok_or_else(invalid)?;
+        let (size, order) = rest_layout.split_once('@').ok_or_else(invalid)?;
+        let mut signal = Self::new(
+            name.trim(),
+            start.parse().map_err(|_| invalid())?,
+            size.parse().map_err(|_| invalid())?,
+        );
+        signal.byte_order = match order.get(..1) {
+            Some("0") => ByteOrder::BigEndian,
+            Some("1") => ByteOrder::LittleEndian,
+            _ => return Err(invalid()),
+        };
+        if order.ends_with('-') {
+            signal.value_type = ValueType::Signed;
+        }
+
+        let scaling = rest
+            .trim()
+            .strip_prefix('(')
+            .and_then(|s| s.split_once(')'))
+            .ok_or_else(invalid)?;
+        let (factor, offset) = scaling.0.split_once(',').ok_or_else(invalid)?;
+        signal.factor = factor.trim().parse().map_err(|_| invalid())?;
+        signal.offset = offset.trim().parse().map_err(|_| invalid())?;
+        signal.unit = scaling.1.trim().trim_matches('"').to_string();
+        Ok(signal)
+    }
+
+    /// Number of payload bytes needed to hold the signal
+    pub fn min_length(&self) -> usize {
+        let start = match self.byte_order {
+            ByteOrder::LittleEndian => self.start_bit,
+            ByteOrder::BigEndian => flip_bit(self.start_bit),
+        };
+        (start + self.size).div_ceil(8)
+    }
+
+    /// Extract the unscaled bits of the signal, or `None` if the signal
+    /// does not fit in the data
+    pub fn raw(&self, bytes: &[u8]) -> Option<u64> {
+        let size = self.size;
+        if size == 0 || size > 64 {
+            return None;
+        }
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                let start = self.start_bit;
+                bytes
+                    .view_bits::<Lsb0>()
+                    .get(start..start + size)
+                    .map(|b| b.load_le::<u64>())
+            }
+            ByteOrder::BigEndian => {
+                let start = flip_bit(self.start_bit);
+                bytes
+                    .view_bits::<Msb0>()
+                    .get(start..start + size)
+                    .map(|b| b.load_be::<u64>())
+            }
+        }
+    }
+
+    /// Decode the physical (scaled) value of the signal
+    pub fn value(&self, bytes: &[u8]) -> Option<f64> {
+        let raw = self.raw(bytes)?;
+        let value = match self.value_type {
+            ValueType::Unsigned => raw as f64,
+            ValueType::Signed => {
+                let shift = 64 - self.size;
+                ((raw << shift) as i64 >> shift) as f64
+            }
+            ValueType::Float => match self.size {
+                32 => f32::from_bits(raw as u32) as f64,
+                64 => f64::from_bits(raw),
+                _ => return None,
+            },
+        };
+        Some(value * self.factor + self.offset)
+    }
+}
+
+/// File name and `#<bus>` suffix of a database file, unless the name
+/// exists as given or the text after the `#` is a path
+pub fn split_bus(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('#') {
+        Some((filename, bus))
+            if !bus.contains(['/', '\\']) && !Path::new(name).exists() =>
+        {
+            (filename, Some(bus))
+        }
+        _ => (name, None),
+    }
+}
+
+/// Convert a bit position counted in transmit order (MSB of the first byte
+/// is bit 0) to DBC numbering and vice versa
+pub fn flip_bit(bit: usize) -> usize {
+    (bit / 8) * 8 + 7 - bit % 8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_little_endian() {
+        let mut signal = Signal::new("s", 4, 12);
+        assert_eq!(signal.raw(&[0x3c, 0xab]), Some(0xab3));
+        signal.value_type = ValueType::Signed;
+        signal.factor = 0.5;
+        assert_eq!(signal.value(&[0x3c, 0xab]), Some(-678.5));
+        assert_eq!(signal.raw(&[0x3c]), None);
+    }
+
+    #[test]
+    fn decode_big_endian() {
+        let mut signal = Signal::new("s", 7, 16);
+        signal.byte_order = ByteOrder::BigEndian;
+        assert_eq!(signal.raw(&[0x12, 0x34]), Some(0x1234));
+        let mut signal = Signal::new("s", 3, 12);
+        signal.byte_order = ByteOrder::BigEndian;
+        assert_eq!(signal.raw(&[0x12, 0x34, 0x56]), Some(0x234));
+    }
+
+    #[test]
+    fn multiplexed() {
+        let mut mux = Signal::new("mux", 0, 8);
+        mux.multiplex = Multiplex::Multiplexor;
+        let mut a = Signal::new("a", 8, 8);
+        a.multiplex = Multiplex::Multiplexed(1);
+        let message = Message {
+            signals: vec![mux, a.clone()],
+            ..Default::default()
+        };
+        assert!(message.is_present(&a, &[1, 0]));
+        assert!(!message.is_present(&a, &[2, 0]));
+    }
+
+    #[test]
+    fn bus_suffix() {
+        assert_eq!(split_bus("a.arxml#CAN1"), ("a.arxml", Some("CAN1")));
+        assert_eq!(
+            split_bus("/data/run#3/car.dbc"),
+            ("/data/run#3/car.dbc", None)
+        );
+        assert_eq!(split_bus("car.dbc"), ("car.dbc", None));
+    }
+}
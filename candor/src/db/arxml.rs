@@ -0,0 +1,552 @@
+//! AUTOSAR ARXML communication matrix loader
+//!
+//! Frames are taken from the frame triggerings of each CAN cluster, with
+//! the signals of the I-PDUs mapped into them.  Multiplexed I-PDUs become
+//! a multiplexor signal (the selector field) plus multiplexed signals.
+
+use super::{
+    flip_bit, ByteOrder, Database, Message, Multiplex, Signal, ValueType,
+};
+use crate::invalid;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::io;
+
+/// Parse an ARXML file, optionally restricted to the named CAN cluster
+pub fn parse(text: &str, cluster: Option<&str>) -> io::Result<Database> {
+    let doc = Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let arxml = Arxml::new(&doc);
+
+    let clusters: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("CAN-CLUSTER"))
+        .filter(|n| cluster.is_none() || short_name(*n) == cluster)
+        .collect();
+    if let (Some(name), true) = (cluster, clusters.is_empty()) {
+        return Err(invalid(format!("No CAN cluster named {name}")));
+    }
+
+    let mut nodes: Vec<String> = vec![];
+    let mut messages = vec![];
+    for triggering in clusters
+        .iter()
+        .flat_map(|c| c.descendants())
+        .filter(|n| n.has_tag_name("CAN-FRAME-TRIGGERING"))
+    {
+        let message = arxml.frame(triggering)?;
+        for node in message.transmitter.iter() {
+            if !nodes.contains(node) {
+                nodes.push(node.clone());
+            }
+        }
+        messages.push(message);
+    }
+
+    Ok(Database::new(nodes, messages))
+}
+
+/// Get the SHORT-NAME of an element
+fn short_name<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    child(node, "SHORT-NAME").and_then(|n| n.text())
+}
+
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    tag: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+/// Text of the first descendant with the given tag
+fn text<'a>(node: Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    node.descendants()
+        .find(|n| n.has_tag_name(tag))
+        .and_then(|n| n.text())
+        .map(|s| s.trim())
+}
+
+/// Parse integers in any of the AUTOSAR notations (decimal, 0x, 0b, 0)
+fn parse_int(text: &str) -> io::Result<u64> {
+    let result = if let Some(hex) = text.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix("0X") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else {
+        text.parse::<u64>()
+    };
+    result.map_err(|_| invalid(format!("Invalid number {text}")))
+}
+
+fn int(node: Node, tag: &str) -> io::Result<Option<u64>> {
+    text(node, tag).map(parse_int).transpose()
+}
+
+fn float(node: Node, tag: &str) -> Option<f64> {
+    text(node, tag).and_then(|s| s.parse::<f64>().ok())
+}
+
+fn byte_order(node: Node) -> ByteOrder {
+    match text(node, "PACKING-BYTE-ORDER") {
+        Some("MOST-SIGNIFICANT-BYTE-FIRST") => ByteOrder::BigEndian,
+        _ => ByteOrder::LittleEndian,
+    }
+}
+
+/// Convert an AUTOSAR start position (always the LSB) to a DBC start bit
+fn start_bit(position: usize, size: usize, byte_order: ByteOrder) -> usize {
+    match byte_order {
+        ByteOrder::LittleEndian => position,
+        ByteOrder::BigEndian => {
+            flip_bit((flip_bit(position) + 1).saturating_sub(size))
+        }
+    }
+}
+
+struct Arxml<'a, 'input> {
+    /// Elements by their absolute short-name path
+    paths: HashMap<String, Node<'a, 'input>>,
+    /// Owning ECU instance of each frame port
+    ports: HashMap<String, String>,
+}
+
+impl<'a, 'input> Arxml<'a, 'input> {
+    fn new(doc: &'a Document<'input>) -> Self {
+        let mut paths = HashMap::new();
+        index(doc.root_element(), "", &mut paths);
+
+        let mut ports = HashMap::new();
+        for (path, node) in paths.iter() {
+            if node.has_tag_name("ECU-INSTANCE") {
+                let ecu = short_name(*node).unwrap_or_default().to_string();
+                // ports nested in the ECU, not in one named alike
+                let prefix = format!("{path}/");
+                for (port, _) in paths.iter().filter(|(p, n)| {
+                    n.has_tag_name("FRAME-PORT") && p.starts_with(&prefix)
+                }) {
+                    ports.insert(port.clone(), ecu.clone());
+                }
+            }
+        }
+
+        fn index<'a, 'input>(
+            node: Node<'a, 'input>,
+            prefix: &str,
+            paths: &mut HashMap<String, Node<'a, 'input>>,
+        ) {
+            for child in node.children().filter(|n| n.is_element()) {
+                match short_name(child) {
+                    Some(name) => {
+                        let path = format!("{prefix}/{name}");
+                        index(child, &path, paths);
+                        paths.insert(path, child);
+                    }
+                    None => index(child, prefix, paths),
+                }
+            }
+        }
+
+        Self { paths, ports }
+    }
+
+    /// Follow the first reference with the given tag
+    fn follow(&self, node: Node, tag: &str) -> Option<Node<'a, 'input>> {
+        text(node, tag)
+            .and_then(|path| self.paths.get(path))
+            .copied()
+    }
+
+    fn frame(&self, triggering: Node) -> io::Result<Message> {
+        let id = int(triggering, "IDENTIFIER")?.ok_or_else(|| {
+            invalid("Frame triggering without identifier".into())
+        })? as u32;
+        let extended =
+            text(triggering, "CAN-ADDRESSING-MODE") == Some("EXTENDED");
+
+        let mut message = Message {
+            id,
+            extended,
+            ..Default::default()
+        };
+
+        // sending/receiving ECUs are found through their frame ports
+        let mut receivers = vec![];
+        for port in triggering
+            .descendants()
+            .filter(|n| n.has_tag_name("FRAME-PORT-REF"))
+            .filter_map(|n| n.text())
+        {
+            let (Some(ecu), Some(node)) =
+                (self.ports.get(port), self.paths.get(port))
+            else {
+                continue;
+            };
+            if text(*node, "COMMUNICATION-DIRECTION") == Some("OUT") {
+                message.transmitter = Some(ecu.clone());
+            } else {
+                receivers.push(ecu.clone());
+            }
+        }
+
+        let Some(frame) = self.follow(triggering, "FRAME-REF") else {
+            message.name = short_name(triggering).unwrap_or_default().into();
+            return Ok(message);
+        };
+        message.name = short_name(frame).unwrap_or_default().to_string();
+        message.size = int(frame, "FRAME-LENGTH")?.unwrap_or(0) as usize;
+
+        for mapping in frame
+            .descendants()
+            .filter(|n| n.has_tag_name("PDU-TO-FRAME-MAPPING"))
+        {
+            let offset = int(mapping, "START-POSITION")?.unwrap_or(0);
+            if let Some(pdu) = self.follow(mapping, "PDU-REF") {
+                self.pdu(pdu, offset as usize, Multiplex::Plain, &mut message)?;
+            }
+        }
+
+        for signal in message.signals.iter_mut() {
+            signal.receivers = receivers.clone();
+        }
+
+        Ok(message)
+    }
+
+    /// Add the signals of a PDU placed at the given bit offset
+    fn pdu(
+        &self,
+        pdu: Node,
+        offset: usize,
+        multiplex: Multiplex,
+        message: &mut Message,
+    ) -> io::Result<()> {
+        if message.cycle_time.is_none() {
+            let period = pdu
+                .descendants()
+                .find(|n| n.has_tag_name("CYCLIC-TIMING"))
+                .and_then(|n| float(n, "VALUE"));
+            message.cycle_time = period
+                .map(|s| (s * 1000.0).round() as u32)
+                .filter(|t| *t > 0);
+        }
+
+        if pdu.has_tag_name("MULTIPLEXED-I-PDU") {
+            let size = int(pdu, "SELECTOR-FIELD-LENGTH")?.unwrap_or(0) as usize;
+            let position =
+                int(pdu, "SELECTOR-FIELD-START-POSITION")?.unwrap_or(0);
+            let order = match text(pdu, "SELECTOR-FIELD-BYTE-ORDER") {
+                Some("MOST-SIGNIFICANT-BYTE-FIRST") => ByteOrder::BigEndian,
+                _ => ByteOrder::LittleEndian,
+            };
+            let mut selector = Signal::new(
+                short_name(pdu).unwrap_or_default(),
+                start_bit(position as usize, size, order) + offset,
+                size,
+            );
+            selector.byte_order = order;
+            selector.multiplex = Multiplex::Multiplexor;
+            message.signals.push(selector);
+
+            for part in
+                pdu.descendants().filter(|n| n.has_tag_name("STATIC-PART"))
+            {
+                if let Some(pdu) = self.follow(part, "I-PDU-REF") {
+                    self.pdu(pdu, offset, Multiplex::Plain, message)?;
+                }
+            }
+            for part in pdu
+                .descendants()
+                .filter(|n| n.has_tag_name("DYNAMIC-PART-ALTERNATIVE"))
+            {
+                let code = int(part, "SELECTOR-FIELD-CODE")?.unwrap_or(0);
+                if let Some(pdu) = self.follow(part, "I-PDU-REF") {
+                    let multiplex = Multiplex::Multiplexed(code);
+                    self.pdu(pdu, offset, multiplex, message)?;
+                }
+            }
+            return Ok(());
+        }
+
+        for mapping in pdu
+            .descendants()
+            .filter(|n| n.has_tag_name("I-SIGNAL-TO-I-PDU-MAPPING"))
+        {
+            let Some(isignal) = self.follow(mapping, "I-SIGNAL-REF") else {
+                continue;
+            };
+            let size = int(isignal, "LENGTH")?.unwrap_or(0) as usize;
+            let position = int(mapping, "START-POSITION")?.unwrap_or(0);
+            let order = byte_order(mapping);
+
+            let mut signal = Signal::new(
+                short_name(isignal).unwrap_or_default(),
+                start_bit(position as usize, size, order) + offset,
+                size,
+            );
+            signal.byte_order = order;
+            signal.multiplex = multiplex;
+            self.scaling(isignal, &mut signal);
+            message.signals.push(signal);
+        }
+
+        Ok(())
+    }
+
+    /// Apply base type and compu method of an I-SIGNAL (or the system
+    /// signal it refers to)
+    fn scaling(&self, isignal: Node, signal: &mut Signal) {
+        let system = self.follow(isignal, "SYSTEM-SIGNAL-REF");
+
+        if let Some(base) = self.follow(isignal, "BASE-TYPE-REF") {
+            signal.value_type = match text(base, "BASE-TYPE-ENCODING") {
+                Some("2C") => ValueType::Signed,
+                Some("IEEE754") => ValueType::Float,
+                _ => ValueType::Unsigned,
+            };
+        }
+
+        let compu = self.follow(isignal, "COMPU-METHOD-REF").or_else(|| {
+            system.and_then(|s| self.follow(s, "COMPU-METHOD-REF"))
+        });
+        let Some(compu) = compu else {
+            return;
+        };
+
+        let unit = self
+            .follow(compu, "UNIT-REF")
+            .or_else(|| system.and_then(|s| self.follow(s, "UNIT-REF")));
+        if let Some(unit) = unit {
+            signal.unit = text(unit, "DISPLAY-NAME")
+                .or(short_name(unit))
+                .unwrap_or_default()
+                .to_string();
+        }
+
+        // linear scales; text tables have no scaling to apply
+        let Some(scale) = compu
+            .descendants()
+            .filter(|n| n.has_tag_name("COMPU-SCALE"))
+            .find(|n| child(*n, "COMPU-RATIONAL-COEFFS").is_some())
+        else {
+            return;
+        };
+        let coefficients = |tag: &str| -> Vec<f64> {
+            scale
+                .descendants()
+                .find(|n| n.has_tag_name(tag))
+                .map(|n| {
+                    n.children()
+                        .filter(|v| v.has_tag_name("V"))
+                        .filter_map(|v| v.text()?.trim().parse::<f64>().ok())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let numerator = coefficients("COMPU-NUMERATOR");
+        let denominator = coefficients("COMPU-DENOMINATOR")
+            .first()
+            .copied()
+            .unwrap_or(1.0);
+        if denominator != 0.0 {
+            signal.offset =
+                numerator.first().copied().unwrap_or(0.0) / denominator;
+            signal.factor =
+                numerator.get(1).copied().unwrap_or(1.0) / denominator;
+        }
+        let physical = |raw: f64| raw * signal.factor + signal.offset;
+        if let Some(min) = float(scale, "LOWER-LIMIT") {
+            signal.min = physical(min);
+        }
+        if let Some(max) = float(scale, "UPPER-LIMIT") {
+            signal.max = physical(max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ARXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<AUTOSAR xmlns="http://autosar.org/schema/r4.0">
+<AR-PACKAGES>
+ <AR-PACKAGE><SHORT-NAME>Cluster</SHORT-NAME><ELEMENTS>
+  <CAN-CLUSTER><SHORT-NAME>Powertrain</SHORT-NAME>
+   <CAN-CLUSTER-VARIANTS><CAN-CLUSTER-CONDITIONAL><PHYSICAL-CHANNELS>
+    <CAN-PHYSICAL-CHANNEL><SHORT-NAME>PT</SHORT-NAME><FRAME-TRIGGERINGS>
+     <CAN-FRAME-TRIGGERING><SHORT-NAME>EngineTrig</SHORT-NAME>
+      <FRAME-PORT-REFS>
+       <FRAME-PORT-REF DEST="FRAME-PORT">/ECU/Engine/Conn/EngineOut</FRAME-PORT-REF>
+      </FRAME-PORT-REFS>
+      <FRAME-REF DEST="CAN-FRAME">/Frame/EngineData</FRAME-REF>
+      <CAN-ADDRESSING-MODE>STANDARD</CAN-ADDRESSING-MODE>
+      <IDENTIFIER>160</IDENTIFIER>
+     </CAN-FRAME-TRIGGERING>
+     <CAN-FRAME-TRIGGERING><SHORT-NAME>DiagTrig</SHORT-NAME>
+      <FRAME-PORT-REFS>
+       <FRAME-PORT-REF DEST="FRAME-PORT">/ECU/Engine2/Conn/DiagOut</FRAME-PORT-REF>
+      </FRAME-PORT-REFS>
+      <FRAME-REF DEST="CAN-FRAME">/Frame/Diag</FRAME-REF>
+      <CAN-ADDRESSING-MODE>EXTENDED</CAN-ADDRESSING-MODE>
+      <IDENTIFIER>0x18FEF100</IDENTIFIER>
+     </CAN-FRAME-TRIGGERING>
+    </FRAME-TRIGGERINGS></CAN-PHYSICAL-CHANNEL>
+   </PHYSICAL-CHANNELS></CAN-CLUSTER-CONDITIONAL></CAN-CLUSTER-VARIANTS>
+  </CAN-CLUSTER>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>ECU</SHORT-NAME><ELEMENTS>
+  <ECU-INSTANCE><SHORT-NAME>Engine</SHORT-NAME><CONNECTORS>
+   <CAN-COMMUNICATION-CONNECTOR><SHORT-NAME>Conn</SHORT-NAME>
+    <ECU-COMM-PORT-INSTANCES>
+     <FRAME-PORT><SHORT-NAME>EngineOut</SHORT-NAME>
+      <COMMUNICATION-DIRECTION>OUT</COMMUNICATION-DIRECTION>
+     </FRAME-PORT>
+    </ECU-COMM-PORT-INSTANCES>
+   </CAN-COMMUNICATION-CONNECTOR>
+  </CONNECTORS></ECU-INSTANCE>
+  <ECU-INSTANCE><SHORT-NAME>Engine2</SHORT-NAME><CONNECTORS>
+   <CAN-COMMUNICATION-CONNECTOR><SHORT-NAME>Conn</SHORT-NAME>
+    <ECU-COMM-PORT-INSTANCES>
+     <FRAME-PORT><SHORT-NAME>DiagOut</SHORT-NAME>
+      <COMMUNICATION-DIRECTION>OUT</COMMUNICATION-DIRECTION>
+     </FRAME-PORT>
+    </ECU-COMM-PORT-INSTANCES>
+   </CAN-COMMUNICATION-CONNECTOR>
+  </CONNECTORS></ECU-INSTANCE>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Frame</SHORT-NAME><ELEMENTS>
+  <CAN-FRAME><SHORT-NAME>EngineData</SHORT-NAME>
+   <FRAME-LENGTH>8</FRAME-LENGTH>
+   <PDU-TO-FRAME-MAPPINGS><PDU-TO-FRAME-MAPPING>
+    <SHORT-NAME>Map</SHORT-NAME>
+    <PDU-REF DEST="I-SIGNAL-I-PDU">/Pdu/EnginePdu</PDU-REF>
+    <START-POSITION>0</START-POSITION>
+   </PDU-TO-FRAME-MAPPING></PDU-TO-FRAME-MAPPINGS>
+  </CAN-FRAME>
+  <CAN-FRAME><SHORT-NAME>Diag</SHORT-NAME>
+   <FRAME-LENGTH>3</FRAME-LENGTH>
+   <PDU-TO-FRAME-MAPPINGS><PDU-TO-FRAME-MAPPING>
+    <SHORT-NAME>Map</SHORT-NAME>
+    <PDU-REF DEST="MULTIPLEXED-I-PDU">/Pdu/DiagMux</PDU-REF>
+   </PDU-TO-FRAME-MAPPING></PDU-TO-FRAME-MAPPINGS>
+  </CAN-FRAME>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Pdu</SHORT-NAME><ELEMENTS>
+  <I-SIGNAL-I-PDU><SHORT-NAME>EnginePdu</SHORT-NAME>
+   <LENGTH>8</LENGTH>
+   <I-SIGNAL-TO-PDU-MAPPINGS>
+    <I-SIGNAL-TO-I-PDU-MAPPING><SHORT-NAME>RpmMap</SHORT-NAME>
+     <I-SIGNAL-REF DEST="I-SIGNAL">/Signal/Rpm</I-SIGNAL-REF>
+     <PACKING-BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</PACKING-BYTE-ORDER>
+     <START-POSITION>0</START-POSITION>
+    </I-SIGNAL-TO-I-PDU-MAPPING>
+    <I-SIGNAL-TO-I-PDU-MAPPING><SHORT-NAME>TempMap</SHORT-NAME>
+     <I-SIGNAL-REF DEST="I-SIGNAL">/Signal/Temp</I-SIGNAL-REF>
+     <PACKING-BYTE-ORDER>MOST-SIGNIFICANT-BYTE-FIRST</PACKING-BYTE-ORDER>
+     <START-POSITION>24</START-POSITION>
+    </I-SIGNAL-TO-I-PDU-MAPPING>
+   </I-SIGNAL-TO-PDU-MAPPINGS>
+   <I-PDU-TIMING-SPECIFICATIONS><I-PDU-TIMING>
+    <TRANSMISSION-MODE-DECLARATION><TRANSMISSION-MODE-TRUE-TIMING>
+     <CYCLIC-TIMING><TIME-PERIOD><VALUE>0.1</VALUE></TIME-PERIOD></CYCLIC-TIMING>
+    </TRANSMISSION-MODE-TRUE-TIMING></TRANSMISSION-MODE-DECLARATION>
+   </I-PDU-TIMING></I-PDU-TIMING-SPECIFICATIONS>
+  </I-SIGNAL-I-PDU>
+  <MULTIPLEXED-I-PDU><SHORT-NAME>DiagMux</SHORT-NAME>
+   <DYNAMIC-PARTS><DYNAMIC-PART><DYNAMIC-PART-ALTERNATIVES>
+    <DYNAMIC-PART-ALTERNATIVE>
+     <I-PDU-REF DEST="I-SIGNAL-I-PDU">/Pdu/DiagVoltage</I-PDU-REF>
+     <SELECTOR-FIELD-CODE>1</SELECTOR-FIELD-CODE>
+    </DYNAMIC-PART-ALTERNATIVE>
+   </DYNAMIC-PART-ALTERNATIVES></DYNAMIC-PART></DYNAMIC-PARTS>
+   <SELECTOR-FIELD-BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</SELECTOR-FIELD-BYTE-ORDER>
+   <SELECTOR-FIELD-LENGTH>8</SELECTOR-FIELD-LENGTH>
+   <SELECTOR-FIELD-START-POSITION>0</SELECTOR-FIELD-START-POSITION>
+  </MULTIPLEXED-I-PDU>
+  <I-SIGNAL-I-PDU><SHORT-NAME>DiagVoltage</SHORT-NAME>
+   <I-SIGNAL-TO-PDU-MAPPINGS>
+    <I-SIGNAL-TO-I-PDU-MAPPING><SHORT-NAME>VoltMap</SHORT-NAME>
+     <I-SIGNAL-REF DEST="I-SIGNAL">/Signal/Voltage</I-SIGNAL-REF>
+     <START-POSITION>8</START-POSITION>
+    </I-SIGNAL-TO-I-PDU-MAPPING>
+   </I-SIGNAL-TO-PDU-MAPPINGS>
+  </I-SIGNAL-I-PDU>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Signal</SHORT-NAME><ELEMENTS>
+  <I-SIGNAL><SHORT-NAME>Rpm</SHORT-NAME><LENGTH>16</LENGTH>
+   <NETWORK-REPRESENTATION-PROPS><SW-DATA-DEF-PROPS-VARIANTS>
+    <SW-DATA-DEF-PROPS-CONDITIONAL>
+     <COMPU-METHOD-REF DEST="COMPU-METHOD">/Compu/Rpm</COMPU-METHOD-REF>
+    </SW-DATA-DEF-PROPS-CONDITIONAL>
+   </SW-DATA-DEF-PROPS-VARIANTS></NETWORK-REPRESENTATION-PROPS>
+  </I-SIGNAL>
+  <I-SIGNAL><SHORT-NAME>Temp</SHORT-NAME><LENGTH>16</LENGTH>
+   <NETWORK-REPRESENTATION-PROPS><SW-DATA-DEF-PROPS-VARIANTS>
+    <SW-DATA-DEF-PROPS-CONDITIONAL>
+     <BASE-TYPE-REF DEST="SW-BASE-TYPE">/Types/sint16</BASE-TYPE-REF>
+    </SW-DATA-DEF-PROPS-CONDITIONAL>
+   </SW-DATA-DEF-PROPS-VARIANTS></NETWORK-REPRESENTATION-PROPS>
+  </I-SIGNAL>
+  <I-SIGNAL><SHORT-NAME>Voltage</SHORT-NAME><LENGTH>8</LENGTH></I-SIGNAL>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Types</SHORT-NAME><ELEMENTS>
+  <SW-BASE-TYPE><SHORT-NAME>sint16</SHORT-NAME>
+   <BASE-TYPE-ENCODING>2C</BASE-TYPE-ENCODING>
+  </SW-BASE-TYPE>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Compu</SHORT-NAME><ELEMENTS>
+  <COMPU-METHOD><SHORT-NAME>Rpm</SHORT-NAME>
+   <CATEGORY>LINEAR</CATEGORY>
+   <UNIT-REF DEST="UNIT">/Units/rpm</UNIT-REF>
+   <COMPU-INTERNAL-TO-PHYS><COMPU-SCALES><COMPU-SCALE>
+    <LOWER-LIMIT>0</LOWER-LIMIT><UPPER-LIMIT>65535</UPPER-LIMIT>
+    <COMPU-RATIONAL-COEFFS>
+     <COMPU-NUMERATOR><V>0</V><V>1</V></COMPU-NUMERATOR>
+     <COMPU-DENOMINATOR><V>4</V></COMPU-DENOMINATOR>
+    </COMPU-RATIONAL-COEFFS>
+   </COMPU-SCALE></COMPU-SCALES></COMPU-INTERNAL-TO-PHYS>
+  </COMPU-METHOD>
+ </ELEMENTS></AR-PACKAGE>
+ <AR-PACKAGE><SHORT-NAME>Units</SHORT-NAME><ELEMENTS>
+  <UNIT><SHORT-NAME>rpm</SHORT-NAME><DISPLAY-NAME>1/min</DISPLAY-NAME></UNIT>
+ </ELEMENTS></AR-PACKAGE>
+</AR-PACKAGES>
+</AUTOSAR>
+"#;
+
+    #[test]
+    fn communication_matrix() {
+        let db = parse(ARXML, None).unwrap();
+        assert_eq!(db.nodes, vec!["Engine", "Engine2"]);
+        assert_eq!(db.messages().len(), 2);
+
+        let engine = db.message(0xa0).unwrap();
+        assert_eq!(engine.name, "EngineData");
+        assert_eq!(engine.size, 8);
+        assert_eq!(engine.cycle_time, Some(100));
+        assert_eq!(engine.transmitter.as_deref(), Some("Engine"));
+        let rpm = &engine.signals[0];
+        assert_eq!(rpm.unit, "1/min");
+        assert_eq!(rpm.factor, 0.25);
+        assert_eq!(rpm.max, 16383.75);
+        assert_eq!(rpm.value(&[0x10, 0x00]), Some(4.0));
+        let temp = &engine.signals[1];
+        assert_eq!(temp.byte_order, ByteOrder::BigEndian);
+        assert_eq!(temp.value_type, ValueType::Signed);
+        assert_eq!(temp.value(&[0, 0, 0xff, 0xfe]), Some(-2.0));
+
+        let diag = db.message(0x18fef100).unwrap();
+        assert!(diag.extended);
+        assert_eq!(diag.transmitter.as_deref(), Some("Engine2"));
+        assert_eq!(diag.signals[0].multiplex, Multiplex::Multiplexor);
+        assert_eq!(diag.signals[1].multiplex, Multiplex::Multiplexed(1));
+        assert!(diag.is_present(&diag.signals[1], &[1, 12, 0]));
+    }
+
+    #[test]
+    fn cluster_selection() {
+        assert!(parse(ARXML, Some("Powertrain")).is_ok());
+        assert!(parse(ARXML, Some("Body")).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+//! DBC (Vector CANdb++) database loader
+
+use super::{
+    flip_bit, ByteOrder, Database, Message, Multiplex, Signal, ValueType,
+};
+use crate::e2e::{DataIdMode, Profile, Protection};
+use can_dbc::{
+    AttributeValue, AttributeValuedForObjectType, MessageId,
+    MultiplexIndicator, SignalExtendedValueType, Transmitter, DBC,
+};
+use std::io;
+
+pub fn parse(buffer: &[u8]) -> io::Result<Database> {
+    let text = String::from_utf8_lossy(buffer);
+    let dbc = DBC::try_from(text.as_ref()).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+    })?;
+    Ok(convert(&dbc))
+}
+
+/// Convert a parsed DBC into the candor database model
+pub fn convert(dbc: &DBC) -> Database {
+    let nodes = dbc.nodes().iter().flat_map(|n| n.0.clone()).collect();
+
+    let messages: Vec<Message> = dbc
+        .messages()
+        .iter()
+        .map(|message| {
+            let message_id = *message.message_id();
+            let (id, extended) = match message_id {
+                MessageId::Standard(id) => (id as u32, false),
+                MessageId::Extended(id) => (id, true),
+            };
+            let transmitter = match message.transmitter() {
+                Transmitter::NodeName(name) if name != "Vector__XXX" => {
+                    Some(name.clone())
+                }
+                _ => None,
+            };
+            let signals = message
+                .signals()
+                .iter()
+                .map(|s| {
+                    let float = dbc
+                        .extended_value_type_for_signal(message_id, s.name());
+                    let value_type = match (float, s.value_type()) {
+                        (
+                            Some(SignalExtendedValueType::IEEEfloat32Bit)
+                            | Some(SignalExtendedValueType::IEEEdouble64bit),
+                            _,
+                        ) => ValueType::Float,
+                        (_, can_dbc::ValueType::Signed) => ValueType::Signed,
+                        (_, can_dbc::ValueType::Unsigned) => {
+                            ValueType::Unsigned
+                        }
+                    };
+                    Signal {
+                        name: s.name().clone(),
+                        start_bit: s.start_bit as usize,
+                        size: s.signal_size as usize,
+                        byte_order: match s.byte_order() {
+                            can_dbc::ByteOrder::LittleEndian => {
+                                ByteOrder::LittleEndian
+                            }
+                            can_dbc::ByteOrder::BigEndian => {
+                                ByteOrder::BigEndian
+                            }
+                        },
+                        value_type,
+                        factor: s.factor,
+                        offset: s.offset,
+                        min: s.min,
+                        max: s.max,
+                        unit: s.unit().clone(),
+                        multiplex: match *s.multiplexer_indicator() {
+                            MultiplexIndicator::Plain => Multiplex::Plain,
+                            MultiplexIndicator::Multiplexor => {
+                                Multiplex::Multiplexor
+                            }
+                            MultiplexIndicator::MultiplexedSignal(v)
+                            | MultiplexIndicator::MultiplexorAndMultiplexedSignal(
+                                v,
+                            ) => Multiplex::Multiplexed(v),
+                        },
+                        receivers: s
+                            .receivers()
+                            .iter()
+                            .filter(|r| *r != "Vector__XXX")
+                            .cloned()
+                            .collect(),
+                        comment: dbc
+                            .signal_comment(message_id, s.name())
+                            .unwrap_or_default()
+                            .to_string(),
+                        values: dbc
+                            .value_descriptions_for_signal(message_id, s.name())
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|v| (*v.a() as i64, v.b().clone()))
+                            .collect(),
+                    }
+                })
+                .collect();
+
+            Message {
+                id,
+                extended,
+                name: message.message_name().clone(),
+                size: *message.message_size() as usize,
+                transmitter,
+                cycle_time: cycle_time(dbc, message_id),
+                signals,
+                comment: dbc
+                    .message_comment(message_id)
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+        })
+        .collect();
+
+    let e2e = messages
+        .iter()
+        .filter_map(|m: &Message| Some((m.id, e2e_protection(dbc, m)?)))
+        .collect();
+
+    let mut database = Database::new(nodes, messages);
+    database.e2e = e2e;
+    database.j1939 = dbc.attribute_values().iter().any(|a| {
+        a.attribute_name() == "ProtocolType"
+            && *a.attribute_value()
+                == AttributeValuedForObjectType::RawAttributeValue(
+                    AttributeValue::AttributeValueCharString("J1939".into()),
+                )
+    });
+    database
+}
+
+/// Look up a message attribute
+fn message_attribute<'a>(
+    dbc: &'a DBC,
+    id: MessageId,
+    name: &str,
+) -> Option<&'a AttributeValue> {
+    dbc.attribute_values().iter().find_map(|a| {
+        if a.attribute_name() != name {
+            return None;
+        }
+        match a.attribute_value() {
+            AttributeValuedForObjectType::MessageDefinitionAttributeValue(
+                message_id,
+                Some(value),
+            ) if *message_id == id => Some(value),
+            _ => None,
+        }
+    })
+}
+
+fn attribute_number(value: &AttributeValue) -> Option<u64> {
+    match value {
+        AttributeValue::AttributeValueU64(v) => Some(*v),
+        AttributeValue::AttributeValueI64(v) => Some(*v as u64),
+        AttributeValue::AttributeValueF64(v) => Some(*v as u64),
+        _ => None,
+    }
+}
+
+/// Look up the `GenMsgCycleTime` attribute of a message
+fn cycle_time(dbc: &DBC, id: MessageId) -> Option<u32> {
+    message_attribute(dbc, id, "GenMsgCycleTime")
+        .and_then(attribute_number)
+        .map(|t| t as u32)
+        .filter(|t| *t > 0)
+}
+
+/// Build the E2E protection of a message from its `E2EProfile`,
+/// `E2EDataID` and `E2EDataIDMode` attributes
+///
+/// The CRC and counter positions are taken from signals named like
+/// `*CRC`/`*Checksum` and `*Counter`/`*Cntr`, falling back to the
+/// profile's default layout. Messages with a CRC not made of whole bytes,
+/// or a counter crossing a byte boundary, are left unprotected.
+fn e2e_protection(dbc: &DBC, message: &Message) -> Option<Protection> {
+    let id = match message.extended {
+        true => MessageId::Extended(message.id),
+        false => MessageId::Standard(message.id as u16),
+    };
+    let profile = match message_attribute(dbc, id, "E2EProfile")? {
+        AttributeValue::AttributeValueCharString(text) => Profile::parse(text)?,
+        value => Profile::parse(&attribute_number(value)?.to_string())?,
+    };
+    let mut protection = Protection::new(profile);
+    if let Some(data_id) =
+        message_attribute(dbc, id, "E2EDataID").and_then(attribute_number)
+    {
+        protection.data_id = data_id as u16;
+    }
+    if let Some(AttributeValue::AttributeValueCharString(mode)) =
+        message_attribute(dbc, id, "E2EDataIDMode")
+    {
+        protection.mode = match mode.to_ascii_lowercase().as_str() {
+            "alt" | "alternating" => DataIdMode::Alternating,
+            "low" => DataIdMode::Low,
+            "nibble" => DataIdMode::Nibble,
+            _ => DataIdMode::Both,
+        };
+    }
+
+    // lowest and highest bit of a signal, numbered as in little endian
+    let bits = |s: &Signal| match s.byte_order {
+        ByteOrder::BigEndian => {
+            (flip_bit(flip_bit(s.start_bit) + s.size - 1), s.start_bit)
+        }
+        ByteOrder::LittleEndian => (s.start_bit, s.start_bit + s.size - 1),
+    };
+    for signal in message.signals.iter().filter(|s| s.size > 0) {
+        let name = signal.name.to_ascii_lowercase();
+        let (low, high) = bits(signal);
+        if ["crc", "checksum", "chksum"]
+            .iter()
+            .any(|n| name.ends_with(n))
+        {
+            // the CRC is read as whole bytes
+            if low % 8 != 0
+                || high % 8 != 7
+                || signal.size > protection.crc_size() * 8
+            {
+                return None;
+            }
+            protection.crc_offset = low.min(high) / 8 * 8;
+        } else if ["counter", "cntr", "alive"]
+            .iter()
+            .any(|n| name.ends_with(n))
+            && signal.size <= 8
+        {
+            // the counter is read from a single byte
+            if low / 8 != high / 8 {
+                return None;
+            }
+            protection.counter_offset = low;
+            protection.counter_size = signal.size;
+        }
+    }
+    Some(protection)
+}
+
+/// DBC identifier for a name, with other characters replaced by `_`
+pub fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// DBC string, which can't hold double quotes
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "'"))
+}
+
+/// Format a message definition in DBC notation (`BO_` and `SG_` lines)
+pub fn format_message(message: &Message) -> String {
+    let id = dbc_id(message);
+    let transmitter = match &message.transmitter {
+        Some(transmitter) => identifier(transmitter),
+        None => "Vector__XXX".into(),
+    };
+    let mut text = format!(
+        "BO_ {id} {}: {} {transmitter}\n",
+        identifier(&message.name),
+        message.size
+    );
+    for signal in message.signals.iter() {
+        let multiplex = match signal.multiplex {
+            Multiplex::Plain => String::new(),
+            Multiplex::Multiplexor => " M".into(),
+            Multiplex::Multiplexed(value) => format!(" m{value}"),
+        };
+        let order = match signal.byte_order {
+            ByteOrder::LittleEndian => 1,
+            ByteOrder::BigEndian => 0,
+        };
+        // floats are marked by `SIG_VALTYPE_`
+        let sign = match signal.value_type {
+            ValueType::Signed | ValueType::Float => '-',
+            ValueType::Unsigned => '+',
+        };
+        let receivers = match signal.receivers.is_empty() {
+            true => "Vector__XXX".to_string(),
+            false => {
+                let receivers: Vec<String> =
+                    signal.receivers.iter().map(|r| identifier(r)).collect();
+                receivers.join(",")
+            }
+        };
+        text.push_str(&format!(
+            " SG_ {}{multiplex} : {}|{}@{order}{sign} ({},{}) [{}|{}] {} \
+             {receivers}\n",
+            identifier(&signal.name),
+            signal.start_bit,
+            signal.size,
+            signal.factor,
+            signal.offset,
+            signal.min,
+            signal.max,
+            string(&signal.unit),
+        ));
+    }
+    text
+}
+
+/// Format a database as a DBC file, with comments, message cycle times as
+/// `GenMsgCycleTime` attributes, E2E protections as `E2E*` attributes,
+/// value descriptions and float signal types
+pub fn format(database: &Database) -> String {
+    let mut text = "VERSION \"\"\n\nNS_ :\n\nBS_:\n\n".to_string();
+    let nodes: Vec<String> =
+        database.nodes.iter().map(|n| identifier(n)).collect();
+    text.push_str(&format!("BU_: {}\n\n", nodes.join(" ")));
+    for message in database.messages() {
+        text.push_str(&format_message(message));
+        text.push('\n');
+    }
+
+    for message in database.messages() {
+        let id = dbc_id(message);
+        if !message.comment.is_empty() {
+            let comment = string(&message.comment);
+            text.push_str(&format!("CM_ BO_ {id} {comment};\n"));
+        }
+        for signal in message.signals.iter().filter(|s| !s.comment.is_empty()) {
+            let name = identifier(&signal.name);
+            let comment = string(&signal.comment);
+            text.push_str(&format!("CM_ SG_ {id} {name} {comment};\n"));
+        }
+    }
+
+    let protections: Vec<(&Message, &Protection)> = database
+        .messages()
+        .iter()
+        .filter_map(|m| Some((m, database.e2e.get(&m.id)?)))
+        .collect();
+    text.push_str("BA_DEF_ BO_ \"GenMsgCycleTime\" INT 0 65535;\n");
+    if !protections.is_empty() {
+        text.push_str("BA_DEF_ BO_ \"E2EProfile\" STRING ;\n");
+        text.push_str("BA_DEF_ BO_ \"E2EDataID\" INT 0 65535;\n");
+        text.push_str("BA_DEF_ BO_ \"E2EDataIDMode\" STRING ;\n");
+    }
+    if database.j1939 {
+        text.push_str("BA_DEF_ \"ProtocolType\" STRING ;\n");
+    }
+    text.push_str("BA_DEF_DEF_ \"GenMsgCycleTime\" 0;\n");
+    if database.j1939 {
+        text.push_str("BA_ \"ProtocolType\" \"J1939\";\n");
+    }
+    for message in database.messages() {
+        if let Some(cycle_time) = message.cycle_time {
+            text.push_str(&format!(
+                "BA_ \"GenMsgCycleTime\" BO_ {} {cycle_time};\n",
+                dbc_id(message)
+            ));
+        }
+    }
+    for (message, protection) in protections {
+        let id = dbc_id(message);
+        let mode = match protection.mode {
+            DataIdMode::Both => "both",
+            DataIdMode::Alternating => "alt",
+            DataIdMode::Low => "low",
+            DataIdMode::Nibble => "nibble",
+        };
+        text.push_str(&format!(
+            "BA_ \"E2EProfile\" BO_ {id} \"{}\";\n\
+             BA_ \"E2EDataID\" BO_ {id} {};\n\
+             BA_ \"E2EDataIDMode\" BO_ {id} \"{mode}\";\n",
+            protection.profile.name(),
+            protection.data_id
+        ));
+    }
+
+    for message in database.messages() {
+        for signal in message.signals.iter().filter(|s| !s.values.is_empty()) {
+            let values: Vec<String> = signal
+                .values
+                .iter()
+                .map(|(value, text)| format!("{value} {}", string(text)))
+                .collect();
+            text.push_str(&format!(
+                "VAL_ {} {} {} ;\n",
+                dbc_id(message),
+                identifier(&signal.name),
+                values.join(" ")
+            ));
+        }
+    }
+    for message in database.messages() {
+        for signal in message.signals.iter() {
+            if signal.value_type == ValueType::Float {
+                let kind = if signal.size == 64 { 2 } else { 1 };
+                text.push_str(&format!(
+                    "SIG_VALTYPE_ {} {} : {kind};\n",
+                    dbc_id(message),
+                    identifier(&signal.name)
+                ));
+            }
+        }
+    }
+    text
+}
+
+fn dbc_id(message: &Message) -> u32 {
+    match message.extended {
+        true => message.id | 0x8000_0000,
+        false => message.id,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn e2e_attributes() {
+        let text = r#"VERSION ""
+
+NS_ :
+
+BS_:
+
+BU_: ECU
+
+BO_ 291 Msg: 8 ECU
+ SG_ Msg_Counter : 12|4@1+ (1,0) [0|14] "" Vector__XXX
+ SG_ Msg_CRC : 8|8@1+ (1,0) [0|255] "" Vector__XXX
+
+BA_DEF_ BO_ "E2EProfile" STRING ;
+BA_DEF_ BO_ "E2EDataID" INT 0 65535;
+BA_ "E2EProfile" BO_ 291 "P01";
+BA_ "E2EDataID" BO_ 291 4660;
+"#;
+        let database = parse(text.as_bytes()).unwrap();
+        let protection = database.e2e.get(&0x123).unwrap();
+        assert_eq!(protection.profile, Profile::P1);
+        assert_eq!(protection.data_id, 0x1234);
+        assert_eq!(protection.crc_offset, 8);
+        assert_eq!(protection.counter_offset, 12);
+        assert_eq!(protection.counter_size, 4);
+
+        // big endian fields, in the second and third byte
+        let text = text
+            .replace("12|4@1+", "15|4@0+")
+            .replace("8|8@1+", "23|8@0+");
+        let database = parse(text.as_bytes()).unwrap();
+        let protection = database.e2e.get(&0x123).unwrap();
+        assert_eq!(protection.crc_offset, 16);
+        assert_eq!(protection.counter_offset, 12);
+
+        // a CRC across two bytes can't be read
+        let text = text.replace("23|8@0+", "3|8@0+");
+        let database = parse(text.as_bytes()).unwrap();
+        assert!(!database.e2e.contains_key(&0x123));
+    }
+
+    #[test]
+    fn format() {
+        let mut message = Message {
+            id: 0x18fef100,
+            extended: true,
+            name: "CCVS".into(),
+            size: 8,
+            ..Default::default()
+        };
+        let mut signal =
+            Signal::parse("Speed : 15|16@0+ (0.5,-10) \"km/h\"").unwrap();
+        signal.max = 250.0;
+        message.signals.push(signal);
+
+        let text = format_message(&message);
+        assert_eq!(
+            text,
+            "BO_ 2566844672 CCVS: 8 Vector__XXX\n \
+             SG_ Speed : 15|16@0+ (0.5,-10) [0|250] \"km/h\" Vector__XXX\n"
+        );
+        let dbc = format!("VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_:\n\n{text}");
+        let database = parse(dbc.as_bytes()).unwrap();
+        assert_eq!(database.messages()[0], message);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut message = Message {
+            id: 0x123,
+            name: "Status".into(),
+            size: 4,
+            transmitter: Some("ECU".into()),
+            cycle_time: Some(100),
+            comment: "Status of the \"ECU\"".into(),
+            ..Default::default()
+        };
+        let mut flag = Signal::new("Flag", 0, 1);
+        flag.comment = "Set when active".into();
+        flag.values = vec![(0, "Off".into()), (1, "On".into())];
+        message.signals.push(flag);
+        let mut level = Signal::new("Level", 8, 32);
+        level.value_type = ValueType::Float;
+        message.signals.push(level);
+        let unknown = Message {
+            id: 0x18fef100,
+            extended: true,
+            name: "MSG_18FEF100".into(),
+            size: 8,
+            cycle_time: Some(1000),
+            ..Default::default()
+        };
+        let mut velocity = Message {
+            id: 0x181,
+            name: "TPDO1".into(),
+            size: 2,
+            ..Default::default()
+        };
+        velocity
+            .signals
+            .push(Signal::new("Velocity actual value", 0, 16));
+        let mut database =
+            Database::new(vec!["ECU".into()], vec![message, unknown, velocity]);
+        database.j1939 = true;
+        let mut protection = Protection::new(Profile::P1);
+        protection.data_id = 0x1234;
+        protection.mode = DataIdMode::Nibble;
+        database.e2e.insert(0x123, protection.clone());
+
+        let text = super::format(&database);
+        let parsed = parse(text.as_bytes()).unwrap();
+        // quotes and names are adapted to DBC syntax
+        let mut messages = database.messages().clone();
+        for message in messages.iter_mut() {
+            message.comment = message.comment.replace('"', "'");
+            for signal in message.signals.iter_mut() {
+                signal.name = identifier(&signal.name);
+            }
+        }
+        assert_eq!(*parsed.messages(), messages);
+        assert_eq!(parsed.nodes, database.nodes);
+        assert!(parsed.j1939);
+        assert_eq!(parsed.e2e.get(&0x123), Some(&protection));
+    }
+}
@@ -0,0 +1,265 @@
+//! CANopen EDS/DCF loader, mapping process data objects (PDOs) to messages
+
+use super::{Database, Message, Signal, ValueType};
+use crate::invalid;
+use std::collections::HashMap;
+use std::io;
+
+/// INI style sections, keyed by lower-case section and key names
+type Sections = HashMap<String, HashMap<String, String>>;
+
+fn sections(text: &str) -> Sections {
+    let mut sections = Sections::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if let Some(name) = line.strip_prefix('[') {
+            current = name.trim_end_matches(']').trim().to_ascii_lowercase();
+            sections.entry(current.clone()).or_default();
+        } else if let Some((key, value)) = line.split_once('=') {
+            sections
+                .entry(current.clone())
+                .or_default()
+                .insert(key.trim().to_ascii_lowercase(), value.trim().into());
+        }
+    }
+    sections
+}
+
+/// Parse an integer value, which may refer to the node ID as in
+/// `$NODEID+0x180`
+fn parse_value(text: &str, node: Option<u32>) -> io::Result<u32> {
+    let error = || invalid(format!("Invalid value {text}"));
+    let mut total = 0u32;
+    for term in text.split('+').map(|t| t.trim()) {
+        let value = if term.eq_ignore_ascii_case("$NODEID") {
+            node.ok_or_else(|| invalid("Node ID required".into()))?
+        } else if let Some(hex) =
+            term.strip_prefix("0x").or_else(|| term.strip_prefix("0X"))
+        {
+            u32::from_str_radix(hex, 16).map_err(|_| error())?
+        } else {
+            term.parse().map_err(|_| error())?
+        };
+        total = total.wrapping_add(value);
+    }
+    Ok(total)
+}
+
+struct Eds {
+    sections: Sections,
+    node: Option<u32>,
+}
+
+impl Eds {
+    fn section(
+        &self,
+        index: u16,
+        sub: Option<u8>,
+    ) -> Option<&HashMap<String, String>> {
+        let name = match sub {
+            Some(sub) => format!("{index:04x}sub{sub:x}"),
+            None => format!("{index:04x}"),
+        };
+        self.sections.get(&name)
+    }
+
+    /// Configured (DCF) or default value of an object
+    fn value(&self, index: u16, sub: u8) -> io::Result<Option<u32>> {
+        let Some(section) = self.section(index, Some(sub)) else {
+            return Ok(None);
+        };
+        let value = section
+            .get("parametervalue")
+            .or_else(|| section.get("defaultvalue"))
+            .filter(|v| !v.is_empty());
+        value.map(|v| parse_value(v, self.node)).transpose()
+    }
+
+    fn object(&self, index: u16, sub: u8) -> Option<&HashMap<String, String>> {
+        self.section(index, Some(sub))
+            .or_else(|| self.section(index, None).filter(|_| sub == 0))
+    }
+
+    fn mapping(
+        &self,
+        name: String,
+        id: u32,
+        mapping: u16,
+        transmitter: Option<String>,
+    ) -> io::Result<Option<Message>> {
+        let count = self.value(mapping, 0)?.unwrap_or_default();
+        let mut signals = vec![];
+        let mut start = 0;
+        for sub in 1..=count.min(64) as u8 {
+            let Some(entry) = self.value(mapping, sub)? else {
+                continue;
+            };
+            let index = (entry >> 16) as u16;
+            let subindex = (entry >> 8) as u8;
+            let size = (entry & 0xff) as usize;
+
+            // dummy entries only occupy space
+            if index >= 0x1000 && size > 0 {
+                let object = self.object(index, subindex);
+                let name = object
+                    .and_then(|o| o.get("parametername"))
+                    .cloned()
+                    .unwrap_or_else(|| format!("{index:04X}sub{subindex:X}"));
+                let data_type = object
+                    .and_then(|o| o.get("datatype"))
+                    .map(|t| parse_value(t, None))
+                    .transpose()?
+                    .unwrap_or_default();
+                let mut signal = Signal::new(&name, start, size);
+                signal.value_type = match data_type {
+                    0x02..=0x04 | 0x10 | 0x12..=0x15 => ValueType::Signed,
+                    0x08 | 0x11 => ValueType::Float,
+                    _ => ValueType::Unsigned,
+                };
+                signals.push(signal);
+            }
+            start += size;
+        }
+        if signals.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Message {
+            id,
+            extended: false,
+            name,
+            size: start.div_ceil(8),
+            transmitter,
+            cycle_time: None,
+            signals,
+            ..Default::default()
+        }))
+    }
+}
+
+/// Parse an EDS or DCF file into a database of its mapped PDOs
+///
+/// The node ID is taken from `node` (e.g. the `#<node>` suffix of the
+/// file name), falling back to the commissioning section of a DCF.
+pub fn parse(text: &str, node: Option<&str>) -> io::Result<Database> {
+    let sections = sections(text);
+    let node = match node {
+        Some(node) => Some(parse_value(node, None)?),
+        None => sections
+            .get("devicecomissioning")
+            .and_then(|s| s.get("nodeid"))
+            .map(|n| parse_value(n, None))
+            .transpose()?,
+    };
+    let eds = Eds { sections, node };
+    let device = eds
+        .sections
+        .get("deviceinfo")
+        .and_then(|s| s.get("productname"))
+        .cloned();
+
+    let mut messages = vec![];
+    for pdo in 0..512u16 {
+        for (prefix, communication, mapping, default) in [
+            ("TPDO", 0x1800, 0x1a00, 0x180),
+            ("RPDO", 0x1400, 0x1600, 0x200),
+        ] {
+            if eds.section(mapping + pdo, None).is_none() {
+                continue;
+            }
+            let id = match eds.value(communication + pdo, 1)? {
+                Some(id) => id,
+                // predefined connection set covers the first four PDOs
+                None if pdo < 4 => match eds.node {
+                    Some(node) => default + 0x100 * pdo as u32 + node,
+                    None => continue,
+                },
+                None => continue,
+            };
+            // PDO not valid
+            if id & 0x8000_0000 != 0 {
+                continue;
+            }
+            let transmitter = (prefix == "TPDO").then(|| device.clone());
+            let name = format!("{prefix}{}", pdo + 1);
+            if let Some(message) = eds.mapping(
+                name,
+                id & 0x7ff,
+                mapping + pdo,
+                transmitter.flatten(),
+            )? {
+                messages.push(message);
+            }
+        }
+    }
+
+    Ok(Database::new(device.into_iter().collect(), messages))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EDS: &str = r#"
+[DeviceInfo]
+ProductName=Drive
+
+[6041]
+ParameterName=Statusword
+DataType=0x0006
+
+[606C]
+ParameterName=Velocity actual value
+DataType=0x0004
+
+[1800]
+SubNumber=2
+
+[1800sub1]
+ParameterName=COB-ID
+DefaultValue=$NODEID+0x180
+
+[1A00]
+SubNumber=3
+
+[1A00sub0]
+DefaultValue=2
+
+[1A00sub1]
+DefaultValue=0x60410010
+
+[1A00sub2]
+DefaultValue=0x606C0020
+
+[1601]
+SubNumber=2
+
+[1601sub0]
+DefaultValue=1
+
+[1601sub1]
+DefaultValue=0x60410010
+"#;
+
+    #[test]
+    fn pdo_mapping() {
+        let db = parse(EDS, Some("5")).unwrap();
+        assert_eq!(db.nodes, vec!["Drive"]);
+        let tpdo = db.message(0x185).unwrap();
+        assert_eq!(tpdo.name, "TPDO1");
+        assert_eq!(tpdo.size, 6);
+        assert_eq!(tpdo.transmitter.as_deref(), Some("Drive"));
+        assert_eq!(tpdo.signals[1].name, "Velocity actual value");
+        assert_eq!(tpdo.signals[1].start_bit, 16);
+        assert_eq!(tpdo.signals[1].value_type, ValueType::Signed);
+        let bytes = [0x37, 0x02, 0xfe, 0xff, 0xff, 0xff];
+        assert_eq!(tpdo.signals[1].value(&bytes), Some(-2.0));
+
+        // RPDO2 of the predefined connection set
+        let rpdo = db.message(0x305).unwrap();
+        assert_eq!(rpdo.name, "RPDO2");
+
+        // EDS files need the node ID for their COB-IDs
+        assert!(parse(EDS, None).is_err());
+    }
+}
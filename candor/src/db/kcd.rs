@@ -0,0 +1,231 @@
+//! Kayak KCD (XML) database loader
+
+use super::{
+    flip_bit, ByteOrder, Database, Message, Multiplex, Signal, ValueType,
+};
+use crate::invalid;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::io;
+
+/// Parse a KCD file, optionally restricted to the named bus
+pub fn parse(text: &str, bus: Option<&str>) -> io::Result<Database> {
+    let doc = Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "NetworkDefinition" {
+        return Err(invalid("Not a KCD network definition".into()));
+    }
+
+    // node references are by ID, signals/messages refer to names
+    let mut node_names: HashMap<String, String> = HashMap::new();
+    for node in children(root, "Node") {
+        let id = node.attribute("id").unwrap_or_default();
+        let name = node.attribute("name").unwrap_or(id);
+        node_names.insert(id.to_string(), name.to_string());
+    }
+    let nodes = children(root, "Node")
+        .filter_map(|n| node_names.get(n.attribute("id").unwrap_or_default()))
+        .cloned()
+        .collect();
+
+    let buses: Vec<Node> = children(root, "Bus")
+        .filter(|b| bus.is_none() || b.attribute("name") == bus)
+        .collect();
+    if let (Some(name), true) = (bus, buses.is_empty()) {
+        return Err(invalid(format!("No bus named {name}")));
+    }
+
+    let mut messages = vec![];
+    for bus in buses {
+        for message in children(bus, "Message") {
+            messages.push(parse_message(message, &node_names)?);
+        }
+    }
+
+    Ok(Database::new(nodes, messages))
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn parse_int(text: &str) -> io::Result<u64> {
+    let result = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+    result.map_err(|_| invalid(format!("Invalid number {text}")))
+}
+
+fn attr_int(node: Node, name: &str, default: u64) -> io::Result<u64> {
+    node.attribute(name).map_or(Ok(default), parse_int)
+}
+
+fn attr_float(node: Node, name: &str, default: f64) -> io::Result<f64> {
+    node.attribute(name).map_or(Ok(default), |s| {
+        s.parse::<f64>()
+            .map_err(|_| invalid(format!("Invalid number {s}")))
+    })
+}
+
+fn node_refs(
+    node: Node,
+    parent: &'static str,
+    names: &HashMap<String, String>,
+) -> Vec<String> {
+    children(node, parent)
+        .flat_map(|p| children(p, "NodeRef"))
+        .filter_map(|r| names.get(r.attribute("id").unwrap_or_default()))
+        .cloned()
+        .collect()
+}
+
+fn parse_message(
+    node: Node,
+    names: &HashMap<String, String>,
+) -> io::Result<Message> {
+    let id = node
+        .attribute("id")
+        .ok_or_else(|| invalid("Message without id".into()))?;
+    let id = parse_int(id)? as u32;
+    let extended = node.attribute("format") == Some("extended");
+
+    let mut signals = vec![];
+    for signal in children(node, "Signal") {
+        signals.push(parse_signal(signal, Multiplex::Plain, names)?);
+    }
+    for mux in children(node, "Multiplex") {
+        signals.push(parse_signal(mux, Multiplex::Multiplexor, names)?);
+        for group in children(mux, "MuxGroup") {
+            let count = attr_int(group, "count", 0)?;
+            for signal in children(group, "Signal") {
+                let multiplex = Multiplex::Multiplexed(count);
+                signals.push(parse_signal(signal, multiplex, names)?);
+            }
+        }
+    }
+
+    // "auto" (or missing) length is derived from the signal layout
+    let size = match node.attribute("length") {
+        Some("auto") | None => {
+            signals.iter().map(|s| s.min_length()).max().unwrap_or(0)
+        }
+        Some(length) => parse_int(length)? as usize,
+    };
+
+    let interval = attr_int(node, "interval", 0)? as u32;
+
+    Ok(Message {
+        id,
+        extended,
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        size,
+        transmitter: node_refs(node, "Producer", names).into_iter().next(),
+        cycle_time: (interval > 0).then_some(interval),
+        signals,
+        ..Default::default()
+    })
+}
+
+fn parse_signal(
+    node: Node,
+    multiplex: Multiplex,
+    names: &HashMap<String, String>,
+) -> io::Result<Signal> {
+    let offset = attr_int(node, "offset", 0)? as usize;
+    let size = attr_int(node, "length", 1)? as usize;
+    let byte_order = match node.attribute("endianess") {
+        Some("big") => ByteOrder::BigEndian,
+        _ => ByteOrder::LittleEndian,
+    };
+
+    let mut signal =
+        Signal::new(node.attribute("name").unwrap_or_default(), 0, size);
+    signal.byte_order = byte_order;
+    signal.start_bit = match byte_order {
+        ByteOrder::LittleEndian => offset,
+        ByteOrder::BigEndian => flip_bit(offset),
+    };
+    signal.multiplex = multiplex;
+    signal.receivers = node_refs(node, "Consumer", names);
+
+    if let Some(value) = children(node, "Value").next() {
+        signal.value_type = match value.attribute("type") {
+            Some("signed") => ValueType::Signed,
+            Some("single") | Some("double") => ValueType::Float,
+            _ => ValueType::Unsigned,
+        };
+        signal.factor = attr_float(value, "slope", 1.0)?;
+        signal.offset = attr_float(value, "intercept", 0.0)?;
+        signal.min = attr_float(value, "min", 0.0)?;
+        signal.max = attr_float(value, "max", 0.0)?;
+        signal.unit = value.attribute("unit").unwrap_or_default().to_string();
+    }
+
+    Ok(signal)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn network_definition() {
+        let kcd = r#"<?xml version="1.0" encoding="UTF-8"?>
+<NetworkDefinition xmlns="http://kayak.2codeornot2code.org/1.0">
+  <Document name="Test" version="1.0"/>
+  <Node id="1" name="Motor"/>
+  <Node id="2" name="Dashboard"/>
+  <Bus name="Powertrain" baudrate="500000">
+    <Message id="0x0A0" name="EngineData" length="8" interval="100">
+      <Producer><NodeRef id="1"/></Producer>
+      <Signal name="Rpm" offset="0" length="16">
+        <Value slope="0.25" unit="rpm" min="0" max="16383.75"/>
+        <Consumer><NodeRef id="2"/></Consumer>
+      </Signal>
+      <Signal name="Temp" offset="8" length="8" endianess="big">
+        <Value type="signed" intercept="-40" unit="C"/>
+      </Signal>
+    </Message>
+    <Message id="0x18FEF100" name="Diag" format="extended">
+      <Multiplex name="Page" offset="0" length="8">
+        <MuxGroup count="1">
+          <Signal name="Voltage" offset="8" length="8"/>
+        </MuxGroup>
+        <MuxGroup count="2">
+          <Signal name="Current" offset="8" length="16"/>
+        </MuxGroup>
+      </Multiplex>
+    </Message>
+  </Bus>
+</NetworkDefinition>
+"#;
+        let db = parse(kcd, None).unwrap();
+        assert_eq!(db.nodes, vec!["Motor", "Dashboard"]);
+        assert_eq!(db.messages().len(), 2);
+
+        let engine = db.message(0xa0).unwrap();
+        assert_eq!(engine.name, "EngineData");
+        assert!(!engine.extended);
+        assert_eq!(engine.cycle_time, Some(100));
+        assert_eq!(engine.transmitter.as_deref(), Some("Motor"));
+        assert_eq!(engine.signals[0].receivers, vec!["Dashboard"]);
+        assert_eq!(engine.signals[0].value(&[0x10, 0x00]), Some(4.0));
+        assert_eq!(engine.signals[1].start_bit, 15);
+        assert_eq!(engine.signals[1].value(&[0, 0x50]), Some(40.0));
+
+        let diag = db.message(0x18fef100).unwrap();
+        assert!(diag.extended);
+        assert_eq!(diag.size, 3);
+        assert_eq!(diag.signals.len(), 3);
+        assert_eq!(diag.signals[2].multiplex, Multiplex::Multiplexed(2));
+        assert!(diag.is_present(&diag.signals[2], &[2, 0, 0]));
+
+        assert!(parse(kcd, Some("Powertrain")).is_ok());
+        assert!(parse(kcd, Some("Body")).is_err());
+    }
+}
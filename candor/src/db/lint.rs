@@ -0,0 +1,236 @@
+//! Database consistency checks
+
+use super::{ByteOrder, Database, Message, Multiplex, Signal, ValueType};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Problem found in a database
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// Name of the message concerned
+    pub message: String,
+    pub text: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.message, self.text)
+    }
+}
+
+/// Check the messages of a database
+pub fn check(database: &Database) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut ids: BTreeMap<u32, &str> = BTreeMap::new();
+    for message in database.messages() {
+        let mut warn = |text: String| {
+            warnings.push(Warning {
+                message: message.name.clone(),
+                text,
+            })
+        };
+        if let Some(other) = ids.insert(message.id, &message.name) {
+            warn(format!("duplicate ID {:X} of {other}", message.id));
+        }
+        if message.cycle_time.is_none() {
+            warn("no cycle time".into());
+        }
+        check_multiplexing(message, &mut warn);
+
+        for (index, signal) in message.signals.iter().enumerate() {
+            if signal.size == 0 || signal.min_length() > message.size {
+                warn(format!(
+                    "{} exceeds the message length of {} bytes",
+                    signal.name, message.size
+                ));
+            }
+            if let Some(text) = check_range(signal) {
+                warn(format!("{} {text}", signal.name));
+            }
+            for other in message.signals[index + 1..].iter() {
+                if concurrent(signal, other) && overlap(signal, other) {
+                    warn(format!("{} overlaps {}", signal.name, other.name));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// Check for messages defined in more than one of a set of databases
+pub fn check_duplicates(databases: &[&Database]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut ids: BTreeMap<u32, (usize, &str)> = BTreeMap::new();
+    for (index, database) in databases.iter().enumerate() {
+        for message in database.messages() {
+            let other = ids.entry(message.id).or_insert((index, &message.name));
+            if other.0 != index {
+                warnings.push(Warning {
+                    message: message.name.clone(),
+                    text: format!(
+                        "ID {:X} also defined as {} in database {}",
+                        message.id,
+                        other.1,
+                        other.0 + 1
+                    ),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+fn check_multiplexing(message: &Message, warn: &mut impl FnMut(String)) {
+    let multiplexors: Vec<&Signal> = message
+        .signals
+        .iter()
+        .filter(|s| s.multiplex == Multiplex::Multiplexor)
+        .collect();
+    if multiplexors.len() > 1 {
+        warn(format!("{} multiplexor signals", multiplexors.len()));
+    }
+    for signal in message.signals.iter() {
+        let Multiplex::Multiplexed(value) = signal.multiplex else {
+            continue;
+        };
+        match multiplexors.first() {
+            None => {
+                warn(format!("{} multiplexed without multiplexor", signal.name))
+            }
+            Some(multiplexor)
+                if multiplexor.size < 64 && value >> multiplexor.size != 0 =>
+            {
+                warn(format!(
+                    "{} multiplexor value {value} exceeds {}",
+                    signal.name, multiplexor.name
+                ))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether two signals can be present in the same frame
+fn concurrent(a: &Signal, b: &Signal) -> bool {
+    match (a.multiplex, b.multiplex) {
+        (Multiplex::Multiplexed(x), Multiplex::Multiplexed(y)) => x == y,
+        _ => true,
+    }
+}
+
+/// Payload bits of a signal, in DBC numbering
+fn bits(signal: &Signal) -> Vec<usize> {
+    match signal.byte_order {
+        ByteOrder::LittleEndian => {
+            (signal.start_bit..signal.start_bit + signal.size).collect()
+        }
+        ByteOrder::BigEndian => {
+            let start = super::flip_bit(signal.start_bit);
+            (start..start + signal.size).map(super::flip_bit).collect()
+        }
+    }
+}
+
+fn overlap(a: &Signal, b: &Signal) -> bool {
+    let a = bits(a);
+    bits(b).iter().any(|bit| a.contains(bit))
+}
+
+/// Check a signal's limits against the values its bits can hold
+fn check_range(signal: &Signal) -> Option<String> {
+    if signal.min == 0.0 && signal.max == 0.0 {
+        return None;
+    }
+    if signal.min > signal.max {
+        return Some(format!(
+            "minimum {} above maximum {}",
+            signal.min, signal.max
+        ));
+    }
+    if signal.value_type == ValueType::Float || signal.size > 64 {
+        return None;
+    }
+    let size = signal.size as i32;
+    let (raw_min, raw_max) = match signal.value_type {
+        ValueType::Signed => {
+            (-(2f64.powi(size - 1)), 2f64.powi(size - 1) - 1.0)
+        }
+        _ => (0.0, 2f64.powi(size) - 1.0),
+    };
+    let a = raw_min * signal.factor + signal.offset;
+    let b = raw_max * signal.factor + signal.offset;
+    let (min, max) = (a.min(b), a.max(b));
+    let margin = signal.factor.abs() / 2.0;
+    (signal.min < min - margin || signal.max > max + margin).then(|| {
+        format!(
+            "range [{}|{}] exceeds [{min}|{max}] of {} bits",
+            signal.min, signal.max, signal.size
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn warnings() {
+        let signal = |text: &str| Signal::parse(text).unwrap();
+        let mut speed = signal("Speed : 0|16@1+ (0.1,0) \"km/h\"");
+        speed.max = 7000.0;
+        let mut mode = signal("Mode : 16|2@1+ (1,0)");
+        mode.multiplex = Multiplex::Multiplexor;
+        let mut a = signal("A : 24|8@1+ (1,0)");
+        a.multiplex = Multiplex::Multiplexed(0);
+        let mut b = signal("B : 24|8@1+ (1,0)");
+        b.multiplex = Multiplex::Multiplexed(1);
+        let mut c = signal("C : 24|8@1+ (1,0)");
+        c.multiplex = Multiplex::Multiplexed(4);
+        let message = Message {
+            id: 0x100,
+            name: "Status".into(),
+            size: 4,
+            cycle_time: Some(100),
+            signals: vec![
+                speed,
+                signal("Temp : 15|8@0- (1,-40)"),
+                mode,
+                a,
+                b,
+                c,
+                signal("Tail : 30|4@1+ (1,0)"),
+            ],
+            ..Default::default()
+        };
+        let other = Message {
+            id: 0x100,
+            name: "Other".into(),
+            ..Default::default()
+        };
+        let database = Database::new(vec![], vec![message, other.clone()]);
+
+        let warnings: Vec<String> =
+            check(&database).iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "Status: C multiplexor value 4 exceeds Mode",
+                "Status: Speed range [0|7000] exceeds [0|6553.5] of 16 bits",
+                "Status: Speed overlaps Temp",
+                "Status: A overlaps Tail",
+                "Status: B overlaps Tail",
+                "Status: C overlaps Tail",
+                "Status: Tail exceeds the message length of 4 bytes",
+                "Other: duplicate ID 100 of Status",
+                "Other: no cycle time",
+            ]
+        );
+
+        let second = Database::new(vec![], vec![other]);
+        let warnings = check_duplicates(&[&database, &second]);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Other: ID 100 also defined as Status in database 1"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Scoring of candidate databases against observed traffic
+
+use super::Database;
+use crate::{analysis, Packet};
+use std::collections::VecDeque;
+
+/// Number of recent packets per message checked for plausible values
+const PLAUSIBILITY_SAMPLES: usize = 32;
+
+/// Agreement of a database with observed traffic, each as a fraction
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct Score {
+    /// Observed IDs defined by the database
+    pub coverage: f64,
+    /// Defined messages observed with the defined length
+    pub dlc: f64,
+    /// Defined messages with a cycle time observed close to it
+    pub cycle_time: f64,
+    /// Decoded values within the defined limits
+    pub plausibility: f64,
+    /// Weighted total
+    pub total: f64,
+}
+
+impl Score {
+    pub fn text(&self) -> String {
+        format!(
+            "{:3.0}% (IDs {:.0}%, DLC {:.0}%, cycle {:.0}%, values {:.0}%)",
+            self.total * 100.0,
+            self.coverage * 100.0,
+            self.dlc * 100.0,
+            self.cycle_time * 100.0,
+            self.plausibility * 100.0
+        )
+    }
+}
+
+/// Traffic of an observed message
+pub struct Observed<'a> {
+    /// Latest packet
+    pub current: &'a Packet,
+    /// Recent packets, if kept
+    pub history: &'a VecDeque<Packet>,
+    /// Cycle time measured without history, in milliseconds
+    pub period: Option<u32>,
+}
+
+/// Score a database against the traffic of each observed message
+pub fn score<'a>(
+    database: &Database,
+    messages: impl IntoIterator<Item = Observed<'a>>,
+) -> Score {
+    let (mut observed, mut defined) = (0, 0);
+    let (mut dlc, mut timed, mut cycle_time) = (0, 0, 0);
+    let (mut values, mut plausible) = (0, 0);
+    for Observed {
+        current: packet,
+        history,
+        period,
+    } in messages
+    {
+        observed += 1;
+        let Some(message) = database.message(packet.id) else {
+            continue;
+        };
+        defined += 1;
+
+        if packet.bytes.len() == message.size {
+            dlc += 1;
+        }
+        if let Some(expected) = message.cycle_time {
+            timed += 1;
+            let measured = analysis::cycle_time(history.iter()).or(period);
+            let tolerance = expected as f64 * 0.25 + 2.0;
+            if measured.is_some_and(|m| {
+                (m as f64 - expected as f64).abs() <= tolerance
+            }) {
+                cycle_time += 1;
+            }
+        }
+
+        let recent = match history.is_empty() {
+            true => vec![packet],
+            false => history.iter().rev().take(PLAUSIBILITY_SAMPLES).collect(),
+        };
+        for packet in recent {
+            let bytes = packet.bytes.as_slice();
+            for signal in message.signals.iter() {
+                if signal.min >= signal.max
+                    || !message.is_present(signal, bytes)
+                {
+                    continue;
+                }
+                let Some(value) = signal.value(bytes) else {
+                    continue;
+                };
+                values += 1;
+                let margin = signal.factor.abs() / 2.0;
+                if value >= signal.min - margin && value <= signal.max + margin
+                {
+                    plausible += 1;
+                }
+            }
+        }
+    }
+
+    let fraction = |count: usize, total: usize| match total {
+        0 => 0.0,
+        total => count as f64 / total as f64,
+    };
+    let mut score = Score {
+        coverage: fraction(defined, observed),
+        dlc: fraction(dlc, defined),
+        cycle_time: fraction(cycle_time, timed),
+        plausibility: fraction(plausible, values),
+        total: 0.0,
+    };
+    // criteria that couldn't be checked don't count against the database
+    if timed == 0 && defined > 0 {
+        score.cycle_time = 1.0;
+    }
+    if values == 0 && defined > 0 {
+        score.plausibility = 1.0;
+    }
+    score.total = score.coverage
+        * (0.4
+            + 0.2 * score.dlc
+            + 0.2 * score.cycle_time
+            + 0.2 * score.plausibility);
+    score
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::{Message, Signal};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn scoring() {
+        let start = Instant::now();
+        let history = |id: u32, len: usize, period: u64, value: u8| {
+            (0..20u64)
+                .map(|i| Packet {
+                    id,
+                    time: Some(start + Duration::from_millis(i * period)),
+                    bytes: vec![value; len],
+                    ..Default::default()
+                })
+                .collect::<VecDeque<Packet>>()
+        };
+        let traffic = [
+            history(0x100, 8, 10, 10),
+            history(0x200, 4, 100, 200),
+            history(0x300, 8, 20, 0),
+        ];
+
+        let mut level = Signal::new("Level", 0, 8);
+        level.max = 100.0;
+        let message = |id: u32, size: usize, cycle_time: u32| Message {
+            id,
+            name: format!("M{id:X}"),
+            size,
+            cycle_time: Some(cycle_time),
+            signals: vec![level.clone()],
+            ..Default::default()
+        };
+        let good = Database::new(
+            vec![],
+            vec![message(0x100, 8, 10), message(0x200, 4, 100)],
+        );
+        let bad = Database::new(
+            vec![],
+            vec![message(0x100, 4, 50), message(0x400, 8, 10)],
+        );
+
+        let observed = || {
+            traffic.iter().map(|history| Observed {
+                current: history.back().unwrap(),
+                history,
+                period: None,
+            })
+        };
+
+        let scored = score(&good, observed());
+        assert_eq!(scored.coverage, 2.0 / 3.0);
+        assert_eq!(scored.dlc, 1.0);
+        assert_eq!(scored.cycle_time, 1.0);
+        assert_eq!(scored.plausibility, 0.5);
+
+        // decoded messages keep no history, only their rate
+        let none = VecDeque::new();
+        let decoded =
+            traffic
+                .iter()
+                .zip([10, 100, 20])
+                .map(|(h, period)| Observed {
+                    current: h.back().unwrap(),
+                    history: &none,
+                    period: Some(period),
+                });
+        assert_eq!(score(&good, decoded), scored);
+
+        let bad = score(&bad, observed());
+        assert_eq!(bad.coverage, 1.0 / 3.0);
+        assert_eq!(bad.dlc, 0.0);
+        assert_eq!(bad.cycle_time, 0.0);
+        assert!(scored.total > bad.total);
+    }
+}
@@ -0,0 +1,311 @@
+//! PCAN Symbol (`.sym`) database loader
+//!
+//! Symbol files are produced by the PEAK PCAN Symbol Editor/PCAN-Explorer.
+//! Messages are `[Name]` sections holding `ID=`, `Type=`, `DLC=`,
+//! `CycleTime=` and signal (`Var=`/`Sig=`) lines; multiplexed messages
+//! repeat the section once per `Mux=` value.
+
+use super::{
+    flip_bit, ByteOrder, Database, Message, Multiplex, Signal, ValueType,
+};
+use crate::invalid;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(PartialEq)]
+enum Section {
+    None,
+    Enums,
+    Signals,
+    Messages,
+}
+
+pub fn parse(text: &str) -> io::Result<Database> {
+    let mut section = Section::None;
+    // signals declared in {SIGNALS}, placed in messages by `Sig=`
+    let mut shared: HashMap<String, Signal> = HashMap::new();
+    let mut messages: Vec<Message> = vec![];
+    let mut current: Option<usize> = None;
+    let mut mux: Option<u64> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: String| invalid(format!("line {}: {e}", number + 1));
+
+        if line.starts_with('{') {
+            section = match line {
+                "{ENUMS}" => Section::Enums,
+                "{SIGNALS}" => Section::Signals,
+                "{SEND}" | "{RECEIVE}" | "{SENDRECEIVE}" => Section::Messages,
+                _ => return Err(error(format!("unknown section {line}"))),
+            };
+            current = None;
+            continue;
+        }
+
+        if section == Section::Messages && line.starts_with('[') {
+            let name = line.trim_start_matches('[').trim_end_matches(']');
+            // repeated sections (multiplexing) extend the same message
+            current =
+                Some(match messages.iter().position(|m| m.name == name) {
+                    Some(index) => index,
+                    None => {
+                        messages.push(Message {
+                            name: name.to_string(),
+                            size: 8,
+                            ..Default::default()
+                        });
+                        messages.len() - 1
+                    }
+                });
+            mux = None;
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+
+        match section {
+            Section::Signals if key == "Sig" => {
+                let signal = parse_signal(value, false).map_err(error)?;
+                shared.insert(signal.name.clone(), signal);
+            }
+            Section::Messages => {
+                let Some(index) = current else {
+                    continue;
+                };
+                let message = &mut messages[index];
+                match key {
+                    "ID" => {
+                        let id = value.split('-').next().unwrap_or_default();
+                        message.id = parse_int(id).map_err(error)? as u32;
+                    }
+                    "Type" => message.extended = value.contains("Extended"),
+                    "DLC" | "Len" => {
+                        message.size = parse_int(value).map_err(error)? as usize
+                    }
+                    "CycleTime" => {
+                        let time = parse_int(value).map_err(error)? as u32;
+                        message.cycle_time = (time > 0).then_some(time);
+                    }
+                    "Mux" => {
+                        let tokens = tokenize(value);
+                        if tokens.len() < 3 {
+                            return Err(error(format!("invalid mux {value}")));
+                        }
+                        let mut signal = Signal::new(&tokens[0], 0, 0);
+                        place(&mut signal, &tokens[1], &tokens[3..])
+                            .map_err(error)?;
+                        let count = parse_int(&tokens[2]).map_err(error)?;
+                        if message.multiplexor().is_none() {
+                            signal.multiplex = Multiplex::Multiplexor;
+                            message.signals.push(signal);
+                        }
+                        mux = Some(count);
+                    }
+                    "Var" => {
+                        let mut signal =
+                            parse_signal(value, true).map_err(error)?;
+                        if let Some(count) = mux {
+                            signal.multiplex = Multiplex::Multiplexed(count);
+                        }
+                        message.signals.push(signal);
+                    }
+                    "Sig" => {
+                        let tokens = tokenize(value);
+                        let name = tokens.first().cloned().unwrap_or_default();
+                        let Some(mut signal) = shared.get(&name).cloned()
+                        else {
+                            return Err(error(format!(
+                                "unknown signal {name}"
+                            )));
+                        };
+                        let start = tokens.get(1).cloned().unwrap_or_default();
+                        let start = format!("{start},{}", signal.size);
+                        place(
+                            &mut signal,
+                            &start,
+                            &tokens[2.min(tokens.len())..],
+                        )
+                        .map_err(error)?;
+                        if let Some(count) = mux {
+                            signal.multiplex = Multiplex::Multiplexed(count);
+                        }
+                        message.signals.push(signal);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Database::new(vec![], messages))
+}
+
+/// Parse a decimal number, or hexadecimal with an `h` suffix
+fn parse_int(text: &str) -> Result<u64, String> {
+    let result = match text.strip_suffix(['h', 'H']) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+    result.map_err(|_| format!("invalid number {text}"))
+}
+
+/// Split on whitespace, keeping quoted strings together
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Parse `<name> <type> <start>,<size> <flags>` (in messages) or
+/// `<name> <type> <size> <flags>` (in the {SIGNALS} section)
+fn parse_signal(text: &str, positioned: bool) -> Result<Signal, String> {
+    let tokens = tokenize(text);
+    if tokens.len() < 3 {
+        return Err(format!("invalid signal {text}"));
+    }
+    let mut signal = Signal::new(&tokens[0], 0, 0);
+    let value_type = tokens[1].as_str();
+    signal.value_type = match value_type {
+        "signed" => ValueType::Signed,
+        "float" | "double" => ValueType::Float,
+        _ => ValueType::Unsigned,
+    };
+    let layout = if positioned {
+        tokens[2].clone()
+    } else {
+        format!("0,{}", tokens[2])
+    };
+    place(&mut signal, &layout, &tokens[3..])?;
+    match value_type {
+        "bit" => signal.size = 1,
+        "float" => signal.size = 32,
+        "double" => signal.size = 64,
+        _ => {}
+    }
+    Ok(signal)
+}
+
+/// Apply `<start>,<size>` and option flags to a signal
+fn place(
+    signal: &mut Signal,
+    layout: &str,
+    flags: &[String],
+) -> Result<(), String> {
+    let (start, size) = layout
+        .split_once(',')
+        .ok_or_else(|| format!("invalid bit layout {layout}"))?;
+    let start = parse_int(start)? as usize;
+    signal.size = parse_int(size)? as usize;
+
+    for flag in flags {
+        let number = |s: &str| {
+            s.parse::<f64>()
+                .map_err(|_| format!("invalid number in {flag}"))
+        };
+        if flag == "-m" {
+            signal.byte_order = ByteOrder::BigEndian;
+        } else if let Some(unit) = flag.strip_prefix("/u:") {
+            signal.unit = unit.to_string();
+        } else if let Some(factor) = flag.strip_prefix("/f:") {
+            signal.factor = number(factor)?;
+        } else if let Some(offset) = flag.strip_prefix("/o:") {
+            signal.offset = number(offset)?;
+        } else if let Some(min) = flag.strip_prefix("/min:") {
+            signal.min = number(min)?;
+        } else if let Some(max) = flag.strip_prefix("/max:") {
+            signal.max = number(max)?;
+        }
+    }
+
+    // Motorola signals are positioned by their MSB in transmit order
+    signal.start_bit = match signal.byte_order {
+        ByteOrder::LittleEndian => start,
+        ByteOrder::BigEndian => flip_bit(start),
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn symbols() {
+        let sym = r#"FormatVersion=6.0 // Do not edit this line!
+Title="Test"
+
+{ENUMS}
+enum OnOff(0="Off", 1="On")
+
+{SIGNALS}
+Sig=Temperature signed 8 /u:C /o:-40
+
+{SENDRECEIVE}
+
+[EngineData]
+ID=0A0h
+DLC=8
+CycleTime=100
+Var=Rpm unsigned 0,16 /u:rpm /f:0.25 /max:16383.75
+Sig=Temperature 16
+Var=Pressure unsigned 24,16 -m /u:"k Pa"
+Var=Running bit 40,1 /e:OnOff
+
+[Diag]
+ID=18FEF100h
+Type=Extended
+DLC=3
+Mux=Voltage 0,8 1
+Var=Voltage unsigned 8,8 /u:V
+
+[Diag]
+DLC=3
+Mux=Current 0,8 2
+Var=Current unsigned 8,16 /u:A
+"#;
+        let db = parse(sym).unwrap();
+        assert_eq!(db.messages().len(), 2);
+
+        let engine = db.message(0xa0).unwrap();
+        assert_eq!(engine.name, "EngineData");
+        assert_eq!(engine.cycle_time, Some(100));
+        assert_eq!(engine.signals.len(), 4);
+        let data = [0x10, 0x00, 0x50, 0x01, 0x02, 0x01];
+        assert_eq!(engine.signals[0].value(&data), Some(4.0));
+        assert_eq!(engine.signals[1].name, "Temperature");
+        assert_eq!(engine.signals[1].value(&data), Some(40.0));
+        assert_eq!(engine.signals[2].unit, "k Pa");
+        assert_eq!(engine.signals[2].value(&data), Some(258.0));
+        assert_eq!(engine.signals[3].size, 1);
+
+        let diag = db.message(0x18fef100).unwrap();
+        assert!(diag.extended);
+        assert_eq!(diag.size, 3);
+        assert_eq!(diag.signals.len(), 3);
+        assert_eq!(diag.signals[0].multiplex, Multiplex::Multiplexor);
+        assert_eq!(diag.signals[2].multiplex, Multiplex::Multiplexed(2));
+    }
+}
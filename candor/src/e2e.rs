@@ -0,0 +1,482 @@
+//! End-to-end protection (CRC and alive counter) validation
+//!
+//! Supports the AUTOSAR E2E profiles 1, 2, 5 and 11 and plain SAE J1850
+//! CRC8 checksums.  Protections are configured per message ID, either in
+//! a configuration file or through DBC message attributes (see
+//! [`crate::db::dbc`]).
+//!
+//! Configuration files hold one message per line as `<ID> <profile>
+//! [<key>=<value>...]` with hexadecimal IDs, a profile of `p1`, `p2`, `p5`,
+//! `p11` or `j1850` and the keys:
+//!
+//! - `data_id`: data ID (profiles 1, 5 and 11)
+//! - `data_ids`: 16 comma separated data IDs (profile 2)
+//! - `mode`: data ID mode `both`, `alt`, `low` or `nibble` (profiles 1
+//!   and 11)
+//! - `crc`: bit offset of the CRC
+//! - `counter`: bit offset and size of the counter as `<offset>[:<size>]`
+//!
+//! ```text
+//! 123 p1 data_id=0x123 mode=both
+//! 1a0 p5 data_id=0x1a0
+//! 200 j1850 crc=56 counter=48:4
+//! ```
+
+use crate::invalid;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Profile {
+    P1,
+    P2,
+    P5,
+    P11,
+    /// SAE J1850 CRC8 over the payload, with an optional counter
+    J1850,
+}
+
+impl Profile {
+    pub fn parse(text: &str) -> Option<Self> {
+        let profile = match text.to_ascii_lowercase().as_str() {
+            "1" | "p1" | "p01" | "profile1" => Self::P1,
+            "2" | "p2" | "p02" | "profile2" => Self::P2,
+            "5" | "p5" | "p05" | "profile5" => Self::P5,
+            "11" | "p11" | "profile11" => Self::P11,
+            "j1850" | "crc8" => Self::J1850,
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::P1 => "P01",
+            Self::P2 => "P02",
+            Self::P5 => "P05",
+            Self::P11 => "P11",
+            Self::J1850 => "J1850",
+        }
+    }
+}
+
+/// How the 16-bit data ID enters the CRC (profiles 1 and 11)
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataIdMode {
+    #[default]
+    Both,
+    /// Low byte for even, high byte for odd counter values
+    Alternating,
+    Low,
+    /// Low byte in the CRC, high nibble sent next to the counter
+    Nibble,
+}
+
+/// Protection of a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Protection {
+    pub profile: Profile,
+    pub data_id: u16,
+    /// Data IDs by counter value (profile 2)
+    pub data_ids: [u8; 16],
+    pub mode: DataIdMode,
+    /// Bit offset of the CRC, which must be byte aligned
+    pub crc_offset: usize,
+    pub counter_offset: usize,
+    /// Counter size in bits, 0 for none
+    pub counter_size: usize,
+}
+
+impl Protection {
+    /// Protection with the profile's default layout
+    pub fn new(profile: Profile) -> Self {
+        let (counter_offset, counter_size) = match profile {
+            Profile::P1 | Profile::P2 | Profile::P11 => (8, 4),
+            Profile::P5 => (16, 8),
+            Profile::J1850 => (0, 0),
+        };
+        Self {
+            profile,
+            data_id: 0,
+            data_ids: [0; 16],
+            mode: DataIdMode::Both,
+            crc_offset: 0,
+            counter_offset,
+            counter_size,
+        }
+    }
+
+    pub(crate) fn crc_size(&self) -> usize {
+        match self.profile {
+            Profile::P5 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Number of distinct counter values
+    fn counter_modulo(&self) -> u16 {
+        match self.profile {
+            // 15 is reserved as invalid
+            Profile::P1 | Profile::P11 => 15,
+            _ => 1 << self.counter_size,
+        }
+    }
+
+    pub fn counter(&self, bytes: &[u8]) -> Option<u8> {
+        if self.counter_size == 0 {
+            return None;
+        }
+        let byte = bytes.get(self.counter_offset / 8)?;
+        let mask = ((1u16 << self.counter_size) - 1) as u8;
+        Some((byte >> (self.counter_offset % 8)) & mask)
+    }
+
+    /// Transmitted CRC
+    fn crc(&self, bytes: &[u8]) -> Option<u16> {
+        let start = self.crc_offset / 8;
+        match bytes.get(start..start + self.crc_size())? {
+            [crc] => Some(*crc as u16),
+            [low, high] => Some(u16::from_le_bytes([*low, *high])),
+            _ => None,
+        }
+    }
+
+    /// CRC computed over the payload
+    pub fn compute(&self, bytes: &[u8]) -> Option<u16> {
+        let start = self.crc_offset / 8;
+        let end = start + self.crc_size();
+        let before = bytes.get(..start)?;
+        let after = bytes.get(end..)?;
+        let counter = self.counter(bytes).unwrap_or_default();
+        let [id_low, id_high] = self.data_id.to_le_bytes();
+
+        let crc = match self.profile {
+            Profile::P1 | Profile::P11 => {
+                let id: &[u8] = match self.mode {
+                    DataIdMode::Both => &[id_low, id_high],
+                    DataIdMode::Alternating if counter.is_multiple_of(2) => {
+                        &[id_low]
+                    }
+                    DataIdMode::Alternating => &[id_high],
+                    DataIdMode::Low => &[id_low],
+                    DataIdMode::Nibble => &[id_low, 0],
+                };
+                // chained library calls cancel out the start and final XOR
+                let crc = crc8(J1850, 0x00, id);
+                let crc = crc8(J1850, crc, before);
+                crc8(J1850, crc, after) as u16
+            }
+            Profile::P2 => {
+                let id = self.data_ids[counter as usize & 0xf];
+                let crc = crc8(H2F, 0xff, before);
+                let crc = crc8(H2F, crc, after);
+                crc8(H2F, crc, &[id]) as u16 ^ 0xff
+            }
+            Profile::P5 => {
+                let crc = crc16(0xffff, before);
+                let crc = crc16(crc, after);
+                crc16(crc, &[id_low, id_high])
+            }
+            Profile::J1850 => {
+                let crc = crc8(J1850, 0xff, before);
+                crc8(J1850, crc, after) as u16 ^ 0xff
+            }
+        };
+        Some(crc)
+    }
+
+    /// Whether the CRC (and, in nibble mode, the data ID nibble) is valid
+    pub fn crc_valid(&self, bytes: &[u8]) -> bool {
+        if self.mode == DataIdMode::Nibble
+            && matches!(self.profile, Profile::P1 | Profile::P11)
+        {
+            let nibble = bytes.get(1).map(|b| b >> 4);
+            if nibble != Some((self.data_id >> 8) as u8 & 0xf) {
+                return false;
+            }
+        }
+        match (self.crc(bytes), self.compute(bytes)) {
+            (Some(crc), Some(computed)) => crc == computed,
+            _ => false,
+        }
+    }
+}
+
+/// SAE J1850 polynomial
+pub(crate) const J1850: u8 = 0x1d;
+/// AUTOSAR CRC8H2F polynomial
+pub(crate) const H2F: u8 = 0x2f;
+
+/// MSB-first CRC8 continuing from `crc` (without final XOR)
+pub(crate) fn crc8(poly: u8, mut crc: u8, data: &[u8]) -> u8 {
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ poly
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-16/CCITT-FALSE continuing from `crc`
+fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Validation results of a message
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Status {
+    /// Number of packets checked
+    pub checked: usize,
+    pub crc_errors: usize,
+    /// Counter value repeated
+    pub repeated: usize,
+    /// Counter jumps, i.e. packets lost
+    pub lost: usize,
+    last_counter: Option<u8>,
+}
+
+impl Status {
+    pub fn counter_errors(&self) -> usize {
+        self.repeated + self.lost
+    }
+
+    pub fn errors(&self) -> usize {
+        self.crc_errors + self.counter_errors()
+    }
+
+    /// Check a packet's payload against its protection
+    pub fn check(&mut self, protection: &Protection, bytes: &[u8]) {
+        self.checked += 1;
+        if !protection.crc_valid(bytes) {
+            self.crc_errors += 1;
+        }
+
+        let Some(counter) = protection.counter(bytes) else {
+            return;
+        };
+        let modulo = protection.counter_modulo();
+        if let Some(last) = self.last_counter {
+            let delta =
+                (counter as u16 + modulo - last as u16 % modulo) % modulo;
+            match delta {
+                0 => self.repeated += 1,
+                1 => {}
+                _ => self.lost += 1,
+            }
+        }
+        self.last_counter = Some(counter);
+    }
+
+    pub fn text(&self, protection: &Protection) -> String {
+        format!(
+            "E2E {}: {} checked, {} CRC errors, {} repeated, {} lost",
+            protection.profile.name(),
+            self.checked,
+            self.crc_errors,
+            self.repeated,
+            self.lost
+        )
+    }
+}
+
+fn number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Protections by message ID
+pub type Protections = BTreeMap<u32, Protection>;
+
+pub fn from_file(filename: &str) -> io::Result<Protections> {
+    parse(&fs::read_to_string(filename)?)
+}
+
+/// Parse a protection configuration
+pub fn parse(text: &str) -> io::Result<Protections> {
+    let mut protections = Protections::new();
+    for (number_, line) in text.lines().enumerate() {
+        let error = |e: &str| invalid(format!("line {}: {e}", number_ + 1));
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let id = tokens.next().unwrap_or_default();
+        let id = u32::from_str_radix(id.trim_start_matches("0x"), 16)
+            .map_err(|_| error("invalid ID"))?;
+        let profile = tokens
+            .next()
+            .and_then(Profile::parse)
+            .ok_or_else(|| error("invalid profile"))?;
+        let mut protection = Protection::new(profile);
+
+        for token in tokens {
+            let (key, value) =
+                token.split_once('=').ok_or_else(|| error(token))?;
+            let value_error = || error(&format!("invalid {key}"));
+            match key {
+                "data_id" => {
+                    protection.data_id =
+                        number(value).ok_or_else(value_error)? as u16;
+                }
+                "data_ids" => {
+                    for (index, id) in value.split(',').enumerate().take(16) {
+                        protection.data_ids[index] =
+                            number(id).ok_or_else(value_error)? as u8;
+                    }
+                }
+                "mode" => {
+                    protection.mode = match value {
+                        "both" => DataIdMode::Both,
+                        "alt" => DataIdMode::Alternating,
+                        "low" => DataIdMode::Low,
+                        "nibble" => DataIdMode::Nibble,
+                        _ => return Err(value_error()),
+                    };
+                }
+                "crc" => {
+                    protection.crc_offset =
+                        number(value).ok_or_else(value_error)? as usize;
+                }
+                "counter" => {
+                    let (offset, size) = match value.split_once(':') {
+                        Some((offset, size)) => (offset, Some(size)),
+                        None => (value, None),
+                    };
+                    protection.counter_offset =
+                        number(offset).ok_or_else(value_error)? as usize;
+                    if let Some(size) = size {
+                        protection.counter_size =
+                            number(size).ok_or_else(value_error)? as usize;
+                    } else if protection.counter_size == 0 {
+                        protection.counter_size = 4;
+                    }
+                }
+                _ => return Err(error(&format!("unknown key {key}"))),
+            }
+        }
+        if !protection.crc_offset.is_multiple_of(8)
+            || protection.counter_size > 8
+        {
+            return Err(error("unsupported CRC or counter layout"));
+        }
+        protections.insert(id, protection);
+    }
+    Ok(protections)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn algorithms() {
+        // standard check values
+        let data = b"123456789";
+        assert_eq!(crc8(J1850, 0xff, data) ^ 0xff, 0x4b);
+        assert_eq!(crc8(H2F, 0xff, data) ^ 0xff, 0xdf);
+        assert_eq!(crc16(0xffff, data), 0x29b1);
+
+        // examples of the AUTOSAR CRC library specification
+        let examples: [(&[u8], u8, u8, u16); 7] = [
+            (&[0x00, 0x00, 0x00, 0x00], 0x59, 0x12, 0x84c0),
+            (&[0xf2, 0x01, 0x83], 0x37, 0xc2, 0xd374),
+            (&[0x0f, 0xaa, 0x00, 0x55], 0x79, 0xc6, 0x2023),
+            (&[0x00, 0xff, 0x55, 0x11], 0xb8, 0x77, 0xb8f9),
+            (
+                &[0x33, 0x22, 0x55, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff],
+                0xcb,
+                0x11,
+                0xf53f,
+            ),
+            (&[0x92, 0x6b, 0x55], 0x8c, 0x33, 0x0745),
+            (&[0xff, 0xff, 0xff, 0xff], 0x74, 0x6c, 0x1d0f),
+        ];
+        for (data, j1850, h2f, ccitt) in examples {
+            assert_eq!(crc8(J1850, 0xff, data) ^ 0xff, j1850);
+            assert_eq!(crc8(H2F, 0xff, data) ^ 0xff, h2f);
+            assert_eq!(crc16(0xffff, data), ccitt);
+        }
+    }
+
+    #[test]
+    fn known_answers() {
+        let crc = |protection: &Protection, counter: u8| {
+            let mut bytes = [0u8; 8];
+            let offset = protection.counter_offset;
+            bytes[offset / 8] |= counter << (offset % 8);
+            if protection.mode == DataIdMode::Nibble {
+                bytes[1] |= ((protection.data_id >> 8) as u8) << 4;
+            }
+            protection.compute(&bytes).unwrap()
+        };
+
+        // examples of the AUTOSAR E2E protocol specification
+        let mut p1 = Protection::new(Profile::P1);
+        p1.data_id = 0x123;
+        assert_eq!(crc(&p1, 0), 0xcc);
+        assert_eq!(crc(&p1, 1), 0x91);
+        let mut p5 = Protection::new(Profile::P5);
+        p5.data_id = 0x1234;
+        assert_eq!(crc(&p5, 0), 0xca1c);
+
+        // profile 11 uses the CRC of profile 1
+        let mut p11 = Protection::new(Profile::P11);
+        p11.data_id = 0x123;
+        assert_eq!(crc(&p11, 0), 0xcc);
+        p11.mode = DataIdMode::Nibble;
+        assert_eq!(crc(&p11, 0), 0x2a);
+        assert_eq!(crc(&p11, 1), 0x77);
+
+        let mut p2 = Protection::new(Profile::P2);
+        p2.data_ids = std::array::from_fn(|i| i as u8 + 1);
+        assert_eq!(crc(&p2, 0), 0x0e);
+        assert_eq!(crc(&p2, 1), 0x1b);
+    }
+
+    #[test]
+    fn profiles() {
+        let config = "123 p1 data_id=0x123\n\
+                      1a0 p5 data_id=0x1a0\n\
+                      200 j1850 crc=56 counter=48:4\n";
+        let protections = parse(config).unwrap();
+
+        for (id, protection) in protections.iter() {
+            let mut bytes = vec![0u8, 0, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+            let mut status = Status::default();
+            for counter in [0u8, 1, 2, 4, 4] {
+                let offset = protection.counter_offset;
+                bytes[offset / 8] = counter << (offset % 8);
+                let crc = protection.compute(&bytes).unwrap().to_le_bytes();
+                let start = protection.crc_offset / 8;
+                let size = protection.crc_size();
+                bytes[start..start + size].copy_from_slice(&crc[..size]);
+                status.check(protection, &bytes);
+            }
+            assert_eq!(status.crc_errors, 0, "{id:x}");
+            assert_eq!((status.lost, status.repeated), (1, 1), "{id:x}");
+
+            bytes[3] ^= 0x01;
+            status.check(protection, &bytes);
+            assert_eq!(status.crc_errors, 1, "{id:x}");
+        }
+    }
+}
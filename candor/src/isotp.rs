@@ -0,0 +1,383 @@
+//! ISO 15765-2 (ISO-TP) transport reassembly
+//!
+//! Passively follows the single, first, consecutive and flow control
+//! frames exchanged on configured request/response ID pairs and produces
+//! the complete PDUs, along with any protocol errors seen on the way.
+
+use crate::Packet;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Maximum time between consecutive frames (N_Cr)
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Addressing {
+    /// Protocol control information starts at the first data byte
+    #[default]
+    Normal,
+    /// First data byte holds the target address
+    Extended,
+    /// First data byte holds the address extension
+    Mixed,
+}
+
+/// Request/response identifiers of a diagnostic connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pair {
+    pub request: u32,
+    pub response: u32,
+    pub addressing: Addressing,
+}
+
+/// Reassembled transport PDU
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pdu {
+    pub source: usize,
+    pub time: Option<Instant>,
+    /// CAN ID the PDU was sent on
+    pub id: u32,
+    /// Target address/address extension for extended/mixed addressing
+    pub address: Option<u8>,
+    /// Index of the ID pair the PDU was sent on
+    pub pair: usize,
+    /// Whether the PDU was sent on the request ID of its pair
+    pub request: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Consecutive frame sequence number other than the expected one
+    SequenceGap { expected: u8, received: u8 },
+    /// Consecutive frame without a transfer in progress
+    UnexpectedConsecutive,
+    /// New transfer started before the previous one completed
+    Interrupted,
+    /// No consecutive frame received in time
+    Timeout,
+    /// Receiver reported a buffer overflow/abort in its flow control
+    Overflow,
+    /// Frame too short or with an invalid length
+    InvalidFrame,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SequenceGap { expected, received } => write!(
+                f,
+                "sequence gap (expected {expected:X}, got {received:X})"
+            ),
+            Error::UnexpectedConsecutive => write!(f, "unexpected CF"),
+            Error::Interrupted => write!(f, "transfer interrupted"),
+            Error::Timeout => write!(f, "timeout"),
+            Error::Overflow => write!(f, "receiver overflow"),
+            Error::InvalidFrame => write!(f, "invalid frame"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Pdu(Pdu),
+    Error {
+        source: usize,
+        time: Option<Instant>,
+        id: u32,
+        error: Error,
+    },
+}
+
+/// Multi-frame transfer in progress
+#[derive(Clone)]
+struct Transfer {
+    source: usize,
+    size: usize,
+    next: u8,
+    data: Vec<u8>,
+    time: Option<Instant>,
+}
+
+/// Reassembles ISO-TP transfers on a set of ID pairs
+#[derive(Default, Clone)]
+pub struct Decoder {
+    pairs: Vec<Pair>,
+    /// Transfers in progress by (CAN ID, address byte)
+    transfers: HashMap<(u32, Option<u8>), Transfer>,
+}
+
+impl Decoder {
+    pub fn new(pairs: Vec<Pair>) -> Self {
+        Self {
+            pairs,
+            ..Default::default()
+        }
+    }
+
+    pub fn pairs(&self) -> &Vec<Pair> {
+        &self.pairs
+    }
+
+    /// Process a packet, returning completed PDUs and protocol errors
+    pub fn process(&mut self, packet: &Packet) -> Vec<Event> {
+        let mut events = vec![];
+        let Some((index, pair)) =
+            self.pairs.iter().enumerate().find(|(_, p)| {
+                p.request == packet.id || p.response == packet.id
+            })
+        else {
+            return events;
+        };
+        let request = pair.request == packet.id;
+
+        let bytes = packet.bytes.as_slice();
+        let (address, bytes) = match (pair.addressing, bytes.split_first()) {
+            (Addressing::Normal, _) => (None, bytes),
+            (_, Some((address, rest))) => (Some(*address), rest),
+            (_, None) => (None, bytes),
+        };
+        let key = (packet.id, address);
+
+        let error = |error| Event::Error {
+            source: packet.source,
+            time: packet.time,
+            id: packet.id,
+            error,
+        };
+        let pdu = |data: Vec<u8>| {
+            Event::Pdu(Pdu {
+                source: packet.source,
+                time: packet.time,
+                id: packet.id,
+                address,
+                pair: index,
+                request,
+                data,
+            })
+        };
+
+        let Some(pci) = bytes.first() else {
+            events.push(error(Error::InvalidFrame));
+            return events;
+        };
+        match pci >> 4 {
+            // single frame
+            0 => {
+                if self.transfers.remove(&key).is_some() {
+                    events.push(error(Error::Interrupted));
+                }
+                let (size, data) = match pci & 0xf {
+                    // CAN FD: length in the following byte
+                    0 if bytes.len() > 8 => {
+                        (bytes[1] as usize, bytes.get(2..).unwrap_or(&[]))
+                    }
+                    size => (size as usize, &bytes[1..]),
+                };
+                if size == 0 || size > data.len() {
+                    events.push(error(Error::InvalidFrame));
+                } else {
+                    events.push(pdu(data[..size].to_vec()));
+                }
+            }
+            // first frame
+            1 => {
+                let size = match bytes.get(1) {
+                    Some(low) => ((*pci as usize & 0xf) << 8) | *low as usize,
+                    None => 0,
+                };
+                let (size, data) = match (size, bytes.get(2..6)) {
+                    // lengths over 4095 use a 32-bit escape
+                    (0, Some(escape)) => (
+                        u32::from_be_bytes(escape.try_into().unwrap()) as usize,
+                        &bytes[6..],
+                    ),
+                    (0, None) => {
+                        events.push(error(Error::InvalidFrame));
+                        return events;
+                    }
+                    (size, _) => (size, &bytes[2..]),
+                };
+                let transfer = Transfer {
+                    source: packet.source,
+                    size,
+                    next: 1,
+                    data: data.to_vec(),
+                    time: packet.time,
+                };
+                if self.transfers.insert(key, transfer).is_some() {
+                    events.push(error(Error::Interrupted));
+                }
+            }
+            // consecutive frame
+            2 => {
+                let Some(transfer) = self.transfers.get_mut(&key) else {
+                    events.push(error(Error::UnexpectedConsecutive));
+                    return events;
+                };
+                let expired = match (transfer.time, packet.time) {
+                    (Some(last), Some(now)) => now - last > TIMEOUT,
+                    _ => false,
+                };
+                let received = pci & 0xf;
+                if expired || received != transfer.next {
+                    let expected = transfer.next;
+                    self.transfers.remove(&key);
+                    events.push(error(if expired {
+                        Error::Timeout
+                    } else {
+                        Error::SequenceGap { expected, received }
+                    }));
+                    return events;
+                }
+                transfer.next = (transfer.next + 1) & 0xf;
+                transfer.time = packet.time;
+                transfer.data.extend_from_slice(&bytes[1..]);
+                if transfer.data.len() >= transfer.size {
+                    if let Some(mut transfer) = self.transfers.remove(&key) {
+                        transfer.data.truncate(transfer.size);
+                        events.push(pdu(transfer.data));
+                    }
+                }
+            }
+            // flow control, sent on the opposite ID of the transfer
+            3 => {
+                if pci & 0xf == 2 {
+                    let other =
+                        if request { pair.response } else { pair.request };
+                    self.transfers.remove(&(other, address));
+                    events.push(error(Error::Overflow));
+                }
+            }
+            _ => events.push(error(Error::InvalidFrame)),
+        }
+        events
+    }
+
+    /// Drop transfers that have not progressed in time, reporting them
+    pub fn expire(&mut self, now: Instant) -> Vec<Event> {
+        let mut events = vec![];
+        self.transfers.retain(|(id, _), transfer| {
+            let expired = transfer.time.is_some_and(|t| now - t > TIMEOUT);
+            if expired {
+                events.push(Event::Error {
+                    source: transfer.source,
+                    time: Some(now),
+                    id: *id,
+                    error: Error::Timeout,
+                });
+            }
+            !expired
+        });
+        events
+    }
+}
+
+impl Pair {
+    /// Parse a `<request>:<response>[:ext|:mixed]` specifier with
+    /// hexadecimal IDs
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.split(':');
+        let mut id = || {
+            let id = parts.next().unwrap_or_default();
+            u32::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid ISO-TP ID in {text}"))
+        };
+        let request = id()?;
+        let response = id()?;
+        let addressing = match parts.next() {
+            None | Some("normal") => Addressing::Normal,
+            Some("ext") | Some("extended") => Addressing::Extended,
+            Some("mixed") => Addressing::Mixed,
+            Some(other) => {
+                return Err(format!("Invalid ISO-TP addressing {other}"))
+            }
+        };
+        Ok(Self {
+            request,
+            response,
+            addressing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn data(events: Vec<Event>) -> Vec<u8> {
+        match events.as_slice() {
+            [Event::Pdu(pdu)] => pdu.data.clone(),
+            other => panic!("expected PDU, got {other:?}"),
+        }
+    }
+
+    fn error(events: Vec<Event>) -> Error {
+        match events.as_slice() {
+            [Event::Error { error, .. }] => error.clone(),
+            other => panic!("expected error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn normal_addressing() {
+        let mut isotp = Decoder::new(vec![Pair::parse("7e0:7e8").unwrap()]);
+        let sf = Packet::new(0x7e0, &[0x02, 0x10, 0x03, 0, 0, 0, 0, 0]);
+        assert_eq!(data(isotp.process(&sf)), vec![0x10, 0x03]);
+
+        let ff = Packet::new(0x7e8, &[0x10, 0x0a, 0x62, 0xf1, 0x90, 1, 2, 3]);
+        assert!(isotp.process(&ff).is_empty());
+        let fc = Packet::new(0x7e0, &[0x30, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(isotp.process(&fc).is_empty());
+        let cf = Packet::new(0x7e8, &[0x21, 4, 5, 6, 7, 0xaa, 0xaa, 0xaa]);
+        assert_eq!(
+            data(isotp.process(&cf)),
+            vec![0x62, 0xf1, 0x90, 1, 2, 3, 4, 5, 6, 7]
+        );
+
+        // packets on other IDs are ignored
+        assert!(isotp.process(&Packet::new(0x123, &[0x02, 1, 2])).is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let mut isotp = Decoder::new(vec![Pair::parse("7e0:7e8").unwrap()]);
+        let cf = Packet::new(0x7e8, &[0x21, 4, 5, 6, 7, 0xaa, 0xaa, 0xaa]);
+        assert_eq!(error(isotp.process(&cf)), Error::UnexpectedConsecutive);
+
+        let ff = Packet::new(0x7e8, &[0x10, 0x14, 0x62, 0xf1, 0x90, 1, 2, 3]);
+        assert!(isotp.process(&ff).is_empty());
+        let cf = Packet::new(0x7e8, &[0x22, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            error(isotp.process(&cf)),
+            Error::SequenceGap {
+                expected: 1,
+                received: 2
+            }
+        );
+
+        let mut ff =
+            Packet::new(0x7e8, &[0x10, 0x14, 0x62, 0xf1, 0x90, 1, 2, 3]);
+        ff.time = Some(Instant::now());
+        assert!(isotp.process(&ff).is_empty());
+        let events = isotp.expire(Instant::now() + Duration::from_secs(2));
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn extended_fd() {
+        let mut isotp = Decoder::new(vec![Pair::parse("6f1:6f2:ext").unwrap()]);
+        let mut bytes = vec![0xf1, 0x00, 20];
+        bytes.extend(0..20u8);
+        bytes.resize(24, 0xcc);
+        match isotp.process(&Packet::new(0x6f1, &bytes)).as_slice() {
+            [Event::Pdu(pdu)] => {
+                assert_eq!(pdu.address, Some(0xf1));
+                assert!(pdu.request);
+                assert_eq!(pdu.data, (0..20u8).collect::<Vec<_>>());
+            }
+            other => panic!("expected PDU, got {other:?}"),
+        }
+    }
+}
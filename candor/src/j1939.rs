@@ -0,0 +1,257 @@
+//! SAE J1939 identifier decoding and transport protocol reassembly
+
+use crate::Packet;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Transport protocol connection management PGN (TP.CM)
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// Transport protocol data transfer PGN (TP.DT)
+pub const PGN_TP_DT: u32 = 0xEB00;
+
+/// Global (broadcast) destination address
+pub const GLOBAL: u8 = 0xFF;
+
+/// Maximum time between transport protocol packets (T1)
+const TIMEOUT: Duration = Duration::from_millis(750);
+
+/// Fields of a 29-bit J1939 identifier
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Id {
+    pub priority: u8,
+    /// Parameter group number, with the destination (PDU1 format) removed
+    pub pgn: u32,
+    pub source: u8,
+    /// Destination address for PDU1 (peer-to-peer) parameter groups
+    pub destination: Option<u8>,
+}
+
+impl Id {
+    pub fn from_id(id: u32) -> Self {
+        let pf = (id >> 16) & 0xff;
+        let ps = ((id >> 8) & 0xff) as u8;
+        let (pgn, destination) = if pf < 240 {
+            ((id >> 8) & 0x3ff00, Some(ps))
+        } else {
+            ((id >> 8) & 0x3ffff, None)
+        };
+        Self {
+            priority: ((id >> 26) & 7) as u8,
+            pgn,
+            source: (id & 0xff) as u8,
+            destination,
+        }
+    }
+
+    pub fn to_id(&self) -> u32 {
+        let destination = self.destination.unwrap_or(0) as u32;
+        ((self.priority as u32 & 7) << 26)
+            | ((self.pgn | destination) << 8)
+            | self.source as u32
+    }
+
+    /// Short description of the addressing, e.g. `PGN FEF1 SA 00`
+    pub fn text(&self) -> String {
+        match self.destination {
+            Some(da) => format!(
+                "PGN {:04X} SA {:02X} DA {:02X}",
+                self.pgn, self.source, da
+            ),
+            None => format!("PGN {:04X} SA {:02X}", self.pgn, self.source),
+        }
+    }
+}
+
+/// Name of commonly used parameter groups
+pub fn pgn_name(pgn: u32) -> Option<&'static str> {
+    let name = match pgn {
+        0xE800 => "ACKM",
+        0xEA00 => "RQST",
+        0xEB00 => "TP.DT",
+        0xEC00 => "TP.CM",
+        0xEE00 => "Address Claimed",
+        0xF003 => "EEC2",
+        0xF004 => "EEC1",
+        0xF005 => "ETC2",
+        0xFEC1 => "VDHR",
+        0xFECA => "DM1",
+        0xFECB => "DM2",
+        0xFEDA => "SOFT",
+        0xFEE5 => "HOURS",
+        0xFEE6 => "TD",
+        0xFEE9 => "LFC",
+        0xFEEC => "VI",
+        0xFEEE => "ET1",
+        0xFEEF => "EFL/P1",
+        0xFEF1 => "CCVS1",
+        0xFEF2 => "LFE1",
+        0xFEF5 => "AMB",
+        0xFEF6 => "IC1",
+        0xFEF7 => "VEP1",
+        0xFEFC => "DD1",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Transport protocol session (BAM or connection mode)
+struct Session {
+    pgn: u32,
+    size: usize,
+    packets: u8,
+    next: u8,
+    data: Vec<u8>,
+    time: Option<Instant>,
+}
+
+/// Reassembles multi-packet transfers (BAM and CMDT) into complete
+/// parameter group payloads
+#[derive(Default)]
+pub struct Transport {
+    /// Open sessions by (source, destination)
+    sessions: HashMap<(u8, u8), Session>,
+}
+
+impl Clone for Transport {
+    fn clone(&self) -> Self {
+        // sessions in progress are not carried over
+        Self::default()
+    }
+}
+
+impl Transport {
+    /// Process a packet, returning the reassembled parameter group once a
+    /// transfer completes
+    pub fn process(&mut self, packet: &Packet) -> Option<Packet> {
+        if !packet.extended || packet.bytes.len() < 8 {
+            return None;
+        }
+        let id = Id::from_id(packet.id);
+        let bytes = &packet.bytes;
+        let key = (id.source, id.destination.unwrap_or(GLOBAL));
+
+        match id.pgn {
+            PGN_TP_CM => match bytes[0] {
+                // RTS and BAM open a session
+                16 | 32 => {
+                    let pgn = bytes[5] as u32
+                        | (bytes[6] as u32) << 8
+                        | (bytes[7] as u32) << 16;
+                    let size = bytes[1] as usize | (bytes[2] as usize) << 8;
+                    self.sessions.insert(
+                        key,
+                        Session {
+                            pgn,
+                            size,
+                            packets: bytes[3],
+                            next: 1,
+                            data: Vec::with_capacity(size),
+                            time: packet.time,
+                        },
+                    );
+                    None
+                }
+                // connection abort
+                255 => {
+                    self.sessions.remove(&key);
+                    // aborts may be sent by either side of the connection
+                    self.sessions.remove(&(key.1, key.0));
+                    None
+                }
+                _ => None,
+            },
+            PGN_TP_DT => {
+                let session = self.sessions.get_mut(&key)?;
+                let expired = match (session.time, packet.time) {
+                    (Some(last), Some(now)) => now - last > TIMEOUT,
+                    _ => false,
+                };
+                if expired || bytes[0] != session.next {
+                    self.sessions.remove(&key);
+                    return None;
+                }
+                session.next = session.next.wrapping_add(1);
+                session.time = packet.time;
+                session.data.extend_from_slice(&bytes[1..8]);
+
+                if bytes[0] < session.packets {
+                    return None;
+                }
+                let mut session = self.sessions.remove(&key)?;
+                session.data.truncate(session.size);
+                let mut pgn_id = Id::from_id(
+                    (id.priority as u32) << 26
+                        | (session.pgn & 0x3ffff) << 8
+                        | id.source as u32,
+                );
+                if pgn_id.destination.is_some() {
+                    pgn_id.destination = Some(key.1);
+                }
+                Some(Packet {
+                    source: packet.source,
+                    time: packet.time,
+                    extended: true,
+                    id: pgn_id.to_id(),
+                    bytes: session.data,
+                    ..Default::default()
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identifiers() {
+        let id = Id::from_id(0x18FEF100);
+        assert_eq!(id.priority, 6);
+        assert_eq!(id.pgn, 0xFEF1);
+        assert_eq!(id.source, 0x00);
+        assert_eq!(id.destination, None);
+        assert_eq!(id.to_id(), 0x18FEF100);
+
+        let id = Id::from_id(0x18EA0021);
+        assert_eq!(id.pgn, 0xEA00);
+        assert_eq!(id.source, 0x21);
+        assert_eq!(id.destination, Some(0x00));
+        assert_eq!(id.to_id(), 0x18EA0021);
+    }
+
+    #[test]
+    fn broadcast() {
+        let mut tp = Transport::default();
+        let cm = [32, 10, 0, 2, 0xff, 0xca, 0xfe, 0x00];
+        assert!(tp.process(&Packet::new(0x1CECFF00, &cm)).is_none());
+        let dt = [1, 1, 2, 3, 4, 5, 6, 7];
+        assert!(tp.process(&Packet::new(0x1CEBFF00, &dt)).is_none());
+        let dt = [2, 8, 9, 10, 0xff, 0xff, 0xff, 0xff];
+        let pg = tp.process(&Packet::new(0x1CEBFF00, &dt)).unwrap();
+        assert_eq!(Id::from_id(pg.id).pgn, 0xFECA);
+        assert_eq!(pg.bytes, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn connection_mode() {
+        let mut tp = Transport::default();
+        let rts = [16, 9, 0, 2, 2, 0x00, 0xda, 0x00];
+        assert!(tp.process(&Packet::new(0x1CEC0300, &rts)).is_none());
+        // sequence gap drops the session
+        let dt = [2, 1, 2, 3, 4, 5, 6, 7];
+        assert!(tp.process(&Packet::new(0x1CEB0300, &dt)).is_none());
+        let dt = [1, 1, 2, 3, 4, 5, 6, 7];
+        assert!(tp.process(&Packet::new(0x1CEB0300, &dt)).is_none());
+
+        assert!(tp.process(&Packet::new(0x1CEC0300, &rts)).is_none());
+        assert!(tp.process(&Packet::new(0x1CEB0300, &dt)).is_none());
+        let dt = [2, 8, 9, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let pg = tp.process(&Packet::new(0x1CEB0300, &dt)).unwrap();
+        let id = Id::from_id(pg.id);
+        assert_eq!(id.pgn, 0xDA00);
+        assert_eq!(id.destination, Some(0x03));
+        assert_eq!(pg.bytes.len(), 9);
+    }
+}
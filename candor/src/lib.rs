@@ -1,9 +1,31 @@
 //! CANdor library for CAN bus decoding/observation/reverse-engineering
 
+pub mod analysis;
+pub mod canopen;
+pub mod db;
+pub mod e2e;
+pub mod isotp;
+pub mod j1939;
+pub mod nmea2000;
+pub mod obd;
 pub mod stats;
+pub mod uds;
+pub mod xcp;
 
+use std::io;
 use std::time::Instant;
 
+/// Kind of CAN frame
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    #[default]
+    Data,
+    /// Remote transmission request, without payload
+    Remote,
+    /// Error frame, with the error class as ID and details as payload
+    Error,
+}
+
 #[derive(Default, Clone)]
 pub struct Packet {
     pub source: usize,
@@ -11,9 +33,21 @@ pub struct Packet {
     pub extended: bool,
     pub id: u32,
     pub bytes: Vec<u8>,
+    pub kind: Kind,
 }
 
 impl Packet {
+    /// Packet of a test, with IDs beyond the standard range extended
+    #[cfg(test)]
+    pub(crate) fn new(id: u32, bytes: &[u8]) -> Self {
+        Self {
+            extended: id > 0x7ff,
+            id,
+            bytes: bytes.to_vec(),
+            ..Default::default()
+        }
+    }
+
     pub fn id_string(&self) -> String {
         if self.extended {
             format!("{:08X} ", self.id)
@@ -22,3 +56,8 @@ impl Packet {
         }
     }
 }
+
+/// Error of malformed input files
+pub(crate) fn invalid(text: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, text)
+}
@@ -0,0 +1,395 @@
+//! NMEA 2000 fast-packet reassembly and parameter group definitions
+//!
+//! NMEA 2000 builds on J1939 identifiers, but sends payloads of up to 223
+//! bytes as a series of frames on the parameter group's own identifier
+//! ("fast-packet").
+//!
+//! Parameter groups are described in definition files listing one PGN
+//! (decimal) per line as `<PGN> <name> [<length>] [fast]`, followed by
+//! indented signal lines in DBC notation.  Groups longer than 8 bytes use
+//! fast-packet framing.  A set of common PGNs is built in:
+//!
+//! ```text
+//! 128267 WaterDepth 8
+//!     SID : 0|8@1+ (1,0) ""
+//!     Depth : 8|32@1+ (0.01,0) "m"
+//! ```
+
+use crate::db::{Database, Message, Signal};
+use crate::invalid;
+use crate::{j1939, Packet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Maximum payload size of a fast-packet transfer
+pub const MAX_SIZE: usize = 223;
+
+/// Maximum time between frames of a fast-packet transfer
+const TIMEOUT: Duration = Duration::from_millis(750);
+
+/// Definitions of commonly used parameter groups
+const COMMON: &str = r#"
+127245 Rudder 8
+    Instance : 0|8@1+ (1,0) ""
+    DirectionOrder : 8|2@1+ (1,0) ""
+    AngleOrder : 16|16@1- (0.005729578,0) "deg"
+    Position : 32|16@1- (0.005729578,0) "deg"
+127250 VesselHeading 8
+    SID : 0|8@1+ (1,0) ""
+    Heading : 8|16@1+ (0.005729578,0) "deg"
+    Deviation : 24|16@1- (0.005729578,0) "deg"
+    Variation : 40|16@1- (0.005729578,0) "deg"
+    Reference : 56|2@1+ (1,0) ""
+127488 EngineParametersRapid 8
+    Instance : 0|8@1+ (1,0) ""
+    Speed : 8|16@1+ (0.25,0) "rpm"
+    BoostPressure : 24|16@1+ (100,0) "Pa"
+    TiltTrim : 40|8@1- (1,0) "%"
+127508 BatteryStatus 8
+    Instance : 0|8@1+ (1,0) ""
+    Voltage : 8|16@1- (0.01,0) "V"
+    Current : 24|16@1- (0.1,0) "A"
+    Temperature : 40|16@1+ (0.01,-273.15) "C"
+    SID : 56|8@1+ (1,0) ""
+128259 Speed 8
+    SID : 0|8@1+ (1,0) ""
+    SpeedWaterReferenced : 8|16@1+ (0.01,0) "m/s"
+    SpeedGroundReferenced : 24|16@1+ (0.01,0) "m/s"
+128267 WaterDepth 8
+    SID : 0|8@1+ (1,0) ""
+    Depth : 8|32@1+ (0.01,0) "m"
+    Offset : 40|16@1- (0.001,0) "m"
+129025 PositionRapidUpdate 8
+    Latitude : 0|32@1- (1e-7,0) "deg"
+    Longitude : 32|32@1- (1e-7,0) "deg"
+129026 CogSogRapidUpdate 8
+    SID : 0|8@1+ (1,0) ""
+    Reference : 8|2@1+ (1,0) ""
+    Cog : 16|16@1+ (0.005729578,0) "deg"
+    Sog : 32|16@1+ (0.01,0) "m/s"
+129029 GnssPositionData 43
+    SID : 0|8@1+ (1,0) ""
+    Date : 8|16@1+ (1,0) "days"
+    Time : 24|32@1+ (0.0001,0) "s"
+    Latitude : 56|64@1- (1e-16,0) "deg"
+    Longitude : 120|64@1- (1e-16,0) "deg"
+    Altitude : 184|64@1- (1e-6,0) "m"
+    NumberOfSvs : 264|8@1+ (1,0) ""
+    Hdop : 272|16@1- (0.01,0) ""
+    Pdop : 288|16@1- (0.01,0) ""
+    GeoidalSeparation : 304|32@1- (0.01,0) "m"
+129038 AisClassAPositionReport 28
+    MessageId : 0|6@1+ (1,0) ""
+    UserId : 8|32@1+ (1,0) ""
+    Longitude : 40|32@1- (1e-7,0) "deg"
+    Latitude : 72|32@1- (1e-7,0) "deg"
+    Cog : 112|16@1+ (0.005729578,0) "deg"
+    Sog : 128|16@1+ (0.01,0) "m/s"
+    Heading : 168|16@1+ (0.005729578,0) "deg"
+130306 WindData 8
+    SID : 0|8@1+ (1,0) ""
+    WindSpeed : 8|16@1+ (0.01,0) "m/s"
+    WindAngle : 24|16@1+ (0.005729578,0) "deg"
+    Reference : 40|3@1+ (1,0) ""
+130312 Temperature 8
+    SID : 0|8@1+ (1,0) ""
+    Instance : 8|8@1+ (1,0) ""
+    Source : 16|8@1+ (1,0) ""
+    ActualTemperature : 24|16@1+ (0.01,-273.15) "C"
+    SetTemperature : 40|16@1+ (0.01,-273.15) "C"
+"#;
+
+/// Fast-packet parameter groups without signal definitions
+const FAST_PGNS: &[u32] = &[
+    126208, 126464, 126720, 126983, 126984, 126985, 126986, 126987, 126988,
+    126996, 126998, 127233, 127237, 127489, 127496, 127497, 127498, 127503,
+    127504, 127506, 127507, 127509, 127510, 127511, 127512, 127513, 127514,
+    128275, 128520, 129039, 129040, 129041, 129044, 129045, 129284, 129285,
+    129301, 129302, 129538, 129540, 129541, 129542, 129545, 129547, 129549,
+    129551, 129556, 129792, 129793, 129794, 129795, 129796, 129797, 129798,
+    129799, 129800, 129801, 129802, 129803, 129804, 129805, 129806, 129807,
+    129808, 129809, 129810, 130052, 130053, 130054, 130060, 130061, 130064,
+    130065, 130066, 130067, 130068, 130069, 130070, 130071, 130072, 130073,
+    130074, 130320, 130321, 130322, 130323, 130324, 130567, 130577, 130578,
+    130816,
+];
+
+/// Parameter group definition
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Pgn {
+    pub name: String,
+    /// Payload length in bytes
+    pub length: usize,
+    /// Sent using fast-packet framing
+    pub fast: bool,
+    pub signals: Vec<Signal>,
+}
+
+/// Parameter group definitions by PGN
+#[derive(Clone, Debug)]
+pub struct Definitions {
+    pgns: BTreeMap<u32, Pgn>,
+}
+
+impl Default for Definitions {
+    /// Built-in definitions of common parameter groups
+    fn default() -> Self {
+        Self::parse(COMMON).expect("valid built-in definitions")
+    }
+}
+
+impl Definitions {
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut pgns: BTreeMap<u32, Pgn> = BTreeMap::new();
+        let mut current: Option<u32> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |e: &str| invalid(format!("line {}: {e}", number + 1));
+            let trimmed = line.split('#').next().unwrap_or_default().trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            // indented lines are signals of the current PGN
+            if line.starts_with(char::is_whitespace) {
+                let pgn = current
+                    .and_then(|p| pgns.get_mut(&p))
+                    .ok_or_else(|| error("signal outside of a PGN"))?;
+                let signal = Signal::parse(trimmed).map_err(|e| error(&e))?;
+                pgn.length = pgn.length.max(signal.min_length());
+                pgn.fast |= pgn.length > 8;
+                pgn.signals.push(signal);
+                continue;
+            }
+
+            let mut tokens = trimmed.split_whitespace();
+            let number = tokens
+                .next()
+                .and_then(|p| p.parse::<u32>().ok())
+                .filter(|p| *p <= 0x3ffff)
+                .ok_or_else(|| error("invalid PGN"))?;
+            let name = tokens.next().ok_or_else(|| error("missing name"))?;
+            let mut pgn = Pgn {
+                name: name.to_string(),
+                ..Default::default()
+            };
+            for token in tokens {
+                match token {
+                    "fast" => pgn.fast = true,
+                    _ => {
+                        pgn.length = token
+                            .parse()
+                            .map_err(|_| error("invalid length"))?;
+                    }
+                }
+            }
+            pgn.fast |= pgn.length > 8;
+            pgns.insert(number, pgn);
+            current = Some(number);
+        }
+
+        Ok(Self { pgns })
+    }
+
+    /// Add (or replace) the definitions of another set
+    pub fn extend(&mut self, other: Definitions) {
+        self.pgns.extend(other.pgns);
+    }
+
+    pub fn get(&self, pgn: u32) -> Option<&Pgn> {
+        self.pgns.get(&pgn)
+    }
+
+    /// Parameter groups sent as fast-packets, including known groups
+    /// without definitions
+    pub fn fast_pgns(&self) -> HashSet<u32> {
+        let defined = self.pgns.iter().filter(|(_, p)| p.fast);
+        defined
+            .map(|(pgn, _)| *pgn)
+            .chain(FAST_PGNS.iter().copied())
+            .collect()
+    }
+
+    /// Database of the defined parameter groups, matched by PGN
+    pub fn database(&self) -> Database {
+        let messages = self
+            .pgns
+            .iter()
+            .map(|(pgn, def)| Message {
+                id: j1939::Id {
+                    priority: 6,
+                    pgn: *pgn,
+                    source: 0,
+                    destination: ((pgn & 0xff00) < 0xf000).then_some(0),
+                }
+                .to_id(),
+                extended: true,
+                name: def.name.clone(),
+                size: def.length,
+                transmitter: None,
+                cycle_time: None,
+                signals: def.signals.clone(),
+                ..Default::default()
+            })
+            .collect();
+        let mut database = Database::new(vec![], messages);
+        database.j1939 = true;
+        database
+    }
+}
+
+/// Fast-packet transfer in progress
+struct Session {
+    sequence: u8,
+    next: u8,
+    size: usize,
+    data: Vec<u8>,
+    time: Option<Instant>,
+}
+
+/// Reassembles fast-packet transfers into complete parameter group
+/// payloads
+#[derive(Default)]
+pub struct FastPacket {
+    pgns: HashSet<u32>,
+    /// Transfers in progress by (PGN, source)
+    sessions: HashMap<(u32, u8), Session>,
+}
+
+impl Clone for FastPacket {
+    fn clone(&self) -> Self {
+        // transfers in progress are not carried over
+        Self {
+            pgns: self.pgns.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl FastPacket {
+    pub fn new(pgns: HashSet<u32>) -> Self {
+        Self {
+            pgns,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the packet is a frame of a fast-packet parameter group
+    pub fn is_fast(&self, packet: &Packet) -> bool {
+        packet.extended
+            && self.pgns.contains(&j1939::Id::from_id(packet.id).pgn)
+    }
+
+    /// Process a fast-packet frame, returning the reassembled parameter
+    /// group once a transfer completes
+    pub fn process(&mut self, packet: &Packet) -> Option<Packet> {
+        if !self.is_fast(packet) || packet.bytes.len() < 2 {
+            return None;
+        }
+        let id = j1939::Id::from_id(packet.id);
+        let key = (id.pgn, id.source);
+        let bytes = packet.bytes.as_slice();
+        let sequence = bytes[0] >> 5;
+        let frame = bytes[0] & 0x1f;
+
+        if frame == 0 {
+            let size = (bytes[1] as usize).min(MAX_SIZE);
+            let session = Session {
+                sequence,
+                next: 1,
+                size,
+                data: bytes[2..].to_vec(),
+                time: packet.time,
+            };
+            self.sessions.insert(key, session);
+        } else {
+            let session = self.sessions.get_mut(&key)?;
+            let expired = match (session.time, packet.time) {
+                (Some(last), Some(now)) => now - last > TIMEOUT,
+                _ => false,
+            };
+            if expired || sequence != session.sequence || frame != session.next
+            {
+                self.sessions.remove(&key);
+                return None;
+            }
+            session.next += 1;
+            session.time = packet.time;
+            session.data.extend_from_slice(&bytes[1..]);
+        }
+
+        let session = self.sessions.get(&key)?;
+        if session.data.len() < session.size {
+            return None;
+        }
+        let mut session = self.sessions.remove(&key)?;
+        session.data.truncate(session.size);
+        Some(Packet {
+            bytes: session.data,
+            ..packet.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn definitions() {
+        let mut definitions = Definitions::default();
+        let depth = definitions.get(128267).unwrap();
+        assert_eq!(depth.name, "WaterDepth");
+        assert!(!depth.fast);
+        assert!(definitions.get(129029).unwrap().fast);
+
+        let custom = "130311 EnvironmentalParameters\n    \
+                      Temperature : 16|16@1+ (0.01,-273.15) \"C\"\n\
+                      126996 ProductInformation 134\n";
+        definitions.extend(Definitions::parse(custom).unwrap());
+        assert_eq!(definitions.get(130311).unwrap().length, 4);
+        assert!(definitions.fast_pgns().contains(&126996));
+
+        let database = definitions.database();
+        let message = database.message(0x09f50b23).unwrap();
+        assert_eq!(message.name, "WaterDepth");
+        let bytes = [0x00, 0xe8, 0x03, 0, 0, 0, 0, 0xff];
+        assert_eq!(message.signals[1].value(&bytes), Some(10.0));
+    }
+
+    #[test]
+    fn fast_packet() {
+        let mut fast = FastPacket::new(Definitions::default().fast_pgns());
+        // GNSS position data from source 0x23, 43 bytes in 7 frames
+        let id = 0x0df80523;
+        let single = Packet::new(0x09f50b23, &[0; 8]);
+        assert!(!fast.is_fast(&single));
+
+        let payload: Vec<u8> = (0..43).collect();
+        let mut frames = vec![[0x40, 43, 0, 1, 2, 3, 4, 5]];
+        for (index, chunk) in payload[6..].chunks(7).enumerate() {
+            let mut frame = [0xff; 8];
+            frame[0] = 0x40 | (index as u8 + 1);
+            frame[1..1 + chunk.len()].copy_from_slice(chunk);
+            frames.push(frame);
+        }
+        let (last, frames) = frames.split_last().unwrap();
+        for frame in frames {
+            assert!(fast.process(&Packet::new(id, frame)).is_none());
+        }
+        let pg = fast.process(&Packet::new(id, last)).unwrap();
+        assert_eq!(pg.id, id);
+        assert_eq!(pg.bytes, payload);
+
+        // a lost frame drops the transfer
+        assert!(fast.process(&Packet::new(id, &frames[0])).is_none());
+        assert!(fast.process(&Packet::new(id, &frames[2])).is_none());
+        assert!(fast.process(&Packet::new(id, last)).is_none());
+    }
+}
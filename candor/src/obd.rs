@@ -0,0 +1,302 @@
+//! OBD-II (SAE J1979) parameter decoding
+//!
+//! Responses on the legislated 11-bit diagnostic identifiers (0x7E8 -
+//! 0x7EF) are reassembled and their mode 01 (current data), 02 (freeze
+//! frame) and 09 (vehicle information) parameters decoded into named
+//! physical values.
+
+use crate::isotp::{self, Addressing, Pair};
+use crate::Packet;
+use std::collections::{BTreeMap, HashMap};
+
+/// Functional (broadcast) request identifier
+pub const FUNCTIONAL: u32 = 0x7df;
+/// Physical request identifier of the first ECU
+pub const REQUEST: u32 = 0x7e0;
+/// Response identifier of the first ECU
+pub const RESPONSE: u32 = 0x7e8;
+
+/// Linear scaled mode 01/02 parameter
+struct Pid {
+    pid: u8,
+    name: &'static str,
+    /// Data length in bytes
+    length: usize,
+    factor: f64,
+    offset: f64,
+    unit: &'static str,
+}
+
+const fn pid(
+    pid: u8,
+    name: &'static str,
+    length: usize,
+    factor: f64,
+    offset: f64,
+    unit: &'static str,
+) -> Pid {
+    Pid {
+        pid,
+        name,
+        length,
+        factor,
+        offset,
+        unit,
+    }
+}
+
+const PIDS: &[Pid] = &[
+    pid(0x04, "EngineLoad", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x05, "CoolantTemp", 1, 1.0, -40.0, "°C"),
+    pid(0x06, "ShortFuelTrim1", 1, 100.0 / 128.0, -100.0, "%"),
+    pid(0x07, "LongFuelTrim1", 1, 100.0 / 128.0, -100.0, "%"),
+    pid(0x08, "ShortFuelTrim2", 1, 100.0 / 128.0, -100.0, "%"),
+    pid(0x09, "LongFuelTrim2", 1, 100.0 / 128.0, -100.0, "%"),
+    pid(0x0a, "FuelPressure", 1, 3.0, 0.0, "kPa"),
+    pid(0x0b, "IntakeManifoldPressure", 1, 1.0, 0.0, "kPa"),
+    pid(0x0c, "EngineSpeed", 2, 0.25, 0.0, "rpm"),
+    pid(0x0d, "VehicleSpeed", 1, 1.0, 0.0, "km/h"),
+    pid(0x0e, "TimingAdvance", 1, 0.5, -64.0, "°"),
+    pid(0x0f, "IntakeAirTemp", 1, 1.0, -40.0, "°C"),
+    pid(0x10, "MafAirFlowRate", 2, 0.01, 0.0, "g/s"),
+    pid(0x11, "ThrottlePosition", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x1c, "ObdStandard", 1, 1.0, 0.0, ""),
+    pid(0x1f, "RunTime", 2, 1.0, 0.0, "s"),
+    pid(0x21, "DistanceWithMil", 2, 1.0, 0.0, "km"),
+    pid(0x22, "FuelRailPressure", 2, 0.079, 0.0, "kPa"),
+    pid(0x23, "FuelRailGaugePressure", 2, 10.0, 0.0, "kPa"),
+    pid(0x2c, "CommandedEgr", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x2f, "FuelLevel", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x30, "WarmUpsSinceCleared", 1, 1.0, 0.0, ""),
+    pid(0x31, "DistanceSinceCleared", 2, 1.0, 0.0, "km"),
+    pid(0x33, "BarometricPressure", 1, 1.0, 0.0, "kPa"),
+    pid(0x42, "ControlModuleVoltage", 2, 0.001, 0.0, "V"),
+    pid(0x43, "AbsoluteLoad", 2, 100.0 / 255.0, 0.0, "%"),
+    pid(0x45, "RelativeThrottle", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x46, "AmbientAirTemp", 1, 1.0, -40.0, "°C"),
+    pid(0x49, "AcceleratorPedalD", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x4d, "TimeWithMil", 2, 1.0, 0.0, "min"),
+    pid(0x4e, "TimeSinceCleared", 2, 1.0, 0.0, "min"),
+    pid(0x51, "FuelType", 1, 1.0, 0.0, ""),
+    pid(0x5a, "RelativePedal", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x5b, "HybridBatteryLife", 1, 100.0 / 255.0, 0.0, "%"),
+    pid(0x5c, "OilTemp", 1, 1.0, -40.0, "°C"),
+    pid(0x5e, "FuelRate", 2, 0.05, 0.0, "L/h"),
+    pid(0x61, "DemandedTorque", 1, 1.0, -125.0, "%"),
+    pid(0x62, "ActualTorque", 1, 1.0, -125.0, "%"),
+    pid(0x63, "ReferenceTorque", 2, 1.0, 0.0, "Nm"),
+    pid(0xa6, "Odometer", 4, 0.1, 0.0, "km"),
+];
+
+/// Decoded parameter value
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+    pub name: String,
+    /// Physical value with unit
+    pub text: String,
+}
+
+/// Decoded values of an ECU, by (mode, PID)
+pub type Values = BTreeMap<(u8, u8), Value>;
+
+/// Name of an OBD-II identifier, e.g. `OBD ECU #1`
+pub fn id_name(id: u32) -> Option<String> {
+    match id {
+        FUNCTIONAL => Some("OBD Request".to_string()),
+        0x7e0..=0x7e7 => Some(format!("OBD Request #{}", id - REQUEST + 1)),
+        0x7e8..=0x7ef => Some(format!("OBD ECU #{}", id - RESPONSE + 1)),
+        _ => None,
+    }
+}
+
+/// Decode a mode 01 or 02 parameter, returning the value and the length
+/// of its data, if known
+fn decode_pid(pid: u8, data: &[u8]) -> Option<(Value, usize)> {
+    // supported PIDs bitmaps
+    if pid.is_multiple_of(0x20) {
+        let bytes = data.get(..4)?;
+        let supported = (1..=32u8)
+            .filter(|bit| {
+                bytes[(bit - 1) as usize / 8] & (0x80 >> ((bit - 1) % 8)) != 0
+            })
+            .map(|bit| format!("{:02X}", pid + bit))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let name = format!("SupportedPids{:02X}", pid + 1);
+        return Some((
+            Value {
+                name,
+                text: supported,
+            },
+            4,
+        ));
+    }
+    if pid == 0x01 {
+        let bytes = data.get(..4)?;
+        let mil = if bytes[0] & 0x80 != 0 { "on" } else { "off" };
+        let text = format!("MIL {}, {} DTCs", mil, bytes[0] & 0x7f);
+        let name = "MonitorStatus".to_string();
+        return Some((Value { name, text }, 4));
+    }
+
+    let def = PIDS.iter().find(|p| p.pid == pid)?;
+    let bytes = data.get(..def.length)?;
+    let raw = bytes.iter().fold(0u64, |v, b| (v << 8) | *b as u64);
+    let value = raw as f64 * def.factor + def.offset;
+    let text = if def.factor.fract() != 0.0 || def.offset.fract() != 0.0 {
+        format!("{:.2}{}", value, def.unit)
+    } else {
+        format!("{}{}", value as i64, def.unit)
+    };
+    let name = def.name.to_string();
+    Some((Value { name, text }, def.length))
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .filter(|b| **b != 0)
+        .map(|b| match b {
+            0x20..=0x7e => *b as char,
+            _ => '.',
+        })
+        .collect()
+}
+
+/// Decode a mode 09 (vehicle information) response
+fn decode_info(info: u8, data: &[u8]) -> Option<Value> {
+    let (name, text) = match info {
+        // items are preceded by their count
+        0x02 => ("VIN", ascii(data.get(1..)?)),
+        0x04 => ("CalibrationId", ascii(data.get(1..)?)),
+        0x06 => {
+            let cvns = data.get(1..)?.chunks(4).map(|c| {
+                c.iter().map(|b| format!("{:02X}", b)).collect::<String>()
+            });
+            (
+                "CalibrationVerification",
+                cvns.collect::<Vec<_>>().join(" "),
+            )
+        }
+        0x0a => ("EcuName", ascii(data.get(1..)?)),
+        _ => return None,
+    };
+    let name = name.to_string();
+    Some(Value { name, text })
+}
+
+/// Decodes OBD-II responses into parameter values per responding ECU
+#[derive(Clone)]
+pub struct Decoder {
+    transport: isotp::Decoder,
+    values: HashMap<u32, Values>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        let pairs = (0..8)
+            .map(|ecu| Pair {
+                request: REQUEST + ecu,
+                response: RESPONSE + ecu,
+                addressing: Addressing::Normal,
+            })
+            .collect();
+        Self {
+            transport: isotp::Decoder::new(pairs),
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl Decoder {
+    /// Decoded values of the ECU responding on `id`
+    pub fn values(&self, id: u32) -> Option<&Values> {
+        self.values.get(&id)
+    }
+
+    pub fn process(&mut self, packet: &Packet) {
+        if packet.extended || !(RESPONSE..RESPONSE + 8).contains(&packet.id) {
+            return;
+        }
+        for event in self.transport.process(packet) {
+            if let isotp::Event::Pdu(pdu) = event {
+                self.decode(pdu.id, &pdu.data);
+            }
+        }
+    }
+
+    fn decode(&mut self, id: u32, data: &[u8]) {
+        let values = self.values.entry(id).or_default();
+        match data {
+            // mode 01 responses may hold several parameters
+            [0x41, rest @ ..] => {
+                let mut rest = rest;
+                while let [pid, data @ ..] = rest {
+                    let Some((value, length)) = decode_pid(*pid, data) else {
+                        break;
+                    };
+                    values.insert((0x01, *pid), value);
+                    rest = &data[length..];
+                }
+            }
+            [0x42, pid, _frame, data @ ..] => {
+                if let Some((mut value, _)) = decode_pid(*pid, data) {
+                    value.name = format!("FreezeFrame.{}", value.name);
+                    values.insert((0x02, *pid), value);
+                }
+            }
+            [0x49, info, data @ ..] => {
+                if let Some(value) = decode_info(*info, data) {
+                    values.insert((0x09, *info), value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn current_data() {
+        let mut obd = Decoder::default();
+        obd.process(&Packet::new(0x7df, &[0x02, 0x01, 0x0c, 0, 0, 0, 0, 0]));
+        obd.process(&Packet::new(
+            0x7e8,
+            &[0x04, 0x41, 0x0c, 0x1a, 0xf8, 0, 0, 0],
+        ));
+        obd.process(&Packet::new(
+            0x7e8,
+            &[0x05, 0x41, 0x0d, 0x32, 0x05, 0x5a, 0, 0],
+        ));
+        obd.process(&Packet::new(
+            0x7e8,
+            &[0x06, 0x41, 0x00, 0xbe, 0x1f, 0xa8, 0x13, 0],
+        ));
+
+        let values = obd.values(0x7e8).unwrap();
+        assert_eq!(values[&(0x01, 0x0c)].name, "EngineSpeed");
+        assert_eq!(values[&(0x01, 0x0c)].text, "1726.00rpm");
+        assert_eq!(values[&(0x01, 0x0d)].text, "50km/h");
+        assert_eq!(values[&(0x01, 0x05)].text, "50°C");
+        assert!(values[&(0x01, 0x00)].text.starts_with("01 03 04 05"));
+        assert!(obd.values(0x7e9).is_none());
+    }
+
+    #[test]
+    fn vehicle_information() {
+        let mut obd = Decoder::default();
+        let frames: [&[u8]; 3] = [
+            &[0x10, 0x14, 0x49, 0x02, 0x01, b'1', b'G', b'1'],
+            &[0x21, b'J', b'C', b'5', b'4', b'4', b'4', b'R'],
+            &[0x22, b'7', b'2', b'5', b'2', b'3', b'6', b'7'],
+        ];
+        for frame in frames {
+            obd.process(&Packet::new(0x7e8, frame));
+        }
+        let values = obd.values(0x7e8).unwrap();
+        assert_eq!(values[&(0x09, 0x02)].text, "1G1JC5444R7252367");
+    }
+}
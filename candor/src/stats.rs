@@ -1,11 +1,14 @@
-use crate::Packet;
-use bitvec::prelude::*;
-use can_dbc::{ByteOrder, DBC, MessageId, MultiplexIndicator, ValueType};
-use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
-use std::fs::File;
+use crate::db::{self, lint, score, Database, ValueType};
+use crate::{
+    analysis, canopen, e2e, isotp, j1939, nmea2000, obd, uds, xcp, Kind, Packet,
+};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fs;
 use std::io;
-use std::io::prelude::*;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Default number of packets kept per message for analysis
+const HISTORY: usize = 256;
 
 /// Main stats for CAN bus/interface
 #[derive(Default, Clone)]
@@ -20,15 +23,65 @@ pub struct Stats {
     pub load: u32,
     /// Packets per second
     pub pps: u32,
+    /// Number of remote frames received
+    pub remote: u32,
+    /// Number of error frames received
+    pub errors: u32,
 
     messages: VecDeque<Message>,
     ids: HashMap<u32, usize>,
     bytes_accum: u32,
     packet_accum: u32,
-    dbcs: Vec<DbcLookup>,
+    dbcs: Vec<Database>,
     sorted: bool,
     ordering: Vec<usize>,
     time: Option<Instant>,
+    start: Option<Instant>,
+    transport: Option<j1939::Transport>,
+    fast_packet: Option<nmea2000::FastPacket>,
+    isotp: Option<isotp::Decoder>,
+    isotp_events: VecDeque<isotp::Event>,
+    uds: uds::Tracker,
+    obd: Option<obd::Decoder>,
+    canopen: Option<canopen::Decoder>,
+    xcp: Option<xcp::Decoder>,
+    e2e: e2e::Protections,
+    history: usize,
+    /// Database signals whose messages keep a history, see `watch_signal`
+    watched: Vec<String>,
+    /// Database of signals defined with `define_signal`, overlaying the
+    /// definitions of the loaded files
+    defined: Option<usize>,
+    /// Signals defined with `define_signal`, by message ID
+    definitions: HashMap<u32, Vec<db::Signal>>,
+    warnings: Vec<String>,
+    files: Vec<DatabaseFile>,
+}
+
+/// Database loaded from a file, for reloading
+#[derive(Clone)]
+struct DatabaseFile {
+    index: usize,
+    filename: String,
+    modified: Option<SystemTime>,
+}
+
+/// Index of the database defining a message, preferring the overlay of
+/// signals defined interactively
+fn database_of(
+    dbcs: &[Database],
+    defined: Option<usize>,
+    id: u32,
+) -> Option<usize> {
+    defined
+        .filter(|d| dbcs[*d].contains(id))
+        .or_else(|| dbcs.iter().position(|d| d.contains(id)))
+}
+
+/// Modification time of a database file, ignoring a `#<bus>` suffix
+fn modified(filename: &str) -> Option<SystemTime> {
+    let (path, _) = db::split_bus(filename);
+    fs::metadata(path).and_then(|m| m.modified()).ok()
 }
 
 /// Message stats
@@ -41,51 +94,348 @@ pub struct Message {
     pub missing: Duration,
     pub current: Packet,
     pub previous: Packet,
+    /// End-to-end validation results, for protected messages
+    pub e2e: Option<e2e::Status>,
+    /// Recent packets of undecoded or watched messages, oldest first
+    pub history: VecDeque<Packet>,
+    /// Proposed fields of undecoded messages, updated periodically
+    pub annotations: Option<analysis::Annotations>,
     count_accum: usize,
 }
 
-/// Helper for looking up DBC messages by ID
-#[derive(Clone)]
-struct DbcLookup {
-    dbc: DBC,
-    ids: BTreeMap<u32, usize>,
+/// Statistics of a network node, from the transmitters of the database
+/// messages
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    /// Indices of the node's messages seen, ordered by ID
+    pub messages: Vec<usize>,
+    /// Number of messages the databases define for the node
+    pub defined: usize,
+    pub packets: usize,
+    /// Fraction of the bytes seen on the bus
+    pub share: f64,
+    /// Names of defined messages not seen or currently missing
+    pub missing: Vec<String>,
 }
 
-impl DbcLookup {
-    fn new(dbc: DBC) -> Self {
-        // get a map of message IDs to their corresponding index
-        let mut ids: BTreeMap<u32, usize> = Default::default();
-        for (index, message) in dbc.messages().iter().enumerate() {
-            let id = match *message.message_id() {
-                MessageId::Standard(id) => id as u32,
-                MessageId::Extended(id) => id,
-            };
-            ids.insert(id, index);
-        }
-        Self { dbc, ids }
-    }
-}
+/// Node grouping the messages of transmitters not given by the database
+pub const NO_TRANSMITTER: &str = "(no transmitter)";
+/// Node grouping the messages without database definition
+pub const UNKNOWN_NODE: &str = "(unknown)";
 
 impl Stats {
     pub fn new(baud: u32) -> Self {
         Self {
             baud,
             time: Some(Instant::now()),
+            history: HISTORY,
             ..Default::default()
         }
     }
 
+    /// Load a message database file (DBC, KCD, SYM, ARXML or EDS/DCF),
+    /// collecting consistency warnings
     pub fn add_dbc(&mut self, filename: String) -> io::Result<()> {
-        let mut f = File::open(filename)?;
-        let mut buffer = Vec::new();
-        f.read_to_end(&mut buffer)?;
-        let dbc = DBC::from_slice(&buffer).map_err(|e| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
-        })?;
-        self.dbcs.push(DbcLookup::new(dbc));
+        let database = Database::from_file(&filename)?;
+        let warnings = self.lint(&filename, &database, None);
+        self.warnings.extend(warnings);
+        self.files.push(DatabaseFile {
+            index: self.dbcs.len(),
+            modified: modified(&filename),
+            filename,
+        });
+        self.add_database(database);
         Ok(())
     }
 
+    /// Reload database files changed on disk since they were loaded,
+    /// returning the names of the files reloaded and the errors of the
+    /// files failing to load
+    ///
+    /// A file failing to load keeps its previous definitions.  Signals
+    /// defined with `define_signal` are kept over the reloaded definitions.
+    pub fn reload_dbcs(&mut self) -> (Vec<String>, Vec<String>) {
+        let mut reloaded = vec![];
+        let mut failed = vec![];
+        for file in 0..self.files.len() {
+            let DatabaseFile {
+                index,
+                ref filename,
+                modified: loaded,
+            } = self.files[file];
+            let modified = modified(filename);
+            if modified == loaded {
+                continue;
+            }
+            let filename = filename.clone();
+            self.files[file].modified = modified;
+
+            let mut database = match Database::from_file(&filename) {
+                Ok(database) => database,
+                Err(e) => {
+                    failed.push(format!("{filename}: {e}"));
+                    continue;
+                }
+            };
+            database.j1939 |= self.transport.is_some();
+            let warnings = self.lint(&filename, &database, Some(index));
+            let prefix = format!("{filename}: ");
+            self.warnings.retain(|w| !w.starts_with(&prefix));
+            self.warnings.extend(warnings);
+            self.dbcs[index] = database;
+            reloaded.push(filename);
+        }
+
+        if !reloaded.is_empty() {
+            let ids: Vec<u32> = self.definitions.keys().copied().collect();
+            for id in ids {
+                self.update_definition(id);
+            }
+            for message in self.messages.iter_mut() {
+                let id = message.current.id;
+                message.dbc = database_of(&self.dbcs, self.defined, id);
+            }
+        }
+        (reloaded, failed)
+    }
+
+    /// Consistency warnings of a database, including IDs also defined by
+    /// the other databases
+    fn lint(
+        &self,
+        filename: &str,
+        database: &Database,
+        replacing: Option<usize>,
+    ) -> Vec<String> {
+        let mut databases: Vec<&Database> = self
+            .dbcs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != replacing && Some(*i) != self.defined)
+            .map(|(_, d)| d)
+            .collect();
+        let known = lint::check_duplicates(&databases).len();
+        databases.push(database);
+        let duplicates = lint::check_duplicates(&databases);
+        lint::check(database)
+            .into_iter()
+            .chain(duplicates.into_iter().skip(known))
+            .map(|w| format!("{filename}: {w}"))
+            .collect()
+    }
+
+    /// Consistency warnings of the loaded database files
+    pub fn warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    pub fn add_database(&mut self, mut database: Database) {
+        database.j1939 |= self.transport.is_some();
+        let index = self.dbcs.len();
+        for message in self.messages.iter_mut() {
+            if message.dbc.is_none() && database.contains(message.current.id) {
+                message.dbc = Some(index);
+            }
+        }
+        self.dbcs.push(database);
+    }
+
+    /// Statistics by transmitting node, in the order declared by the
+    /// databases
+    pub fn nodes(&self) -> Vec<Node> {
+        let mut nodes: Vec<Node> = vec![];
+        fn node<'a>(nodes: &'a mut Vec<Node>, name: &str) -> &'a mut Node {
+            let index = match nodes.iter().position(|n| n.name == name) {
+                Some(index) => index,
+                None => {
+                    nodes.push(Node {
+                        name: name.to_string(),
+                        ..Default::default()
+                    });
+                    nodes.len() - 1
+                }
+            };
+            &mut nodes[index]
+        }
+
+        for database in self.dbcs.iter() {
+            for name in database.nodes.iter() {
+                node(&mut nodes, name);
+            }
+        }
+
+        // messages seen, by the database definition they match
+        let mut seen: HashMap<(usize, u32), Vec<&Message>> = HashMap::new();
+        let mut total = 0;
+        for index in self.ordering.iter() {
+            let message = &self.messages[*index];
+            let bytes = message.count * message.current.bytes.len();
+            total += bytes;
+            let definition = self.dbc_message(message);
+            let name = match definition {
+                Some(definition) => {
+                    let dbc = message.dbc.unwrap_or_default();
+                    seen.entry((dbc, definition.id)).or_default().push(message);
+                    definition.transmitter.as_deref().unwrap_or(NO_TRANSMITTER)
+                }
+                None => UNKNOWN_NODE,
+            };
+            let node = node(&mut nodes, name);
+            node.messages.push(*index);
+            node.packets += message.count;
+            node.share += bytes as f64;
+        }
+
+        for (dbc, database) in self.dbcs.iter().enumerate() {
+            for definition in database.messages() {
+                if self.shadowed(dbc, definition.id) {
+                    continue;
+                }
+                let name =
+                    definition.transmitter.as_deref().unwrap_or(NO_TRANSMITTER);
+                let node = node(&mut nodes, name);
+                node.defined += 1;
+                let missing = match seen.get(&(dbc, definition.id)) {
+                    Some(messages) => {
+                        messages.iter().all(|m| !m.missing.is_zero())
+                    }
+                    None => true,
+                };
+                if missing {
+                    node.missing.push(definition.name.clone());
+                }
+            }
+        }
+
+        for node in nodes.iter_mut() {
+            node.share = match total {
+                0 => 0.0,
+                total => node.share / total as f64,
+            };
+        }
+        nodes
+    }
+
+    /// Agreement of a candidate database with the traffic seen so far
+    pub fn score(&self, database: &Database) -> score::Score {
+        let observed = self.messages.iter().map(|m| score::Observed {
+            current: &m.current,
+            history: &m.history,
+            period: Some(m.delta.as_millis() as u32).filter(|p| *p > 0),
+        });
+        score::score(database, observed)
+    }
+
+    /// Set the number of packets kept per message for analysis
+    pub fn set_history(&mut self, len: usize) {
+        self.history = len;
+        for message in self.messages.iter_mut() {
+            let excess = message.history.len().saturating_sub(len);
+            message.history.drain(..excess);
+        }
+    }
+
+    /// Keep the history of the message decoding a signal, given as
+    /// `<message>.<signal>` or just `<signal>`, for `signal_series`
+    pub fn watch_signal(&mut self, name: &str) {
+        self.watched.push(name.to_string());
+    }
+
+    /// Whether packets of a message are kept, which is the case for
+    /// messages without definition or with a watched signal
+    fn keeps_history(&self, message: &Message) -> bool {
+        let Some(definition) = self.dbc_message(message) else {
+            return true;
+        };
+        let defines = |name: &str| {
+            definition.signals.iter().any(|signal| signal.name == name)
+        };
+        self.watched.iter().any(|name| match name.split_once('.') {
+            Some((message, signal)) => {
+                message == definition.name && defines(signal)
+            }
+            None => defines(name),
+        })
+    }
+
+    /// Time of the first packet received
+    pub fn start(&self) -> Option<Instant> {
+        self.start
+    }
+
+    pub fn databases(&self) -> &Vec<Database> {
+        &self.dbcs
+    }
+
+    /// Treat extended IDs as J1939: match databases by parameter group and
+    /// reassemble transport protocol transfers into their own messages
+    pub fn enable_j1939(&mut self) {
+        self.transport = Some(j1939::Transport::default());
+        for database in self.dbcs.iter_mut() {
+            database.j1939 = true;
+        }
+    }
+
+    pub fn j1939(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Treat extended IDs as NMEA 2000: J1939 with fast-packet transfers
+    /// reassembled in place of their frames, decoded using the given
+    /// parameter group definitions
+    pub fn enable_nmea2000(&mut self, definitions: &nmea2000::Definitions) {
+        self.enable_j1939();
+        self.fast_packet =
+            Some(nmea2000::FastPacket::new(definitions.fast_pgns()));
+        self.add_database(definitions.database());
+    }
+
+    /// Decode OBD-II responses of the emission-related ECUs
+    pub fn enable_obd(&mut self) {
+        self.obd = Some(obd::Decoder::default());
+    }
+
+    pub fn obd(&self) -> bool {
+        self.obd.is_some()
+    }
+
+    /// Interpret 11-bit IDs as CANopen communication objects and track SDO
+    /// transfers
+    pub fn enable_canopen(&mut self) {
+        self.canopen = Some(canopen::Decoder::default());
+    }
+
+    pub fn canopen(&self) -> bool {
+        self.canopen.is_some()
+    }
+
+    /// Follow an XCP on CAN session, decoding DAQ measurements
+    pub fn enable_xcp(&mut self, decoder: xcp::Decoder) {
+        self.xcp = Some(decoder);
+    }
+
+    /// Validate end-to-end protected messages, in addition to those
+    /// configured by database attributes
+    pub fn add_e2e(&mut self, protections: e2e::Protections) {
+        self.e2e.extend(protections);
+    }
+
+    /// Reassemble ISO-TP transfers on the given request/response pairs
+    pub fn enable_isotp(&mut self, pairs: Vec<isotp::Pair>) {
+        self.isotp = Some(isotp::Decoder::new(pairs));
+    }
+
+    /// Recently reassembled ISO-TP PDUs and errors, newest first
+    pub fn isotp_events(&self) -> &VecDeque<isotp::Event> {
+        &self.isotp_events
+    }
+
+    /// Recent diagnostic (UDS) transactions, newest first
+    pub fn transactions(&self) -> &VecDeque<uds::Transaction> {
+        self.uds.transactions()
+    }
+
     pub fn messages(&self) -> &VecDeque<Message> {
         &self.messages
     }
@@ -103,6 +453,11 @@ impl Stats {
         }
         self.time = Some(now);
 
+        let expired = self.isotp.as_mut().map(|d| d.expire(now));
+        for event in expired.unwrap_or_default() {
+            self.push_isotp(event);
+        }
+
         // TOD: improve this very loose estimate
         self.load =
             (self.load + (100 * ((self.bytes_accum * 10) + 5) / self.baud)) / 2;
@@ -127,6 +482,10 @@ impl Stats {
                     message.delta = Duration::default();
                 }
             }
+            if message.dbc.is_none() {
+                message.annotations =
+                    analysis::Annotations::analyze(message.history.iter());
+            }
         }
     }
 
@@ -134,25 +493,84 @@ impl Stats {
         self.packets += 1;
         self.packet_accum += 1;
 
+        if self.start.is_none() {
+            self.start = packet.time;
+        }
+
         let bytes = packet.bytes.len() as u32;
         self.bytes += bytes;
         self.bytes_accum += bytes;
 
+        // only data frames make up messages
+        match packet.kind {
+            Kind::Data => {}
+            Kind::Remote => {
+                self.remote += 1;
+                return;
+            }
+            Kind::Error => {
+                self.errors += 1;
+                return;
+            }
+        }
+
+        // fast-packet frames share the identifier of the reassembled
+        // parameter group, so only complete transfers are registered
+        let fast = self.fast_packet.as_ref().is_some_and(|f| f.is_fast(packet));
+        if fast {
+            let reassembled =
+                self.fast_packet.as_mut().and_then(|f| f.process(packet));
+            if let Some(packet) = reassembled {
+                self.register(&packet);
+            }
+        } else {
+            self.register(packet);
+        }
+
+        let reassembled =
+            self.transport.as_mut().and_then(|t| t.process(packet));
+        if let Some(packet) = reassembled {
+            self.register(&packet);
+        }
+
+        if let Some(decoder) = self.obd.as_mut() {
+            decoder.process(packet);
+        }
+        if let Some(decoder) = self.canopen.as_mut() {
+            decoder.process(packet);
+        }
+        if let Some(decoder) = self.xcp.as_mut() {
+            decoder.process(packet);
+        }
+
+        let events = self.isotp.as_mut().map(|d| d.process(packet));
+        for event in events.unwrap_or_default() {
+            self.push_isotp(event);
+        }
+    }
+
+    fn push_isotp(&mut self, event: isotp::Event) {
+        if let isotp::Event::Pdu(pdu) = &event {
+            self.uds.process(pdu);
+        }
+        self.isotp_events.push_front(event);
+        if self.isotp_events.len() > 100 {
+            let _ = self.isotp_events.pop_back();
+        }
+    }
+
+    fn register(&mut self, packet: &Packet) {
         // register messages as they are seen
         let index = *self.ids.entry(packet.id).or_insert_with(|| {
-            let dbc = self
-                .dbcs
-                .iter()
-                .enumerate()
-                .find(|(_, m)| m.ids.contains_key(&packet.id))
-                .map(|(i, _)| i);
-
+            let dbc = database_of(&self.dbcs, self.defined, packet.id);
             self.messages.push_back(Message::new(packet, dbc));
             self.sorted = false;
 
             self.messages.len() - 1
         });
 
+        let keep =
+            self.history > 0 && self.keeps_history(&self.messages[index]);
         let message = self.messages.get_mut(index).expect("index for id");
 
         message.count += 1;
@@ -162,6 +580,25 @@ impl Stats {
 
         message.missing = Duration::default();
 
+        if keep {
+            let excess =
+                (message.history.len() + 1).saturating_sub(self.history);
+            message.history.drain(..excess);
+            message.history.push_back(packet.clone());
+        }
+
+        let protection = self.e2e.get(&packet.id).or_else(|| {
+            self.dbcs
+                .get(message.dbc?)
+                .and_then(|d| d.e2e.get(&packet.id))
+        });
+        if let Some(protection) = protection {
+            message
+                .e2e
+                .get_or_insert_with(Default::default)
+                .check(protection, &packet.bytes);
+        }
+
         if !self.sorted {
             let mut heap: BinaryHeap<u32> = BinaryHeap::new();
             self.ordering.resize(self.messages.len(), 0);
@@ -176,65 +613,280 @@ impl Stats {
         }
     }
 
-    pub fn dbc_message(&self, message: &Message) -> Option<&can_dbc::Message> {
-        if let Some(dbc) = message.dbc {
-            if let Some(lookup) = self.dbcs.get(dbc) {
-                if let Some(index) = lookup.ids.get(&message.current.id) {
-                    return lookup.dbc.messages().get(*index);
+    pub fn dbc_message(&self, message: &Message) -> Option<&db::Message> {
+        self.dbcs
+            .get(message.dbc?)
+            .and_then(|d| d.message(message.current.id))
+    }
+
+    /// End-to-end validation summary of a protected message
+    pub fn e2e_text(&self, message: &Message) -> Option<String> {
+        let id = message.current.id;
+        let protection = self.e2e.get(&id).or_else(|| {
+            self.dbcs.get(message.dbc?).and_then(|d| d.e2e.get(&id))
+        })?;
+        Some(message.e2e.as_ref()?.text(protection))
+    }
+
+    /// Proposed counters, checksums and constants of an undecoded message,
+    /// based on its history as of the last periodic update
+    pub fn analysis<'a>(
+        &self,
+        message: &'a Message,
+    ) -> Option<&'a analysis::Annotations> {
+        message.annotations.as_ref()
+    }
+
+    /// Draft definition of a message inferred from its history
+    pub fn draft(&self, message: &Message) -> Option<db::Message> {
+        let packet = &message.current;
+        analysis::infer(packet.id, packet.extended, message.history.iter())
+    }
+
+    /// Recent values of a database signal, given as `<message>.<signal>` or
+    /// just `<signal>`
+    pub fn signal_series(&self, name: &str) -> Option<analysis::Series> {
+        let (message_name, signal_name) = match name.split_once('.') {
+            Some((message, signal)) => (Some(message), signal),
+            None => (None, name),
+        };
+        let (msg, sig) = self.dbcs.iter().find_map(|d| {
+            d.messages().iter().find_map(|m| {
+                if message_name.is_some_and(|n| n != m.name) {
+                    return None;
                 }
+                let signal =
+                    m.signals.iter().find(|s| s.name == signal_name)?;
+                Some((m, signal))
+            })
+        })?;
+        let message = self.messages.get(*self.ids.get(&msg.id)?)?;
+        let series = message
+            .history
+            .iter()
+            .filter(|p| msg.is_present(sig, &p.bytes))
+            .filter_map(|p| Some((p.time?, sig.value(&p.bytes)?)))
+            .collect();
+        Some(series)
+    }
+
+    /// Bit fields of messages without database definition, ranked by
+    /// correlation with a reference signal
+    pub fn correlate(
+        &self,
+        reference: &[(Instant, f64)],
+    ) -> Vec<analysis::Correlation> {
+        let mut correlations: Vec<analysis::Correlation> = self
+            .messages
+            .iter()
+            .filter(|m| m.dbc.is_none())
+            .flat_map(|m| analysis::correlate(m.history.iter(), reference))
+            .collect();
+        correlations.sort_by(|a, b| {
+            b.coefficient.abs().total_cmp(&a.coefficient.abs())
+        });
+        correlations
+    }
+
+    /// Add or replace a signal in the definition of a message
+    ///
+    /// The definition goes to a database of its own overlaying the loaded
+    /// files, so that reloading them keeps the signals defined.
+    pub fn define_signal(&mut self, index: usize, signal: db::Signal) {
+        let Some(message) = self.messages.get(index) else {
+            return;
+        };
+        let packet = message.current.clone();
+        let known = message.dbc.is_some();
+        let dbc = match self.defined {
+            Some(dbc) => dbc,
+            None => {
+                let dbc = self.dbcs.len();
+                self.add_database(Database::default());
+                self.defined = Some(dbc);
+                dbc
             }
+        };
+
+        if !known && !self.dbcs[dbc].contains(packet.id) {
+            self.dbcs[dbc].insert(db::Message {
+                id: packet.id,
+                extended: packet.extended,
+                name: format!("MSG_{:X}", packet.id),
+                size: packet.bytes.len(),
+                ..Default::default()
+            });
         }
+        let signals = self.definitions.entry(packet.id).or_default();
+        signals.retain(|s| s.name != signal.name);
+        signals.push(signal);
+        self.update_definition(packet.id);
+        self.messages[index].dbc = Some(dbc);
+    }
 
-        None
+    /// Rebuild the overlay definition of a message from the loaded files
+    /// and the signals defined with `define_signal`
+    fn update_definition(&mut self, id: u32) {
+        let (Some(dbc), Some(signals)) =
+            (self.defined, self.definitions.get(&id))
+        else {
+            return;
+        };
+        let loaded = self
+            .dbcs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != dbc)
+            .find_map(|(_, d)| d.message(id));
+        let Some(mut definition) =
+            loaded.or(self.dbcs[dbc].message(id)).cloned()
+        else {
+            return;
+        };
+        for signal in signals {
+            definition.signals.retain(|s| s.name != signal.name);
+            definition.signals.push(signal.clone());
+        }
+        self.dbcs[dbc].insert(definition);
     }
 
-    pub fn signal_text(
-        &self,
-        _msg: &can_dbc::Message,
-        sig: &can_dbc::Signal,
-        packet: &Packet,
-    ) -> String {
-        let start = *sig.start_bit() as usize;
-        let size = *sig.signal_size() as usize;
+    /// Whether a definition of a loaded file is overlaid by one with
+    /// signals defined with `define_signal`
+    fn shadowed(&self, dbc: usize, id: u32) -> bool {
+        self.defined
+            .is_some_and(|d| d != dbc && self.dbcs[d].message(id).is_some())
+    }
+
+    /// Database of all messages seen, for a DBC skeleton
+    ///
+    /// Definitions of the loaded databases are kept, with their cycle time
+    /// measured if missing.  Unknown messages get their observed length,
+    /// measured cycle time and inferred signals.
+    pub fn export(&self) -> Database {
+        // decoded messages keep no history, only their rate
+        let measured = |id: u32| {
+            let message = self.messages.get(*self.ids.get(&id)?)?;
+            analysis::cycle_time(message.history.iter()).or_else(|| {
+                let delta = message.delta.as_millis() as u32;
+                (delta > 0).then_some(delta)
+            })
+        };
+
+        let mut nodes: Vec<String> = vec![];
+        let mut messages: Vec<db::Message> = vec![];
+        // overlay definitions first, replacing those of the loaded files
+        let databases = self
+            .defined
+            .into_iter()
+            .chain((0..self.dbcs.len()).filter(|i| Some(*i) != self.defined));
+        for database in databases.map(|i| &self.dbcs[i]) {
+            for node in database.nodes.iter() {
+                if !nodes.contains(node) {
+                    nodes.push(node.clone());
+                }
+            }
+            for message in database.messages() {
+                if messages.iter().any(|m| m.id == message.id) {
+                    continue;
+                }
+                let mut message = message.clone();
+                message.cycle_time =
+                    message.cycle_time.or(measured(message.id));
+                messages.push(message);
+            }
+        }
 
-        if *sig.multiplexer_indicator() != MultiplexIndicator::Plain
-            && *sig.multiplexer_indicator() != MultiplexIndicator::Multiplexor
+        for index in self.ordering.iter() {
+            let message = &self.messages[*index];
+            if message.dbc.is_some() {
+                continue;
+            }
+            let packet = &message.current;
+            let mut definition = self.draft(message).unwrap_or(db::Message {
+                id: packet.id,
+                extended: packet.extended,
+                name: format!("MSG_{:X}", packet.id),
+                size: packet.bytes.len(),
+                ..Default::default()
+            });
+            definition.cycle_time = measured(packet.id);
+            messages.push(definition);
+        }
+
+        let mut database = Database::new(nodes, messages);
+        database.j1939 = self.j1939();
+        database
+    }
+
+    /// Decoded OBD-II parameters of a message from a responding ECU
+    pub fn obd_values(&self, message: &Message) -> Option<&obd::Values> {
+        self.obd.as_ref()?.values(message.current.id)
+    }
+
+    /// CANopen interpretation of a message: the frame's meaning and, for
+    /// SDO channels, the last completed transfer
+    pub fn canopen_text(&self, message: &Message) -> Vec<String> {
+        let Some(decoder) = self.canopen.as_ref() else {
+            return vec![];
+        };
+        let packet = &message.current;
+        let mut lines: Vec<String> =
+            canopen::describe(packet).into_iter().collect();
+        if let Some(
+            canopen::Object::SdoRx(node) | canopen::Object::SdoTx(node),
+        ) = canopen::Object::classify(packet.id)
         {
-            // TODO: support multiplexed messages
-            return "<multiplexed>".to_string();
+            if let Some(transfer) = decoder.transfer(node) {
+                lines.push(transfer.text());
+            }
+        }
+        lines
+    }
+
+    /// XCP interpretation of a message: the last command on the master ID,
+    /// or the last response and measurement values on the slave ID
+    pub fn xcp_text(&self, message: &Message) -> Vec<String> {
+        let Some((decoder, ids)) =
+            self.xcp.as_ref().and_then(|d| Some((d, d.ids()?)))
+        else {
+            return vec![];
+        };
+        let id = message.current.id;
+        if id == ids.master {
+            vec![decoder.command_text()]
+        } else if id == ids.slave {
+            let status = decoder.status_text().to_string();
+            let values = decoder
+                .values()
+                .values()
+                .map(|v| format!("{} {}", v.name, v.text));
+            std::iter::once(status).chain(values).collect()
+        } else {
+            vec![]
         }
+    }
 
+    pub fn signal_text(
+        &self,
+        msg: &db::Message,
+        sig: &db::Signal,
+        packet: &Packet,
+    ) -> String {
         let bytes = packet.bytes.as_slice();
-        let value = match *sig.value_type() {
-            ValueType::Unsigned => {
-                let raw = match sig.byte_order() {
-                    ByteOrder::LittleEndian => bytes.view_bits::<Lsb0>()
-                        [start..start + size]
-                        .load_le::<u64>(),
-                    ByteOrder::BigEndian => bytes.view_bits::<Msb0>()
-                        [(start - (size - 1))..start + 1]
-                        .load_be::<u64>(),
-                };
-                raw as f32
-            }
-            ValueType::Signed => {
-                let raw = match sig.byte_order() {
-                    ByteOrder::LittleEndian => bytes.view_bits::<Lsb0>()
-                        [start..start + size]
-                        .load_le::<i64>(),
-                    ByteOrder::BigEndian => bytes.view_bits::<Msb0>()
-                        [(start - (size - 1))..start + 1]
-                        .load_be::<i64>(),
-                };
-                i64::from_ne_bytes(raw.to_ne_bytes()) as f32
-            }
+        if !msg.is_present(sig, bytes) {
+            return String::new();
+        }
+        let Some(value) = sig.value(bytes) else {
+            return String::new();
         };
-        let factor = *sig.factor() as f32;
-        let offset = *sig.offset() as f32;
-        if factor != 1.0 || offset < 0.0 {
-            format!("{:.3}{}", value * factor + offset, sig.unit())
+
+        if sig.factor != 1.0
+            || sig.offset < 0.0
+            || sig.value_type == ValueType::Float
+        {
+            format!("{:.3}{}", value, sig.unit)
         } else {
-            format!("{}{}", (value + offset) as u64, sig.unit())
+            format!("{}{}", value as i64, sig.unit)
         }
     }
 }
@@ -0,0 +1,637 @@
+//! UDS (ISO 14229) diagnostic service decoding
+//!
+//! Requests and responses are paired from reassembled ISO-TP PDUs, and
+//! decoded to text on demand using optional data identifier (DID)
+//! definitions.
+//!
+//! DID definition files list one DID per line as `<DID> <name> [<format>
+//! [<length>]]`, with hexadecimal DIDs and a format of `hex` (default),
+//! `ascii` or `signals`.  Signals follow on indented lines using the DBC
+//! signal syntax, e.g.:
+//!
+//! ```text
+//! F190 VIN ascii 17
+//! 0100 EngineState signals
+//!     Rpm : 7|16@0+ (0.25,0) "rpm"
+//!     Coolant : 23|8@0+ (1,-40) "C"
+//! ```
+
+use crate::db::Signal;
+use crate::invalid;
+use crate::isotp::Pdu;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Negative response service identifier
+pub const NEGATIVE_RESPONSE: u8 = 0x7f;
+
+/// Negative response code: request received, response pending
+const RESPONSE_PENDING: u8 = 0x78;
+
+pub fn service_name(sid: u8) -> Option<&'static str> {
+    let name = match sid {
+        0x10 => "DiagnosticSessionControl",
+        0x11 => "ECUReset",
+        0x14 => "ClearDiagnosticInformation",
+        0x19 => "ReadDTCInformation",
+        0x22 => "ReadDataByIdentifier",
+        0x23 => "ReadMemoryByAddress",
+        0x24 => "ReadScalingDataByIdentifier",
+        0x27 => "SecurityAccess",
+        0x28 => "CommunicationControl",
+        0x29 => "Authentication",
+        0x2a => "ReadDataByPeriodicIdentifier",
+        0x2c => "DynamicallyDefineDataIdentifier",
+        0x2e => "WriteDataByIdentifier",
+        0x2f => "InputOutputControlByIdentifier",
+        0x31 => "RoutineControl",
+        0x34 => "RequestDownload",
+        0x35 => "RequestUpload",
+        0x36 => "TransferData",
+        0x37 => "RequestTransferExit",
+        0x38 => "RequestFileTransfer",
+        0x3d => "WriteMemoryByAddress",
+        0x3e => "TesterPresent",
+        0x83 => "AccessTimingParameter",
+        0x84 => "SecuredDataTransmission",
+        0x85 => "ControlDTCSetting",
+        0x86 => "ResponseOnEvent",
+        0x87 => "LinkControl",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Whether the second byte of a request is a sub-function
+fn has_sub_function(sid: u8) -> bool {
+    matches!(
+        sid,
+        0x10 | 0x11
+            | 0x19
+            | 0x27
+            | 0x28
+            | 0x29
+            | 0x2c
+            | 0x31
+            | 0x3e
+            | 0x83
+            | 0x85
+            | 0x86
+            | 0x87
+    )
+}
+
+pub fn sub_function_name(sid: u8, sub: u8) -> Option<&'static str> {
+    let name = match (sid, sub & 0x7f) {
+        (0x10, 0x01) => "defaultSession",
+        (0x10, 0x02) => "programmingSession",
+        (0x10, 0x03) => "extendedDiagnosticSession",
+        (0x10, 0x04) => "safetySystemDiagnosticSession",
+        (0x11, 0x01) => "hardReset",
+        (0x11, 0x02) => "keyOffOnReset",
+        (0x11, 0x03) => "softReset",
+        (0x11, 0x04) => "enableRapidPowerShutDown",
+        (0x11, 0x05) => "disableRapidPowerShutDown",
+        (0x19, 0x01) => "reportNumberOfDTCByStatusMask",
+        (0x19, 0x02) => "reportDTCByStatusMask",
+        (0x19, 0x03) => "reportDTCSnapshotIdentification",
+        (0x19, 0x04) => "reportDTCSnapshotRecordByDTCNumber",
+        (0x19, 0x06) => "reportDTCExtDataRecordByDTCNumber",
+        (0x19, 0x0a) => "reportSupportedDTC",
+        (0x19, 0x0b) => "reportFirstTestFailedDTC",
+        (0x19, 0x0e) => "reportMostRecentConfirmedDTC",
+        (0x27, s) if s % 2 == 1 => "requestSeed",
+        (0x27, _) => "sendKey",
+        (0x28, 0x00) => "enableRxAndTx",
+        (0x28, 0x01) => "enableRxAndDisableTx",
+        (0x28, 0x02) => "disableRxAndEnableTx",
+        (0x28, 0x03) => "disableRxAndTx",
+        (0x31, 0x01) => "startRoutine",
+        (0x31, 0x02) => "stopRoutine",
+        (0x31, 0x03) => "requestRoutineResults",
+        (0x3e, 0x00) => "zeroSubFunction",
+        (0x85, 0x01) => "on",
+        (0x85, 0x02) => "off",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn nrc_name(code: u8) -> &'static str {
+    match code {
+        0x10 => "generalReject",
+        0x11 => "serviceNotSupported",
+        0x12 => "subFunctionNotSupported",
+        0x13 => "incorrectMessageLengthOrInvalidFormat",
+        0x14 => "responseTooLong",
+        0x21 => "busyRepeatRequest",
+        0x22 => "conditionsNotCorrect",
+        0x24 => "requestSequenceError",
+        0x25 => "noResponseFromSubnetComponent",
+        0x26 => "failurePreventsExecutionOfRequestedAction",
+        0x31 => "requestOutOfRange",
+        0x33 => "securityAccessDenied",
+        0x34 => "authenticationRequired",
+        0x35 => "invalidKey",
+        0x36 => "exceededNumberOfAttempts",
+        0x37 => "requiredTimeDelayNotExpired",
+        0x70 => "uploadDownloadNotAccepted",
+        0x71 => "transferDataSuspended",
+        0x72 => "generalProgrammingFailure",
+        0x73 => "wrongBlockSequenceCounter",
+        0x78 => "requestCorrectlyReceivedResponsePending",
+        0x7e => "subFunctionNotSupportedInActiveSession",
+        0x7f => "serviceNotSupportedInActiveSession",
+        0x81 => "rpmTooHigh",
+        0x82 => "rpmTooLow",
+        0x83 => "engineIsRunning",
+        0x84 => "engineIsNotRunning",
+        0x85 => "engineRunTimeTooLow",
+        0x86 => "temperatureTooHigh",
+        0x87 => "temperatureTooLow",
+        0x88 => "vehicleSpeedTooHigh",
+        0x89 => "vehicleSpeedTooLow",
+        0x8a => "throttlePedalTooHigh",
+        0x8b => "throttlePedalTooLow",
+        0x8c => "transmissionRangeNotInNeutral",
+        0x8d => "transmissionRangeNotInGear",
+        0x8f => "brakeSwitchNotClosed",
+        0x90 => "shifterLeverNotInPark",
+        0x91 => "torqueConverterClutchLocked",
+        0x92 => "voltageTooHigh",
+        0x93 => "voltageTooLow",
+        _ => "unknown",
+    }
+}
+
+/// Diagnostic trouble code with its status byte
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dtc {
+    /// 24-bit DTC number
+    pub code: u32,
+    pub status: u8,
+}
+
+const STATUS_BITS: [&str; 8] = [
+    "testFailed",
+    "testFailedThisOperationCycle",
+    "pending",
+    "confirmed",
+    "testNotCompletedSinceLastClear",
+    "testFailedSinceLastClear",
+    "testNotCompletedThisOperationCycle",
+    "warningIndicatorRequested",
+];
+
+impl Dtc {
+    /// Format as an SAE J2012 code with failure type, e.g. `P0123-45`
+    pub fn text(&self) -> String {
+        let system = ['P', 'C', 'B', 'U'][(self.code >> 22) as usize & 3];
+        format!(
+            "{}{:04X}-{:02X}",
+            system,
+            (self.code >> 8) & 0x3fff,
+            self.code & 0xff
+        )
+    }
+
+    /// Names of the status bits that are set
+    pub fn status_text(&self) -> String {
+        STATUS_BITS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.status & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DidFormat {
+    #[default]
+    Hex,
+    Ascii,
+    Signals,
+}
+
+/// Data identifier definition
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Did {
+    pub name: String,
+    pub format: DidFormat,
+    /// Data length in bytes, if known
+    pub length: Option<usize>,
+    pub signals: Vec<Signal>,
+}
+
+/// User-supplied data identifier definitions
+#[derive(Default, Clone, Debug)]
+pub struct Dids {
+    dids: BTreeMap<u16, Did>,
+}
+
+impl Dids {
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut dids: BTreeMap<u16, Did> = BTreeMap::new();
+        let mut current: Option<u16> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |e: &str| invalid(format!("line {}: {e}", number + 1));
+            let trimmed = line.split('#').next().unwrap_or_default().trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            // indented lines are signals of the current DID
+            if line.starts_with(char::is_whitespace) {
+                let did = current
+                    .and_then(|d| dids.get_mut(&d))
+                    .ok_or_else(|| error("signal outside of a DID"))?;
+                did.signals
+                    .push(Signal::parse(trimmed).map_err(|e| error(&e))?);
+                let length = did.signals.iter().map(|s| s.min_length()).max();
+                did.length = did.length.max(length);
+                continue;
+            }
+
+            let mut tokens = trimmed.split_whitespace();
+            let id = tokens.next().unwrap_or_default();
+            let id = u16::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map_err(|_| error("invalid DID"))?;
+            let name = tokens.next().ok_or_else(|| error("missing name"))?;
+            let format = match tokens.next() {
+                None | Some("hex") => DidFormat::Hex,
+                Some("ascii") => DidFormat::Ascii,
+                Some("signals") => DidFormat::Signals,
+                Some(_) => return Err(error("unknown format")),
+            };
+            let length = tokens
+                .next()
+                .map(|l| l.parse::<usize>())
+                .transpose()
+                .map_err(|_| error("invalid length"))?;
+            dids.insert(
+                id,
+                Did {
+                    name: name.to_string(),
+                    format,
+                    length,
+                    signals: vec![],
+                },
+            );
+            current = Some(id);
+        }
+
+        Ok(Self { dids })
+    }
+
+    pub fn get(&self, did: u16) -> Option<&Did> {
+        self.dids.get(&did)
+    }
+}
+
+/// Request with its (final) response
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub source: usize,
+    /// CAN ID of the request
+    pub id: u32,
+    /// Index of the ISO-TP pair the transaction runs on
+    pub pair: usize,
+    pub time: Option<Instant>,
+    pub request: Vec<u8>,
+    pub response: Option<Vec<u8>>,
+    /// Time from request to final response
+    pub latency: Option<Duration>,
+    /// Number of "response pending" replies received
+    pub pending: usize,
+}
+
+/// Pairs diagnostic requests and responses
+#[derive(Default, Clone)]
+pub struct Tracker {
+    transactions: VecDeque<Transaction>,
+}
+
+impl Tracker {
+    /// Recent transactions, newest first
+    pub fn transactions(&self) -> &VecDeque<Transaction> {
+        &self.transactions
+    }
+
+    pub fn process(&mut self, pdu: &Pdu) {
+        if pdu.request {
+            self.transactions.push_front(Transaction {
+                source: pdu.source,
+                id: pdu.id,
+                pair: pdu.pair,
+                time: pdu.time,
+                request: pdu.data.clone(),
+                response: None,
+                latency: None,
+                pending: 0,
+            });
+            if self.transactions.len() > 100 {
+                let _ = self.transactions.pop_back();
+            }
+            return;
+        }
+
+        let Some(sid) = response_sid(&pdu.data) else {
+            return;
+        };
+        let Some(transaction) = self.transactions.iter_mut().find(|t| {
+            t.source == pdu.source
+                && t.pair == pdu.pair
+                && t.response.is_none()
+                && t.request.first() == Some(&sid)
+        }) else {
+            return;
+        };
+        if pdu.data.first() == Some(&NEGATIVE_RESPONSE)
+            && pdu.data.get(2) == Some(&RESPONSE_PENDING)
+        {
+            transaction.pending += 1;
+            return;
+        }
+        transaction.latency = match (transaction.time, pdu.time) {
+            (Some(request), Some(response)) => Some(response - request),
+            _ => None,
+        };
+        transaction.response = Some(pdu.data.clone());
+    }
+}
+
+/// Service ID of the request a response answers
+fn response_sid(data: &[u8]) -> Option<u8> {
+    match data.first()? {
+        &NEGATIVE_RESPONSE => data.get(1).copied(),
+        sid if *sid >= 0x40 => Some(sid - 0x40),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Describe a request or response: a summary line followed by any detail
+/// lines (DID values, DTCs)
+pub fn describe(data: &[u8], dids: &Dids) -> Vec<String> {
+    let Some(&first) = data.first() else {
+        return vec![];
+    };
+
+    if first == NEGATIVE_RESPONSE {
+        let sid = data.get(1).copied().unwrap_or_default();
+        let nrc = data.get(2).copied().unwrap_or_default();
+        let service = service_name(sid).unwrap_or("?");
+        return vec![format!("{service} NRC {nrc:02X} {}", nrc_name(nrc))];
+    }
+
+    let (sid, response) = match service_name(first) {
+        Some(_) => (first, false),
+        None if first >= 0x40 && service_name(first - 0x40).is_some() => {
+            (first - 0x40, true)
+        }
+        None => return vec![hex(data)],
+    };
+    let mut summary = service_name(sid).unwrap_or_default().to_string();
+    if response {
+        summary.push('+');
+    }
+    let mut lines = vec![];
+    let rest = &data[1..];
+
+    match sid {
+        0x22 if !response => {
+            for did in rest.chunks(2).filter(|c| c.len() == 2) {
+                let did = u16::from_be_bytes([did[0], did[1]]);
+                summary.push_str(&format!(" {did:04X}"));
+                if let Some(def) = dids.get(did) {
+                    summary.push_str(&format!(" ({})", def.name));
+                }
+            }
+        }
+        0x22 | 0x2e if response || sid == 0x2e => {
+            describe_dids(rest, dids, &mut summary, &mut lines, !response);
+        }
+        0x19 if response => {
+            let sub = rest.first().copied().unwrap_or_default();
+            if let Some(name) = sub_function_name(sid, sub) {
+                summary.push_str(&format!(" {name}"));
+            }
+            match sub {
+                0x01 | 0x07 | 0x11 | 0x12 if rest.len() >= 5 => {
+                    let count = u16::from_be_bytes([rest[3], rest[4]]);
+                    summary.push_str(&format!(" count {count}"));
+                }
+                0x02 | 0x0a | 0x0b | 0x0c | 0x0d | 0x0e | 0x0f | 0x13
+                | 0x15 => {
+                    let records = rest.get(2..).unwrap_or_default();
+                    for record in records.chunks(4).filter(|c| c.len() == 4) {
+                        let dtc = Dtc {
+                            code: u32::from_be_bytes([
+                                0, record[0], record[1], record[2],
+                            ]),
+                            status: record[3],
+                        };
+                        lines.push(format!(
+                            "{} {:02X} {}",
+                            dtc.text(),
+                            dtc.status,
+                            dtc.status_text()
+                        ));
+                    }
+                    summary.push_str(&format!(" {} DTCs", lines.len()));
+                }
+                _ => summary.push_str(&format!(
+                    " {}",
+                    hex(rest.get(1..).unwrap_or_default())
+                )),
+            }
+        }
+        sid if has_sub_function(sid) && !rest.is_empty() => {
+            let sub = rest[0];
+            match sub_function_name(sid, sub) {
+                Some(name) => summary.push_str(&format!(" {name}")),
+                None => summary.push_str(&format!(" {sub:02X}")),
+            }
+            if sid == 0x27 {
+                summary
+                    .push_str(&format!(" level {}", (sub & 0x7f).div_ceil(2)));
+            }
+            if !response && sub & 0x80 != 0 {
+                summary.push_str(" (no response)");
+            }
+            if rest.len() > 1 {
+                summary.push_str(&format!(" {}", hex(&rest[1..])));
+            }
+        }
+        _ => {
+            if !rest.is_empty() {
+                summary.push_str(&format!(" {}", hex(rest)));
+            }
+        }
+    }
+
+    lines.insert(0, summary);
+    lines
+}
+
+/// Describe a sequence of DID records, as in read responses and write
+/// requests (which hold a single DID)
+fn describe_dids(
+    mut data: &[u8],
+    dids: &Dids,
+    summary: &mut String,
+    lines: &mut Vec<String>,
+    single: bool,
+) {
+    while data.len() >= 2 {
+        let did = u16::from_be_bytes([data[0], data[1]]);
+        data = &data[2..];
+        let def = dids.get(did);
+        let length = match def.and_then(|d| d.length) {
+            Some(length) if !single => length.min(data.len()),
+            _ => data.len(),
+        };
+        let (value, rest) = data.split_at(length);
+        data = rest;
+
+        summary.push_str(&format!(" {did:04X}"));
+        let Some(def) = def else {
+            lines.push(format!("{did:04X} {}", hex(value)));
+            continue;
+        };
+        summary.push_str(&format!(" ({})", def.name));
+        match def.format {
+            DidFormat::Hex => {
+                lines.push(format!("{} {}", def.name, hex(value)))
+            }
+            DidFormat::Ascii => {
+                let text: String = value
+                    .iter()
+                    .map(|b| match b {
+                        0x20..=0x7e => *b as char,
+                        _ => '.',
+                    })
+                    .collect();
+                lines.push(format!("{} \"{}\"", def.name, text));
+            }
+            DidFormat::Signals => {
+                for signal in def.signals.iter() {
+                    if let Some(v) = signal.value(value) {
+                        lines.push(format!(
+                            "{} {}{}",
+                            signal.name, v, signal.unit
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DIDS: &str = r#"
+# test definitions
+F190 VIN ascii 17
+0100 EngineState signals
+    Rpm : 7|16@0+ (0.25,0) "rpm"
+    Coolant : 23|8@0+ (1,-40) "C"
+"#;
+
+    fn pdu(request: bool, data: &[u8]) -> Pdu {
+        Pdu {
+            source: 0,
+            time: Some(Instant::now()),
+            id: if request { 0x7e0 } else { 0x7e8 },
+            address: None,
+            pair: 0,
+            request,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn data_identifiers() {
+        let dids = Dids::parse(DIDS).unwrap();
+        assert_eq!(dids.get(0xf190).unwrap().length, Some(17));
+        assert_eq!(dids.get(0x0100).unwrap().length, Some(3));
+
+        let lines = describe(&[0x22, 0xf1, 0x90, 0x01, 0x00], &dids);
+        assert_eq!(
+            lines,
+            vec!["ReadDataByIdentifier F190 (VIN) 0100 (EngineState)"]
+        );
+
+        let mut response = vec![0x62, 0xf1, 0x90];
+        response.extend_from_slice(b"WDB1234567890ABCD");
+        response.extend_from_slice(&[0x01, 0x00, 0x1f, 0x40, 0x5a]);
+        let lines = describe(&response, &dids);
+        assert_eq!(
+            lines[0],
+            "ReadDataByIdentifier+ F190 (VIN) 0100 (EngineState)"
+        );
+        assert_eq!(lines[1], "VIN \"WDB1234567890ABCD\"");
+        assert_eq!(lines[2], "Rpm 2000rpm");
+        assert_eq!(lines[3], "Coolant 50C");
+    }
+
+    #[test]
+    fn services() {
+        let dids = Dids::default();
+        assert_eq!(
+            describe(&[0x10, 0x03], &dids),
+            vec!["DiagnosticSessionControl extendedDiagnosticSession"]
+        );
+        assert_eq!(
+            describe(&[0x7f, 0x27, 0x35], &dids),
+            vec!["SecurityAccess NRC 35 invalidKey"]
+        );
+        let lines = describe(
+            &[
+                0x59, 0x02, 0xff, 0x01, 0x23, 0x45, 0x09, 0xc1, 0x00, 0x00,
+                0x08,
+            ],
+            &dids,
+        );
+        assert_eq!(
+            lines[0],
+            "ReadDTCInformation+ reportDTCByStatusMask 2 DTCs"
+        );
+        assert_eq!(lines[1], "P0123-45 09 testFailed confirmed");
+        assert_eq!(lines[2], "U0100-00 08 confirmed");
+        // truncated response, without sub-function
+        let lines = describe(&[0x59], &dids);
+        assert!(lines[0].starts_with("ReadDTCInformation+"));
+    }
+
+    #[test]
+    fn transactions() {
+        let mut tracker = Tracker::default();
+        tracker.process(&pdu(true, &[0x31, 0x01, 0xff, 0x00]));
+        tracker.process(&pdu(false, &[0x7f, 0x31, 0x78]));
+        assert_eq!(tracker.transactions()[0].pending, 1);
+        assert!(tracker.transactions()[0].response.is_none());
+        tracker.process(&pdu(false, &[0x71, 0x01, 0xff, 0x00]));
+        let transaction = &tracker.transactions()[0];
+        assert_eq!(transaction.response, Some(vec![0x71, 0x01, 0xff, 0x00]));
+        assert!(transaction.latency.is_some());
+    }
+}
@@ -0,0 +1,538 @@
+//! XCP on CAN measurement decoding
+//!
+//! Commands on the master identifier and responses on the slave identifier
+//! are followed to learn the DAQ list configuration (ODT entries written
+//! with `WRITE_DAQ` and the PIDs assigned when lists are started), so that
+//! DAQ packets can be decoded into measurement values defined in an A2L
+//! file.
+
+pub mod a2l;
+
+use crate::Packet;
+use a2l::{A2l, DataType};
+use std::collections::BTreeMap;
+
+/// Response packet identifiers (slave to master)
+const RES: u8 = 0xff;
+const ERR: u8 = 0xfe;
+const EV: u8 = 0xfd;
+const SERV: u8 = 0xfc;
+
+/// DAQ list mode flag for timestamped DTOs
+const TIMESTAMP: u8 = 0x10;
+
+pub fn command_name(pid: u8) -> Option<&'static str> {
+    let name = match pid {
+        0xff => "CONNECT",
+        0xfe => "DISCONNECT",
+        0xfd => "GET_STATUS",
+        0xfc => "SYNCH",
+        0xfb => "GET_COMM_MODE_INFO",
+        0xfa => "GET_ID",
+        0xf9 => "SET_REQUEST",
+        0xf8 => "GET_SEED",
+        0xf7 => "UNLOCK",
+        0xf6 => "SET_MTA",
+        0xf5 => "UPLOAD",
+        0xf4 => "SHORT_UPLOAD",
+        0xf3 => "BUILD_CHECKSUM",
+        0xf2 => "TRANSPORT_LAYER_CMD",
+        0xf1 => "USER_CMD",
+        0xf0 => "DOWNLOAD",
+        0xef => "DOWNLOAD_NEXT",
+        0xee => "DOWNLOAD_MAX",
+        0xed => "SHORT_DOWNLOAD",
+        0xec => "MODIFY_BITS",
+        0xeb => "SET_CAL_PAGE",
+        0xea => "GET_CAL_PAGE",
+        0xe9 => "GET_PAG_PROCESSOR_INFO",
+        0xe8 => "GET_SEGMENT_INFO",
+        0xe7 => "GET_PAGE_INFO",
+        0xe6 => "SET_SEGMENT_MODE",
+        0xe5 => "GET_SEGMENT_MODE",
+        0xe4 => "COPY_CAL_PAGE",
+        0xe3 => "CLEAR_DAQ_LIST",
+        0xe2 => "SET_DAQ_PTR",
+        0xe1 => "WRITE_DAQ",
+        0xe0 => "SET_DAQ_LIST_MODE",
+        0xdf => "GET_DAQ_LIST_MODE",
+        0xde => "START_STOP_DAQ_LIST",
+        0xdd => "START_STOP_SYNCH",
+        0xdc => "GET_DAQ_CLOCK",
+        0xdb => "READ_DAQ",
+        0xda => "GET_DAQ_PROCESSOR_INFO",
+        0xd9 => "GET_DAQ_RESOLUTION_INFO",
+        0xd8 => "GET_DAQ_LIST_INFO",
+        0xd7 => "GET_DAQ_EVENT_INFO",
+        0xd6 => "FREE_DAQ",
+        0xd5 => "ALLOC_DAQ",
+        0xd4 => "ALLOC_ODT",
+        0xd3 => "ALLOC_ODT_ENTRY",
+        0xd2 => "PROGRAM_START",
+        0xd1 => "PROGRAM_CLEAR",
+        0xd0 => "PROGRAM",
+        0xcf => "PROGRAM_RESET",
+        0xc7 => "WRITE_DAQ_MULTIPLE",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn error_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "ERR_CMD_SYNCH",
+        0x10 => "ERR_CMD_BUSY",
+        0x11 => "ERR_DAQ_ACTIVE",
+        0x12 => "ERR_PGM_ACTIVE",
+        0x20 => "ERR_CMD_UNKNOWN",
+        0x21 => "ERR_CMD_SYNTAX",
+        0x22 => "ERR_OUT_OF_RANGE",
+        0x23 => "ERR_WRITE_PROTECTED",
+        0x24 => "ERR_ACCESS_DENIED",
+        0x25 => "ERR_ACCESS_LOCKED",
+        0x26 => "ERR_PAGE_NOT_VALID",
+        0x27 => "ERR_MODE_NOT_VALID",
+        0x28 => "ERR_SEGMENT_NOT_VALID",
+        0x29 => "ERR_SEQUENCE",
+        0x2a => "ERR_DAQ_CONFIG",
+        0x30 => "ERR_MEMORY_OVERFLOW",
+        0x31 => "ERR_GENERIC",
+        0x32 => "ERR_VERIFY",
+        _ => "unknown",
+    }
+}
+
+/// Master and slave identifiers of an XCP on CAN connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ids {
+    pub master: u32,
+    pub slave: u32,
+}
+
+impl Ids {
+    /// Parse a `<master>:<slave>` specifier with hexadecimal IDs
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.split(':');
+        let mut id = || {
+            let id = parts.next().unwrap_or_default();
+            u32::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid XCP ID in {text}"))
+        };
+        Ok(Self {
+            master: id()?,
+            slave: id()?,
+        })
+    }
+}
+
+/// ODT entry: a memory location sampled into DAQ packets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub address: u32,
+    pub extension: u8,
+    pub size: u8,
+    /// Bit of a sampled bit (0xff for whole values)
+    pub bit_offset: u8,
+}
+
+impl Default for Entry {
+    /// Entry allocated but not yet written, sampling nothing
+    fn default() -> Self {
+        Self {
+            address: 0,
+            extension: 0,
+            size: 0,
+            bit_offset: 0xff,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct DaqList {
+    pub odts: Vec<Vec<Entry>>,
+    pub mode: u8,
+    pub event: u16,
+    /// PID of the first ODT, assigned when the list is started
+    pub first_pid: Option<u8>,
+    /// Selected for the next START_STOP_SYNCH
+    pub selected: bool,
+    pub running: bool,
+}
+
+/// Decoded measurement value
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+    pub name: String,
+    /// Physical value with unit
+    pub text: String,
+}
+
+/// Follows an XCP on CAN session, decoding DAQ packets
+#[derive(Default, Clone)]
+pub struct Decoder {
+    ids: Option<Ids>,
+    a2l: A2l,
+    /// Slave byte order, from the CONNECT response
+    big_endian: bool,
+    /// Size of DTO timestamps in bytes
+    timestamp_size: usize,
+    daq_lists: Vec<DaqList>,
+    /// DAQ list, ODT and entry written next
+    pointer: (usize, usize, usize),
+    /// Last command, to interpret its response
+    command: Vec<u8>,
+    /// Last response or event text
+    status: String,
+    values: BTreeMap<String, Value>,
+}
+
+impl Decoder {
+    /// Decode XCP on the given identifiers, falling back to those of the
+    /// A2L file
+    pub fn new(ids: Option<Ids>, a2l: A2l) -> Self {
+        let ids = ids.or(match (a2l.master, a2l.slave) {
+            (Some(master), Some(slave)) => Some(Ids { master, slave }),
+            _ => None,
+        });
+        Self {
+            ids,
+            a2l,
+            ..Default::default()
+        }
+    }
+
+    pub fn ids(&self) -> Option<Ids> {
+        self.ids
+    }
+
+    pub fn daq_lists(&self) -> &Vec<DaqList> {
+        &self.daq_lists
+    }
+
+    /// Latest measurement values, by name
+    pub fn values(&self) -> &BTreeMap<String, Value> {
+        &self.values
+    }
+
+    /// Description of the last command
+    pub fn command_text(&self) -> String {
+        let Some(pid) = self.command.first() else {
+            return String::new();
+        };
+        let name = command_name(*pid).unwrap_or("?");
+        let args = self.command[1..]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{name} {args}")
+    }
+
+    /// Description of the last response, error or event
+    pub fn status_text(&self) -> &str {
+        &self.status
+    }
+
+    fn word(&self, bytes: &[u8]) -> usize {
+        match bytes {
+            [a, b, ..] if self.big_endian => {
+                u16::from_be_bytes([*a, *b]) as usize
+            }
+            [a, b, ..] => u16::from_le_bytes([*a, *b]) as usize,
+            _ => 0,
+        }
+    }
+
+    fn long(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    pub fn process(&mut self, packet: &Packet) {
+        let Some(ids) = self.ids else {
+            return;
+        };
+        if packet.bytes.is_empty() {
+            return;
+        }
+        if packet.id == ids.master {
+            self.process_command(&packet.bytes);
+        } else if packet.id == ids.slave {
+            self.process_response(&packet.bytes);
+        }
+    }
+
+    fn process_command(&mut self, bytes: &[u8]) {
+        self.command = bytes.to_vec();
+        match bytes {
+            // FREE_DAQ
+            [0xd6, ..] => self.daq_lists.clear(),
+            // ALLOC_DAQ
+            [0xd5, _, ..] if bytes.len() >= 4 => {
+                let count = self.word(&bytes[2..]);
+                self.daq_lists = vec![DaqList::default(); count];
+            }
+            // ALLOC_ODT
+            [0xd4, _, _, _, count, ..] => {
+                let daq = self.word(&bytes[2..]);
+                if let Some(list) = self.daq_lists.get_mut(daq) {
+                    list.odts = vec![vec![]; *count as usize];
+                }
+            }
+            // ALLOC_ODT_ENTRY
+            [0xd3, _, _, _, odt, count, ..] => {
+                let daq = self.word(&bytes[2..]);
+                let odt = self
+                    .daq_lists
+                    .get_mut(daq)
+                    .and_then(|l| l.odts.get_mut(*odt as usize));
+                if let Some(odt) = odt {
+                    *odt = vec![Entry::default(); *count as usize];
+                }
+            }
+            // SET_DAQ_PTR
+            [0xe2, _, _, _, odt, entry, ..] => {
+                let daq = self.word(&bytes[2..]);
+                self.pointer = (daq, *odt as usize, *entry as usize);
+            }
+            // WRITE_DAQ
+            [0xe1, bit_offset, size, extension, _, _, _, _, ..] => {
+                let entry = Entry {
+                    bit_offset: *bit_offset,
+                    size: *size,
+                    extension: *extension,
+                    address: self.long(&bytes[4..]),
+                };
+                self.write_entry(entry);
+            }
+            // WRITE_DAQ_MULTIPLE
+            [0xc7, count, ..] => {
+                for element in bytes[2..].chunks(8).take(*count as usize) {
+                    if let [bit_offset, size, _, _, _, _, extension, _] =
+                        element
+                    {
+                        let entry = Entry {
+                            bit_offset: *bit_offset,
+                            size: *size,
+                            extension: *extension,
+                            address: self.long(&element[2..]),
+                        };
+                        self.write_entry(entry);
+                    }
+                }
+            }
+            // SET_DAQ_LIST_MODE
+            [0xe0, mode, _, _, _, _, ..] => {
+                let daq = self.word(&bytes[2..]);
+                let event = self.word(&bytes[4..]) as u16;
+                if let Some(list) = self.daq_lists.get_mut(daq) {
+                    list.mode = *mode;
+                    list.event = event;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_entry(&mut self, entry: Entry) {
+        let (daq, odt, index) = self.pointer;
+        let slot = self
+            .daq_lists
+            .get_mut(daq)
+            .and_then(|l| l.odts.get_mut(odt))
+            .and_then(|o| o.get_mut(index));
+        if let Some(slot) = slot {
+            *slot = entry;
+        }
+        self.pointer.2 += 1;
+    }
+
+    fn process_response(&mut self, bytes: &[u8]) {
+        match bytes[0] {
+            RES => {
+                self.process_positive(bytes);
+                self.status = match self.command.first() {
+                    Some(pid) => {
+                        format!("{} ok", command_name(*pid).unwrap_or("?"))
+                    }
+                    None => "ok".to_string(),
+                };
+            }
+            ERR => {
+                let code = bytes.get(1).copied().unwrap_or_default();
+                self.status = format!("{code:02X} {}", error_name(code));
+            }
+            EV => {
+                let code = bytes.get(1).copied().unwrap_or_default();
+                self.status = format!("Event {code:02X}");
+            }
+            SERV => {
+                let code = bytes.get(1).copied().unwrap_or_default();
+                self.status = format!("Service request {code:02X}");
+            }
+            pid => self.process_daq(pid, &bytes[1..]),
+        }
+    }
+
+    fn process_positive(&mut self, bytes: &[u8]) {
+        match self.command.as_slice() {
+            // CONNECT
+            [0xff, ..] => {
+                self.big_endian = bytes.get(2).is_some_and(|m| m & 1 != 0);
+                self.daq_lists.clear();
+            }
+            // GET_DAQ_RESOLUTION_INFO
+            [0xd9, ..] => {
+                let mode = bytes.get(5).copied().unwrap_or_default();
+                self.timestamp_size = match mode & 7 {
+                    size @ (1 | 2 | 4) => size as usize,
+                    _ => 0,
+                };
+            }
+            // START_STOP_DAQ_LIST
+            [0xde, mode, _, _, ..] => {
+                let daq = self.word(&self.command[2..]);
+                let first_pid = bytes.get(1).copied();
+                if let Some(list) = self.daq_lists.get_mut(daq) {
+                    list.first_pid = first_pid;
+                    match mode {
+                        0 => list.running = false,
+                        1 => list.running = true,
+                        _ => list.selected = true,
+                    }
+                }
+            }
+            // START_STOP_SYNCH
+            [0xdd, mode, ..] => {
+                let mode = *mode;
+                for list in self.daq_lists.iter_mut() {
+                    match mode {
+                        0 => list.running = false,
+                        1 if list.selected => list.running = true,
+                        2 if list.selected => list.running = false,
+                        _ => {}
+                    }
+                    list.selected = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn process_daq(&mut self, pid: u8, mut data: &[u8]) {
+        let Some((list, odt)) = self.daq_lists.iter().find_map(|l| {
+            let first = l.first_pid?;
+            let odt = pid.checked_sub(first)? as usize;
+            (odt < l.odts.len()).then_some((l, odt))
+        }) else {
+            return;
+        };
+        if odt == 0 && list.mode & TIMESTAMP != 0 {
+            data = data.get(self.timestamp_size..).unwrap_or_default();
+        }
+
+        let mut values = vec![];
+        for entry in list.odts[odt].iter() {
+            let size = entry.size as usize;
+            let Some(bytes) = data.get(..size) else {
+                break;
+            };
+            data = &data[size..];
+            if size == 0 {
+                continue;
+            }
+            let Some(measurement) =
+                self.a2l.measurement(entry.extension, entry.address)
+            else {
+                continue;
+            };
+
+            let raw = if entry.bit_offset != 0xff {
+                Some(((bytes[0] >> (entry.bit_offset & 7)) & 1) as f64)
+            } else {
+                measurement.data_type.value(bytes, measurement.big_endian)
+            };
+            let Some(raw) = raw else {
+                continue;
+            };
+            let value = raw * measurement.factor + measurement.offset;
+            let text = match measurement.data_type {
+                DataType::Float32 | DataType::Float64 => {
+                    format!("{:.3}{}", value, measurement.unit)
+                }
+                _ if measurement.factor.fract() != 0.0
+                    || measurement.offset.fract() != 0.0 =>
+                {
+                    format!("{:.3}{}", value, measurement.unit)
+                }
+                _ => format!("{}{}", value, measurement.unit),
+            };
+            values.push(Value {
+                name: measurement.name.clone(),
+                text,
+            });
+        }
+        for value in values {
+            self.values.insert(value.name.clone(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const A2L: &str = r#"
+/begin MEASUREMENT EngineSpeed "" UWORD NO_COMPU_METHOD 0 0 0 8000
+  ECU_ADDRESS 0x1000
+/end MEASUREMENT
+/begin MEASUREMENT Temp "" SBYTE NO_COMPU_METHOD 0 0 -128 127
+  ECU_ADDRESS 0x1002
+/end MEASUREMENT
+"#;
+
+    #[test]
+    fn daq_lists() {
+        let ids = Ids::parse("7f0:7f1").unwrap();
+        let a2l = A2l::parse(A2L).unwrap();
+        let mut xcp = Decoder::new(Some(ids), a2l);
+
+        let session: [(u32, &[u8]); 16] = [
+            (0x7f0, &[0xff, 0x00]),
+            (0x7f1, &[0xff, 0x05, 0x00, 0x08, 0x08, 0x00, 0x01, 0x01]),
+            (0x7f0, &[0xd6]),
+            (0x7f1, &[0xff]),
+            (0x7f0, &[0xd5, 0x00, 0x01, 0x00]),
+            (0x7f0, &[0xd4, 0x00, 0x00, 0x00, 0x01]),
+            (0x7f0, &[0xd3, 0x00, 0x00, 0x00, 0x00, 0x02]),
+            (0x7f0, &[0xe2, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            (0x7f0, &[0xe1, 0xff, 0x02, 0x00, 0x00, 0x10, 0x00, 0x00]),
+            (0x7f1, &[0xff]),
+            (0x7f0, &[0xe1, 0xff, 0x01, 0x00, 0x02, 0x10, 0x00, 0x00]),
+            (0x7f1, &[0xff]),
+            (0x7f0, &[0xe0, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00]),
+            (0x7f0, &[0xde, 0x02, 0x00, 0x00]),
+            (0x7f1, &[0xff, 0x20]),
+            (0x7f0, &[0xdd, 0x01]),
+        ];
+        for (id, bytes) in session {
+            xcp.process(&Packet::new(id, bytes));
+        }
+        assert_eq!(xcp.daq_lists()[0].first_pid, Some(0x20));
+        assert_eq!(xcp.daq_lists()[0].odts[0][1].address, 0x1002);
+
+        xcp.process(&Packet::new(0x7f1, &[0xff]));
+        assert!(xcp.daq_lists()[0].running);
+        xcp.process(&Packet::new(0x7f1, &[0x20, 0xb8, 0x0b, 0xf6]));
+        assert_eq!(xcp.values()["EngineSpeed"].text, "3000");
+        assert_eq!(xcp.values()["Temp"].text, "-10");
+
+        xcp.process(&Packet::new(0x7f0, &[0xd6]));
+        xcp.process(&Packet::new(0x7f1, &[0xfe, 0x10]));
+        assert_eq!(xcp.status_text(), "10 ERR_CMD_BUSY");
+    }
+}
@@ -0,0 +1,386 @@
+//! ASAM MCD-2 MC (A2L) loader for measurement definitions
+
+use crate::invalid;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataType {
+    #[default]
+    UByte,
+    SByte,
+    UWord,
+    SWord,
+    ULong,
+    SLong,
+    UInt64,
+    Int64,
+    Float32,
+    Float64,
+}
+
+impl DataType {
+    fn parse(text: &str) -> Option<Self> {
+        let data_type = match text {
+            "UBYTE" => Self::UByte,
+            "SBYTE" => Self::SByte,
+            "UWORD" => Self::UWord,
+            "SWORD" => Self::SWord,
+            "ULONG" => Self::ULong,
+            "SLONG" => Self::SLong,
+            "A_UINT64" => Self::UInt64,
+            "A_INT64" => Self::Int64,
+            "FLOAT32_IEEE" => Self::Float32,
+            "FLOAT64_IEEE" => Self::Float64,
+            _ => return None,
+        };
+        Some(data_type)
+    }
+
+    /// Size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::UByte | Self::SByte => 1,
+            Self::UWord | Self::SWord => 2,
+            Self::ULong | Self::SLong | Self::Float32 => 4,
+            Self::UInt64 | Self::Int64 | Self::Float64 => 8,
+        }
+    }
+
+    /// Raw value of the data, if it is long enough
+    pub fn value(&self, data: &[u8], big_endian: bool) -> Option<f64> {
+        let bytes = data.get(..self.size())?;
+        let mut raw = [0u8; 8];
+        if big_endian {
+            raw[8 - bytes.len()..].copy_from_slice(bytes);
+        } else {
+            raw[..bytes.len()].copy_from_slice(bytes);
+        }
+        let raw = if big_endian {
+            u64::from_be_bytes(raw)
+        } else {
+            u64::from_le_bytes(raw)
+        };
+        let value = match self {
+            Self::SByte => raw as i8 as f64,
+            Self::SWord => raw as i16 as f64,
+            Self::SLong => raw as i32 as f64,
+            Self::Int64 => raw as i64 as f64,
+            Self::Float32 => f32::from_bits(raw as u32) as f64,
+            Self::Float64 => f64::from_bits(raw),
+            _ => raw as f64,
+        };
+        Some(value)
+    }
+}
+
+/// Measurement (readable ECU variable) definition
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub name: String,
+    pub data_type: DataType,
+    pub address: u32,
+    pub extension: u8,
+    pub big_endian: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub unit: String,
+}
+
+/// Measurements and XCP on CAN settings of an A2L file
+#[derive(Default, Clone, Debug)]
+pub struct A2l {
+    pub measurements: Vec<Measurement>,
+    /// Identifier of master (command) frames
+    pub master: Option<u32>,
+    /// Identifier of slave (response and DAQ) frames
+    pub slave: Option<u32>,
+    /// Measurement index by (address extension, address)
+    addresses: HashMap<(u8, u32), usize>,
+}
+
+/// Split A2L text into tokens, keeping quoted strings whole and dropping
+/// comments
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => token.extend(chars.next()),
+                        '"' if chars.peek() == Some(&'"') => {
+                            token.push(chars.next().unwrap_or_default());
+                        }
+                        '"' => break,
+                        _ => token.push(c),
+                    }
+                }
+                tokens.push(token);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '"' {
+                        break;
+                    }
+                    token.push(*c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+fn number(text: &str) -> io::Result<f64> {
+    let value =
+        match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16).map(|v| v as f64).ok(),
+            None => text.parse().ok(),
+        };
+    value.ok_or_else(|| invalid(format!("Invalid number {text}")))
+}
+
+/// Block contents between `/begin <keyword>` and its `/end`, with nested
+/// blocks left in place
+fn blocks<'a>(tokens: &'a [String], keyword: &str) -> Vec<&'a [String]> {
+    let mut blocks = vec![];
+    let mut index = 0;
+    while index + 1 < tokens.len() {
+        if tokens[index] != "/begin" || tokens[index + 1] != keyword {
+            index += 1;
+            continue;
+        }
+        let start = index + 2;
+        let mut depth = 0;
+        let mut end = start;
+        while end < tokens.len() {
+            match tokens[end].as_str() {
+                "/begin" => depth += 1,
+                "/end" if depth == 0 => break,
+                "/end" => depth -= 1,
+                _ => {}
+            }
+            end += 1;
+        }
+        blocks.push(&tokens[start..end]);
+        index = end;
+    }
+    blocks
+}
+
+/// Value following a keyword at the top level of a block
+fn keyword<'a>(block: &'a [String], name: &str) -> Option<&'a [String]> {
+    let mut depth = 0;
+    for (index, token) in block.iter().enumerate() {
+        match token.as_str() {
+            "/begin" => depth += 1,
+            "/end" => depth -= 1,
+            token if depth == 0 && token == name => {
+                return Some(&block[index + 1..]);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Linear conversion (factor, offset) and unit of a compu method
+fn conversion(block: &[String]) -> io::Result<(f64, f64, String)> {
+    let unit = block.get(4).cloned().unwrap_or_default();
+    let kind = block.get(2).map(|s| s.as_str()).unwrap_or_default();
+    let (factor, offset) = match kind {
+        "LINEAR" => match keyword(block, "COEFFS_LINEAR") {
+            Some([a, b, ..]) => (number(a)?, number(b)?),
+            _ => (1.0, 0.0),
+        },
+        // f(x) = (ax^2 + bx + c) / (dx^2 + ex + f) converts physical to
+        // internal values; only its linear form can be inverted here
+        "RAT_FUNC" => match keyword(block, "COEFFS") {
+            Some([a, b, c, d, e, f, ..]) => {
+                let [a, b, c, d, e, f] = [a, b, c, d, e, f].map(|n| number(n));
+                let (a, b, c, d, e, f) = (a?, b?, c?, d?, e?, f?);
+                if a == 0.0 && d == 0.0 && e == 0.0 && b != 0.0 {
+                    (f / b, -c / b)
+                } else {
+                    (1.0, 0.0)
+                }
+            }
+            _ => (1.0, 0.0),
+        },
+        _ => (1.0, 0.0),
+    };
+    Ok((factor, offset, unit))
+}
+
+impl A2l {
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let tokens = tokens(text);
+
+        let mut conversions = HashMap::new();
+        for block in blocks(&tokens, "COMPU_METHOD") {
+            let name = block.first().cloned().unwrap_or_default();
+            conversions.insert(name, conversion(block)?);
+        }
+
+        let big_endian = |block: &[String]| {
+            keyword(block, "BYTE_ORDER")
+                .and_then(|v| v.first())
+                .map(|order| order == "MSB_FIRST")
+        };
+        let default_big_endian = blocks(&tokens, "MOD_COMMON")
+            .first()
+            .and_then(|b| big_endian(b))
+            .unwrap_or_default();
+
+        let mut measurements = vec![];
+        for block in blocks(&tokens, "MEASUREMENT") {
+            let [name, _, data_type, method, ..] = block else {
+                return Err(invalid("Incomplete MEASUREMENT".into()));
+            };
+            let data_type = DataType::parse(data_type).ok_or_else(|| {
+                invalid(format!("Unknown data type {data_type} of {name}"))
+            })?;
+            let Some([address, ..]) = keyword(block, "ECU_ADDRESS") else {
+                continue;
+            };
+            let extension = match keyword(block, "ECU_ADDRESS_EXTENSION") {
+                Some([extension, ..]) => number(extension)? as u8,
+                _ => 0,
+            };
+            let (factor, offset, unit) = conversions
+                .get(method)
+                .cloned()
+                .unwrap_or((1.0, 0.0, "".into()));
+            measurements.push(Measurement {
+                name: name.clone(),
+                data_type,
+                address: number(address)? as u32,
+                extension,
+                big_endian: big_endian(block).unwrap_or(default_big_endian),
+                factor,
+                offset,
+                unit,
+            });
+        }
+
+        let can = blocks(&tokens, "XCP_ON_CAN");
+        let id = |name| -> io::Result<Option<u32>> {
+            let value = can.iter().find_map(|b| keyword(b, name));
+            match value {
+                Some([id, ..]) => Ok(Some(number(id)? as u32 & 0x1fff_ffff)),
+                _ => Ok(None),
+            }
+        };
+        let master = id("CAN_ID_MASTER")?;
+        let slave = id("CAN_ID_SLAVE")?;
+
+        let addresses = measurements
+            .iter()
+            .enumerate()
+            .map(|(index, m)| ((m.extension, m.address), index))
+            .collect();
+        Ok(Self {
+            measurements,
+            master,
+            slave,
+            addresses,
+        })
+    }
+
+    /// Measurement at an ECU address
+    pub fn measurement(
+        &self,
+        extension: u8,
+        address: u32,
+    ) -> Option<&Measurement> {
+        self.addresses
+            .get(&(extension, address))
+            .map(|index| &self.measurements[*index])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const A2L: &str = r#"
+ASAP2_VERSION 1 71
+/begin PROJECT demo ""
+  /begin MODULE ecu "ECU /* not a comment */"
+    /begin MOD_COMMON "" BYTE_ORDER MSB_LAST /end MOD_COMMON
+    /begin IF_DATA XCP
+      /begin XCP_ON_CAN 0x0100
+        CAN_ID_MASTER 0x7F0 // commands
+        CAN_ID_SLAVE 0x7F1
+      /end XCP_ON_CAN
+    /end IF_DATA
+    /begin COMPU_METHOD rpm "" RAT_FUNC "%6.1" "rpm"
+      COEFFS 0 4 0 0 0 1
+    /end COMPU_METHOD
+    /begin COMPU_METHOD temp "" LINEAR "%5.1" "degC"
+      COEFFS_LINEAR 0.5 -40
+    /end COMPU_METHOD
+    /* measurements */
+    /begin MEASUREMENT EngineSpeed "engine speed" UWORD rpm 0 0 0 8000
+      ECU_ADDRESS 0x20001000
+    /end MEASUREMENT
+    /begin MEASUREMENT CoolantTemp "" UBYTE temp 0 0 -40 87.5
+      ECU_ADDRESS 0x20001002
+      /begin IF_DATA XCP /end IF_DATA
+    /end MEASUREMENT
+    /begin MEASUREMENT Torque "" SWORD NO_COMPU_METHOD 0 0 -500 500
+      ECU_ADDRESS 0x20001004
+      BYTE_ORDER MSB_FIRST
+    /end MEASUREMENT
+  /end MODULE
+/end PROJECT
+"#;
+
+    #[test]
+    fn measurements() {
+        let a2l = A2l::parse(A2L).unwrap();
+        assert_eq!(a2l.master, Some(0x7f0));
+        assert_eq!(a2l.slave, Some(0x7f1));
+        assert_eq!(a2l.measurements.len(), 3);
+
+        let speed = a2l.measurement(0, 0x20001000).unwrap();
+        assert_eq!(speed.name, "EngineSpeed");
+        assert_eq!(speed.factor, 0.25);
+        assert_eq!(speed.unit, "rpm");
+
+        let temp = a2l.measurement(0, 0x20001002).unwrap();
+        assert_eq!((temp.factor, temp.offset), (0.5, -40.0));
+
+        let torque = a2l.measurement(0, 0x20001004).unwrap();
+        assert!(torque.big_endian);
+        let value = torque.data_type.value(&[0xff, 0x38], true);
+        assert_eq!(value, Some(-200.0));
+    }
+}